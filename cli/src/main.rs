@@ -0,0 +1,1103 @@
+use std::{fmt, fs::File, io::BufWriter};
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use cli_table::{print_stdout, WithTitle};
+use const_format::formatcp;
+use env_logger::Env;
+use log::{error, info};
+use senso::{
+    connector::Connector,
+    db::{Channel, DB},
+    degree_days,
+    emissions::{self, EmissionFactors, GasBoiler},
+    export::{self, ExportOptions, ExportTable, Format},
+    external::{self, ImportFormat, Source},
+    heating_curve,
+    legionella::{self, Requirement},
+    quality,
+    request::system::VentilationMode,
+    response::system::{HolidayMode, QuickMode, QuickModeConfiguration, Ventilation, Zone},
+    retention::{self, RetentionPolicy},
+    spf::{self, Period},
+    tariff::{self, TariffConfig},
+    timeprogram::{self, Schedules},
+    units::{Unit, UnitSet},
+    urls::UrlBase,
+};
+
+pub const VERSION_STR: &str =
+    formatcp!("v{}, senso v{}", env!("CARGO_PKG_VERSION"), senso::VERSION);
+
+/// Reports over the data collected by sensor and usage.
+#[derive(Parser)]
+#[command(version = VERSION_STR, about, long_about = None)]
+struct Args {
+    /// Path of the Sqlite file.
+    #[arg(short, long, default_value = "./data.db")]
+    db_file: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Latest stored value of every channel.
+    Latest,
+    /// Devices of the facility with serial and firmware recorded by usage.
+    Devices {
+        /// Print the firmware changes instead.
+        #[arg(long)]
+        firmware: bool,
+    },
+    /// Open faults and maintenance messages recorded by sensor.
+    Faults {
+        /// Include cleared faults.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Seasonal performance factor over a period.
+    /// Yield and power are summed before dividing.
+    Report {
+        #[command(subcommand)]
+        period: PeriodArg,
+    },
+    /// Heating energy per heating degree day over a period.
+    DegreeDays {
+        /// Base temperature in °C for the heating degree days.
+        #[arg(long, default_value_t = degree_days::DEFAULT_BASE_TEMPERATURE)]
+        base: f64,
+
+        /// Print every day of the period.
+        #[arg(long)]
+        daily: bool,
+
+        /// Compare with the same period one year earlier, adjusted for the weather.
+        #[arg(long)]
+        yoy: bool,
+
+        #[command(subcommand)]
+        period: PeriodArg,
+    },
+    /// Fit the heating curve from flow and outdoor temperature over a period.
+    HeatingCurve {
+        /// Samples with a higher outdoor temperature in °C are ignored.
+        #[arg(long, default_value_t = heating_curve::DEFAULT_HEATING_LIMIT)]
+        heating_limit: f64,
+
+        /// Samples that deviate more K from the fitted curve are printed.
+        #[arg(long, default_value_t = heating_curve::DEFAULT_MAX_DEVIATION)]
+        max_deviation: f64,
+
+        /// Estimate the yp for central heating with a curve lowered by this K.
+        #[arg(long)]
+        lower: Option<f64>,
+
+        #[command(subcommand)]
+        period: PeriodArg,
+    },
+    /// Calculate, store and print the electricity costs over a period.
+    Cost {
+        /// Path to a json file with electricity tariffs.
+        #[arg(long)]
+        tariff_file: String,
+
+        /// Print every day of the period.
+        #[arg(long)]
+        daily: bool,
+
+        #[command(subcommand)]
+        period: PeriodArg,
+    },
+    /// Estimate the CO2 emissions in kg and the avoided emissions compared to a gas boiler over a period.
+    Emissions {
+        /// Constant grid emission factor in g CO2 per kWh.
+        #[arg(
+            long,
+            required_unless_present = "factor_file",
+            conflicts_with = "factor_file"
+        )]
+        factor: Option<f64>,
+
+        /// Path to a csv file with hourly or daily grid emission factors as `time,g_per_kwh`.
+        #[arg(long)]
+        factor_file: Option<String>,
+
+        /// Emission factor of natural gas in g CO2 per kWh.
+        #[arg(long, default_value_t = emissions::DEFAULT_GAS_FACTOR)]
+        gas_factor: f64,
+
+        /// Efficiency of the gas boiler.
+        #[arg(long, default_value_t = emissions::DEFAULT_GAS_BOILER_EFFICIENCY)]
+        gas_efficiency: f64,
+
+        /// Print every day of the period.
+        #[arg(long)]
+        daily: bool,
+
+        #[command(subcommand)]
+        period: PeriodArg,
+    },
+    /// Compliance history of the legionella protection of the hot water tank over a period.
+    Legionella {
+        /// Temperature in °C the hot water tank has to reach.
+        #[arg(long, default_value_t = legionella::DEFAULT_DISINFECTION_TEMPERATURE)]
+        temperature: f64,
+
+        /// Minutes the temperature has to be held.
+        #[arg(long, default_value_t = legionella::DEFAULT_HOLD_MINUTES)]
+        hold_minutes: i64,
+
+        /// Days in which at least one disinfection cycle is needed.
+        #[arg(long, default_value_t = legionella::DEFAULT_INTERVAL_DAYS)]
+        interval_days: i64,
+
+        /// Print every disinfection cycle of the period.
+        #[arg(long)]
+        cycles: bool,
+
+        #[command(subcommand)]
+        period: PeriodArg,
+    },
+    /// Find gaps, NULL runs, missing usage days, implausible values and duplicated timestamps over a period.
+    Quality {
+        /// Minutes between two sensor samples above which samples are missing.
+        #[arg(long, default_value_t = quality::DEFAULT_MAX_GAP_MINUTES)]
+        max_gap_minutes: i64,
+
+        /// Print every finding.
+        #[arg(long)]
+        details: bool,
+
+        /// Queue the days without usage for backfill by `usage --backfill`.
+        #[arg(long)]
+        backfill: bool,
+
+        #[command(subcommand)]
+        period: PeriodArg,
+    },
+    /// Roll up the sensor samples into hourly and daily aggregates and delete expired samples.
+    Retain {
+        /// Days raw sensor samples are kept.
+        #[arg(long, default_value_t = retention::DEFAULT_RAW_DAYS)]
+        raw_days: i64,
+
+        /// Days hourly aggregates are kept. Kept forever if not set.
+        #[arg(long)]
+        hourly_days: Option<i64>,
+    },
+    /// Write the rows of a table over a period as csv, newline delimited json or parquet.
+    Export {
+        /// temperature, usage, temperature_hourly or temperature_daily
+        #[arg(long)]
+        table: ExportTable,
+
+        /// Comma separated columns, all columns of the table if not set.
+        /// Sensor channels for the hourly and daily tables, exported as min, max, mean and count.
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<Channel>,
+
+        /// csv, ndjson or parquet
+        #[arg(long, default_value = "csv")]
+        format: Format,
+
+        /// IANA timezone of the timestamps, e.g. Europe/Berlin.
+        #[arg(long, default_value = "UTC")]
+        timezone: Tz,
+
+        /// Units of the exported values, e.g. kWh,°F,kPa. Stored units for other dimensions.
+        #[arg(long = "unit", value_delimiter = ',')]
+        units: Vec<Unit>,
+
+        /// Path of the output file. Writes to stdout if not set.
+        #[arg(short, long)]
+        output: Option<String>,
+
+        #[command(subcommand)]
+        period: PeriodArg,
+    },
+    /// Import energy of an external electricity meter or PV system from csv or a SunSpec dump.
+    Import {
+        /// meter or pv
+        #[arg(long)]
+        source: Source,
+
+        /// csv or sunspec
+        #[arg(long, default_value = "csv")]
+        format: ImportFormat,
+
+        /// Values of the csv are meter readings instead of the energy per interval.
+        #[arg(long)]
+        cumulative: bool,
+
+        /// Unit of the csv values, Wh or kWh.
+        #[arg(long, default_value = "Wh")]
+        unit: Unit,
+
+        /// Path of the file.
+        file: String,
+    },
+    /// Compare the daily usage with the imported meter and PV energy over a period.
+    CrossCheck {
+        #[command(subcommand)]
+        period: PeriodArg,
+    },
+    /// Change a zone of the facility.
+    Zone {
+        #[command(subcommand)]
+        action: ZoneAction,
+    },
+    /// Start or cancel a temporary setpoint of a zone.
+    Quickveto {
+        #[command(subcommand)]
+        action: QuickVetoAction,
+    },
+    /// Set or clear the holiday period of the facility.
+    Holiday {
+        #[command(subcommand)]
+        action: HolidayAction,
+    },
+    /// Show or change the fan of a ventilation.
+    Ventilation {
+        #[command(subcommand)]
+        action: VentilationAction,
+    },
+    /// Start, cancel or show the quick mode of the facility.
+    Quickmode {
+        #[command(subcommand)]
+        action: QuickModeAction,
+    },
+    /// Export, compare and apply the time programs of the facility as yaml.
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleAction,
+    },
+}
+
+// login to the vaillant api
+#[derive(ClapArgs)]
+struct Login {
+    /// Specify the serial of the facility.
+    #[arg(long)]
+    serial: String,
+
+    /// User name for login.
+    #[arg(long)]
+    user: String,
+
+    /// Password for login.
+    #[arg(long)]
+    pwd: String,
+
+    /// Path to token file.
+    /// Creates a new file if not found.
+    #[arg(long, default_value = "./token")]
+    token_file: String,
+}
+
+#[derive(Subcommand)]
+enum ZoneAction {
+    /// Set the day temperature of the zone's heating.
+    Set {
+        /// Id of the zone, e.g. Control_ZO1.
+        #[arg(long)]
+        zone: String,
+
+        /// Temperature in °C.
+        #[arg(long)]
+        temperature: f64,
+
+        #[command(flatten)]
+        login: Login,
+    },
+}
+
+#[derive(Subcommand)]
+enum QuickVetoAction {
+    /// Hold a temperature for a duration.
+    Start {
+        /// Id of the zone, e.g. Control_ZO1.
+        #[arg(long)]
+        zone: String,
+
+        /// Temperature in °C.
+        #[arg(long)]
+        temperature: f64,
+
+        #[arg(long, default_value_t = 180)]
+        duration_minutes: i64,
+
+        #[command(flatten)]
+        login: Login,
+    },
+    /// Return to the time program.
+    Cancel {
+        /// Id of the zone, e.g. Control_ZO1.
+        #[arg(long)]
+        zone: String,
+
+        #[command(flatten)]
+        login: Login,
+    },
+}
+
+#[derive(Subcommand)]
+enum HolidayAction {
+    /// Set the holiday period, from and to inclusive.
+    Set {
+        #[arg(long)]
+        from: NaiveDate,
+
+        #[arg(long)]
+        to: NaiveDate,
+
+        /// Temperature in °C of all zones during the holiday.
+        #[arg(long, default_value_t = 15.0)]
+        temperature: f64,
+
+        #[command(flatten)]
+        login: Login,
+    },
+    /// End the holiday period.
+    Clear {
+        #[command(flatten)]
+        login: Login,
+    },
+}
+
+#[derive(Subcommand)]
+enum VentilationAction {
+    /// Show the operation mode and fan levels.
+    Show {
+        /// Id of the ventilation, e.g. Control_VE1.
+        #[arg(long)]
+        ventilation: String,
+
+        #[command(flatten)]
+        login: Login,
+    },
+    /// Set the operation mode and/or the fan levels.
+    Set {
+        /// Id of the ventilation, e.g. Control_VE1.
+        #[arg(long)]
+        ventilation: String,
+
+        /// auto, day or night.
+        #[arg(long)]
+        mode: Option<VentilationMode>,
+
+        /// Fan level 1-6 of the day setting.
+        #[arg(long)]
+        day_level: Option<u8>,
+
+        /// Fan level 1-6 of the night setting.
+        #[arg(long)]
+        night_level: Option<u8>,
+
+        #[command(flatten)]
+        login: Login,
+    },
+}
+
+#[derive(Subcommand)]
+enum QuickModeAction {
+    /// Start a quick mode, replaces the active one.
+    Start {
+        /// QM_HOTWATER_BOOST, QM_VENTILATION_BOOST, QM_ONE_DAY_AWAY, QM_SYSTEM_OFF, QM_ONE_DAY_AT_HOME or QM_PARTY.
+        #[arg(long)]
+        mode: QuickMode,
+
+        /// Duration in minutes. Uses the default of the mode if not set.
+        #[arg(long)]
+        duration_minutes: Option<i64>,
+
+        #[command(flatten)]
+        login: Login,
+    },
+    /// Cancel the active quick mode.
+    Cancel {
+        #[command(flatten)]
+        login: Login,
+    },
+    /// Show the active quick mode.
+    Status {
+        #[command(flatten)]
+        login: Login,
+    },
+}
+
+#[derive(Subcommand)]
+enum ScheduleAction {
+    /// Write the time programs of the facility as yaml.
+    Export {
+        /// Path of the yaml file. Prints to stdout if not set.
+        #[arg(short, long)]
+        output: Option<String>,
+
+        #[command(flatten)]
+        login: Login,
+    },
+    /// Print the days of the yaml file that differ from the facility.
+    Diff {
+        /// Path of the yaml file.
+        file: String,
+
+        #[command(flatten)]
+        login: Login,
+    },
+    /// Validate the yaml file and push the changed time programs to the facility.
+    Apply {
+        /// Path of the yaml file.
+        file: String,
+
+        /// Only print the changes.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        login: Login,
+    },
+    /// Check the yaml file against the limits of the api without login.
+    Validate {
+        /// Path of the yaml file.
+        file: String,
+    },
+}
+
+#[derive(Subcommand, Clone, Copy)]
+enum PeriodArg {
+    /// iso week
+    Week {
+        #[arg(long)]
+        year: i32,
+        #[arg(long)]
+        week: u32,
+    },
+    Month {
+        #[arg(long)]
+        year: i32,
+        #[arg(long)]
+        month: u32,
+    },
+    /// October till end of April of the next year
+    Season {
+        /// year the heating season starts in
+        #[arg(long)]
+        year: i32,
+    },
+    Year {
+        #[arg(long)]
+        year: i32,
+    },
+    /// from inclusive, to exclusive
+    Range {
+        #[arg(long)]
+        from: NaiveDate,
+        #[arg(long)]
+        to: NaiveDate,
+    },
+}
+
+impl From<PeriodArg> for Period {
+    fn from(value: PeriodArg) -> Self {
+        match value {
+            PeriodArg::Week { year, week } => Period::Week { year, week },
+            PeriodArg::Month { year, month } => Period::Month { year, month },
+            PeriodArg::Season { year } => Period::HeatingSeason { start_year: year },
+            PeriodArg::Year { year } => Period::Year { year },
+            PeriodArg::Range { from, to } => Period::Range { from, to },
+        }
+    }
+}
+
+impl fmt::Display for Args {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "db_path: {}", self.db_file)
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    info!("{} {}", env!("CARGO_PKG_NAME"), VERSION_STR);
+    info!("Starting {} with: \n{}", env!("CARGO_PKG_NAME"), args);
+
+    match args.command {
+        Command::Zone {
+            action:
+                ZoneAction::Set {
+                    zone,
+                    temperature,
+                    login,
+                },
+        } => {
+            if let Err(e) = connect(&login)
+                .and_then(|c| c.set_zone_setpoint(&zone, temperature))
+                .map(|z| print_zone(&z))
+            {
+                error!("Failed to set zone {}. Error: \"{}\".", zone, e.to_string())
+            }
+        }
+        Command::Quickveto { action } => {
+            let result = match &action {
+                QuickVetoAction::Start {
+                    zone,
+                    temperature,
+                    duration_minutes,
+                    login,
+                } => connect(login)
+                    .and_then(|c| c.start_quick_veto(zone, *temperature, *duration_minutes)),
+                QuickVetoAction::Cancel { zone, login } => {
+                    connect(login).and_then(|c| c.cancel_quick_veto(zone))
+                }
+            };
+            match result {
+                Ok(zone) => print_zone(&zone),
+                Err(e) => error!("Failed to change quick veto. Error: \"{}\".", e.to_string()),
+            }
+        }
+        Command::Holiday { action } => {
+            let result = match &action {
+                HolidayAction::Set {
+                    from,
+                    to,
+                    temperature,
+                    login,
+                } => connect(login).and_then(|c| c.set_holiday_mode(*from, *to, *temperature)),
+                HolidayAction::Clear { login } => {
+                    connect(login).and_then(|c| c.clear_holiday_mode())
+                }
+            };
+            match result {
+                Ok(holiday) => print_holiday(holiday.as_ref()),
+                Err(e) => error!(
+                    "Failed to change holiday mode. Error: \"{}\".",
+                    e.to_string()
+                ),
+            }
+        }
+        Command::Ventilation { action } => {
+            let result = match &action {
+                VentilationAction::Show { ventilation, login } => {
+                    connect(login).and_then(|c| c.ventilation(ventilation))
+                }
+                VentilationAction::Set {
+                    ventilation,
+                    mode,
+                    day_level,
+                    night_level,
+                    login,
+                } => connect(login).and_then(|c| {
+                    set_ventilation(&c, ventilation, *mode, *day_level, *night_level)
+                }),
+            };
+            match result {
+                Ok(ventilation) => print_ventilation(&ventilation),
+                Err(e) => error!(
+                    "Failed to change ventilation. Error: \"{}\".",
+                    e.to_string()
+                ),
+            }
+        }
+        Command::Quickmode { action } => {
+            let result = match &action {
+                QuickModeAction::Start {
+                    mode,
+                    duration_minutes,
+                    login,
+                } => {
+                    connect(login).and_then(|c| c.start_quick_mode(mode.clone(), *duration_minutes))
+                }
+                QuickModeAction::Cancel { login } => {
+                    connect(login).and_then(|c| c.cancel_quick_mode())
+                }
+                QuickModeAction::Status { login } => connect(login).and_then(|c| c.quick_mode()),
+            };
+            match result {
+                Ok(quick_mode) => print_quick_mode(quick_mode.as_ref()),
+                Err(e) => error!("Failed to change quick mode. Error: \"{}\".", e.to_string()),
+            }
+        }
+        Command::Schedule { action } => {
+            if let Err(e) = run_schedule(&action) {
+                error!(
+                    "Failed to {} schedule. Error: \"{}\".",
+                    action,
+                    e.to_string()
+                )
+            }
+        }
+        command => run_with_db(&args.db_file, command),
+    }
+}
+
+// commands that read or write the database
+fn run_with_db(db_file: &str, command: Command) {
+    let db = match DB::new(Some(db_file)) {
+        Ok(db) => db,
+        Err(e) => {
+            error!("Failed to open database because \"{}\".", e.to_string());
+            return;
+        }
+    };
+
+    match command {
+        Command::Latest => match db.latest_values() {
+            Ok(latest) => {
+                let _ = print_stdout(latest.with_title());
+            }
+            Err(e) => error!(
+                "Failed to read latest values. Error: \"{}\".",
+                e.to_string()
+            ),
+        },
+        Command::Devices { firmware } => {
+            let result = if firmware {
+                db.get_firmware_changes()
+                    .map(|changes| print_stdout(changes.with_title()))
+            } else {
+                db.get_devices()
+                    .map(|devices| print_stdout(devices.with_title()))
+            };
+            if let Err(e) = result {
+                error!("Failed to read devices. Error: \"{}\".", e.to_string())
+            }
+        }
+        Command::Faults { all } => match db.get_faults(!all) {
+            Ok(faults) => {
+                let _ = print_stdout(faults.with_title());
+            }
+            Err(e) => error!("Failed to read faults. Error: \"{}\".", e.to_string()),
+        },
+        Command::Report { period } => match spf::aggregate(&db, period.into()) {
+            Ok(spf) => {
+                let _ = print_stdout(vec![spf].with_title());
+            }
+            Err(e) => error!("Failed to aggregate usage. Error: \"{}\".", e.to_string()),
+        },
+        Command::DegreeDays {
+            base,
+            daily,
+            yoy,
+            period,
+        } => {
+            if let Err(e) = print_degree_days(&db, period.into(), base, daily, yoy) {
+                error!("Failed to normalize usage. Error: \"{}\".", e.to_string())
+            }
+        }
+        Command::HeatingCurve {
+            heating_limit,
+            max_deviation,
+            lower,
+            period,
+        } => {
+            if let Err(e) =
+                print_heating_curve(&db, period.into(), heating_limit, max_deviation, lower)
+            {
+                error!(
+                    "Failed to analyse heating curve. Error: \"{}\".",
+                    e.to_string()
+                )
+            }
+        }
+        Command::Cost {
+            tariff_file,
+            daily,
+            period,
+        } => {
+            if let Err(e) = print_cost(&db, &tariff_file, period.into(), daily) {
+                error!("Failed to calculate costs. Error: \"{}\".", e.to_string())
+            }
+        }
+        Command::Emissions {
+            factor,
+            factor_file,
+            gas_factor,
+            gas_efficiency,
+            daily,
+            period,
+        } => {
+            let gas = GasBoiler {
+                factor: gas_factor,
+                efficiency: gas_efficiency,
+            };
+            if let Err(e) = print_emissions(&db, period.into(), factor, factor_file, &gas, daily) {
+                error!(
+                    "Failed to estimate emissions. Error: \"{}\".",
+                    e.to_string()
+                )
+            }
+        }
+        Command::Legionella {
+            temperature,
+            hold_minutes,
+            interval_days,
+            cycles,
+            period,
+        } => {
+            let requirement = Requirement {
+                temperature,
+                hold_minutes,
+                interval_days,
+            };
+            if let Err(e) = print_legionella(&db, period.into(), &requirement, cycles) {
+                error!(
+                    "Failed to check legionella protection. Error: \"{}\".",
+                    e.to_string()
+                )
+            }
+        }
+        Command::Quality {
+            max_gap_minutes,
+            details,
+            backfill,
+            period,
+        } => {
+            if let Err(e) = print_quality(&db, period.into(), max_gap_minutes, details, backfill) {
+                error!(
+                    "Failed to check data quality. Error: \"{}\".",
+                    e.to_string()
+                )
+            }
+        }
+        Command::Retain {
+            raw_days,
+            hourly_days,
+        } => {
+            let policy = RetentionPolicy {
+                raw_days,
+                hourly_days,
+            };
+            match retention::apply(&db, &policy, chrono::Utc::now().naive_utc()) {
+                Ok(report) => {
+                    let _ = print_stdout(vec![report].with_title());
+                }
+                Err(e) => error!("Failed to apply retention. Error: \"{}\".", e.to_string()),
+            }
+        }
+        Command::Export {
+            table,
+            columns,
+            format,
+            timezone,
+            units,
+            output,
+            period,
+        } => {
+            let result = UnitSet::new(&units).and_then(|units| {
+                let options = ExportOptions {
+                    table,
+                    channels: columns,
+                    format,
+                    timezone,
+                    units,
+                };
+                write_export(&db, &options, period.into(), output.as_deref())
+            });
+            if let Err(e) = result {
+                error!("Failed to export {}. Error: \"{}\".", table, e.to_string())
+            }
+        }
+        Command::Import {
+            source,
+            format,
+            cumulative,
+            unit,
+            file,
+        } => match external::import(&db, source, &file, format, cumulative, unit) {
+            Ok(count) => info!("Imported {} intervals of {}.", count, source),
+            Err(e) => error!(
+                "Failed to import \"{}\". Error: \"{}\".",
+                file,
+                e.to_string()
+            ),
+        },
+        Command::CrossCheck { period } => match external::cross_check(&db, period.into()) {
+            Ok(result) => {
+                let _ = print_stdout(result.with_title());
+            }
+            Err(e) => error!("Failed to cross check usage. Error: \"{}\".", e.to_string()),
+        },
+        Command::Zone { .. }
+        | Command::Quickveto { .. }
+        | Command::Holiday { .. }
+        | Command::Ventilation { .. }
+        | Command::Quickmode { .. }
+        | Command::Schedule { .. } => unreachable!("remote commands don't use the database"),
+    }
+}
+
+fn print_degree_days(
+    db: &DB,
+    period: Period,
+    base: f64,
+    daily: bool,
+    yoy: bool,
+) -> anyhow::Result<()> {
+    if daily {
+        print_stdout(degree_days::daily(db, period, base)?.with_title())?;
+    }
+    print_stdout(vec![degree_days::normalize(db, period, base)?].with_title())?;
+    if yoy {
+        let comparison = degree_days::compare(db, period.shifted_years(-1)?, period, base)?;
+        print_stdout(vec![comparison].with_title())?;
+    }
+    Ok(())
+}
+
+fn print_heating_curve(
+    db: &DB,
+    period: Period,
+    heating_limit: f64,
+    max_deviation: f64,
+    lower: Option<f64>,
+) -> anyhow::Result<()> {
+    let fit = heating_curve::fit(db, period, heating_limit)?;
+    let curve = fit.curve();
+    print_stdout(vec![fit].with_title())?;
+
+    let deviations = heating_curve::deviations(db, period, heating_limit, &curve, max_deviation)?;
+    if !deviations.is_empty() {
+        print_stdout(deviations.with_title())?;
+    }
+
+    if let Some(kelvin) = lower {
+        let estimate =
+            heating_curve::estimate_cop(db, period, heating_limit, &curve, &curve.lowered(kelvin))?;
+        print_stdout(vec![estimate].with_title())?;
+    }
+    Ok(())
+}
+
+fn print_cost(db: &DB, tariff_file: &str, period: Period, daily: bool) -> anyhow::Result<()> {
+    let config = TariffConfig::from_file(tariff_file)?;
+    let costs = tariff::calculate(db, &config, period)?;
+    if daily {
+        print_stdout(costs.with_title())?;
+    }
+    print_stdout(vec![tariff::aggregate(db, period)?].with_title())?;
+    Ok(())
+}
+
+fn print_emissions(
+    db: &DB,
+    period: Period,
+    factor: Option<f64>,
+    factor_file: Option<String>,
+    gas: &GasBoiler,
+    daily: bool,
+) -> anyhow::Result<()> {
+    let factors = match (factor, factor_file) {
+        (Some(factor), _) => EmissionFactors::Constant(factor),
+        (None, Some(path)) => EmissionFactors::from_csv(&path)?,
+        (None, None) => return Err(anyhow::anyhow!("No emission factor given.")),
+    };
+    if daily {
+        print_stdout(emissions::daily(db, period, &factors, gas)?.with_title())?;
+    }
+    print_stdout(vec![emissions::aggregate(db, period, &factors, gas)?].with_title())?;
+    Ok(())
+}
+
+fn print_legionella(
+    db: &DB,
+    period: Period,
+    requirement: &Requirement,
+    cycles: bool,
+) -> anyhow::Result<()> {
+    if cycles {
+        let (from, to) = period.range()?;
+        print_stdout(legionella::cycles(db, from, to, requirement)?.with_title())?;
+    }
+    print_stdout(legionella::history(db, period, requirement)?.with_title())?;
+    Ok(())
+}
+
+fn connect(login: &Login) -> anyhow::Result<Connector> {
+    let mut c = Connector::new(
+        UrlBase::VaillantSensoApi,
+        login.serial.clone(),
+        login.token_file.clone(),
+    );
+    c.login(&login.user, &login.pwd)?;
+    Ok(c)
+}
+
+impl fmt::Display for ScheduleAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ScheduleAction::Export { .. } => "export",
+            ScheduleAction::Diff { .. } => "diff",
+            ScheduleAction::Apply { .. } => "apply",
+            ScheduleAction::Validate { .. } => "validate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn run_schedule(action: &ScheduleAction) -> anyhow::Result<()> {
+    match action {
+        ScheduleAction::Export { output, login } => {
+            let yaml = Schedules::from_system(&connect(login)?.system()?.body)?.to_yaml()?;
+            match output {
+                Some(path) => std::fs::write(path, yaml)?,
+                None => print!("{}", yaml),
+            }
+        }
+        ScheduleAction::Diff { file, login } => {
+            let desired = Schedules::from_file(file)?;
+            let live = Schedules::from_system(&connect(login)?.system()?.body)?;
+            print_changes(timeprogram::diff(&live, &desired))?;
+        }
+        ScheduleAction::Apply {
+            file,
+            dry_run,
+            login,
+        } => {
+            let desired = Schedules::from_file(file)?;
+            let c = connect(login)?;
+            let changes = if *dry_run {
+                desired.validate()?;
+                timeprogram::diff(&Schedules::from_system(&c.system()?.body)?, &desired)
+            } else {
+                timeprogram::apply(&c, &desired)?
+            };
+            print_changes(changes)?;
+        }
+        ScheduleAction::Validate { file } => {
+            Schedules::from_file(file)?.validate()?;
+            println!("{} is valid", file);
+        }
+    }
+    Ok(())
+}
+
+fn print_changes(changes: Vec<timeprogram::Change>) -> anyhow::Result<()> {
+    if changes.is_empty() {
+        println!("no changes");
+    } else {
+        print_stdout(changes.with_title())?;
+    }
+    Ok(())
+}
+
+fn print_zone(zone: &Zone) {
+    let heating = zone.heating.as_ref().map(|h| &h.configuration);
+    println!(
+        "{} \"{}\": mode {}, setpoint {}, inside {}",
+        zone.id,
+        zone.configuration.name,
+        heating.map_or("-".to_string(), |h| format!("{:?}", h.mode)),
+        display_option(heating.and_then(|h| h.setpoint_temperature)),
+        display_option(zone.configuration.inside_temperature)
+    );
+    if let Some(veto) = &zone.configuration.quick_veto {
+        println!(
+            "quick veto: {}, setpoint {}",
+            if veto.active { "active" } else { "inactive" },
+            display_option(veto.temperature_setpoint)
+        );
+    }
+}
+
+fn print_holiday(holiday: Option<&HolidayMode>) {
+    match holiday {
+        Some(h) if h.active => println!(
+            "holiday from {} to {}, setpoint {}",
+            h.start_date.as_deref().unwrap_or("-"),
+            h.end_date.as_deref().unwrap_or("-"),
+            display_option(h.temperature_setpoint)
+        ),
+        _ => println!("no holiday"),
+    }
+}
+
+// applies every given setting, returns the ventilation after the last change
+fn set_ventilation(
+    c: &Connector,
+    id: &str,
+    mode: Option<VentilationMode>,
+    day_level: Option<u8>,
+    night_level: Option<u8>,
+) -> anyhow::Result<Ventilation> {
+    let mut ventilation = None;
+    if let Some(mode) = mode {
+        ventilation = Some(c.set_ventilation_operation_mode(id, mode)?);
+    }
+    if let Some(level) = day_level {
+        ventilation = Some(c.set_ventilation_day_level(id, level)?);
+    }
+    if let Some(level) = night_level {
+        ventilation = Some(c.set_ventilation_night_level(id, level)?);
+    }
+    ventilation.ok_or(anyhow::anyhow!(
+        "Nothing to set, use --mode, --day-level or --night-level."
+    ))
+}
+
+fn print_ventilation(ventilation: &Ventilation) {
+    let config = &ventilation.fan.configuration;
+    let level = |l: Option<u8>| l.map_or("-".to_string(), |l| l.to_string());
+    println!(
+        "{}: mode {:?}, day level {}, night level {}",
+        ventilation.id,
+        config.operation_mode,
+        level(config.day_level),
+        level(config.night_level)
+    );
+}
+
+fn print_quick_mode(quick_mode: Option<&QuickModeConfiguration>) {
+    match quick_mode {
+        Some(q) => println!(
+            "quick mode {}, duration {} minutes",
+            q.quickmode,
+            q.duration.map_or("-".to_string(), |d| d.to_string())
+        ),
+        None => println!("no quick mode"),
+    }
+}
+
+fn display_option(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |v| v.to_string())
+}
+
+fn write_export(
+    db: &DB,
+    options: &ExportOptions,
+    period: Period,
+    output: Option<&str>,
+) -> anyhow::Result<()> {
+    let (from, to) = period.range()?;
+    let count = match output {
+        Some(path) => export::export(db, options, from, to, BufWriter::new(File::create(path)?))?,
+        None => export::export(db, options, from, to, std::io::stdout())?,
+    };
+    info!("Exported {} rows of {}.", count, options.table.name());
+    Ok(())
+}
+
+fn print_quality(
+    db: &DB,
+    period: Period,
+    max_gap_minutes: i64,
+    details: bool,
+    backfill: bool,
+) -> anyhow::Result<()> {
+    let findings = quality::check(db, period, max_gap_minutes)?;
+    if details {
+        print_stdout(findings.with_title())?;
+    }
+    print_stdout(quality::summarize(&findings).with_title())?;
+    if backfill {
+        info!(
+            "Queued {} days for backfill.",
+            quality::queue_backfills(db, &findings)?
+        );
+    }
+    Ok(())
+}
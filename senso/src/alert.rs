@@ -0,0 +1,588 @@
+use std::fs;
+
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDateTime};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{Channel, DB},
+    legionella::{self, Requirement},
+    notify::{Sink, SinkConfig},
+    response::system,
+};
+
+/// Default minutes until an active alert is sent again
+pub const DEFAULT_REPEAT_AFTER_MINUTES: i64 = 12 * 60;
+
+/// Default °C the hot water tank may be below its setpoint
+pub const DEFAULT_HOT_WATER_MARGIN: f64 = 5.0;
+
+/// Rules and sinks loaded from a json config.
+///
+/// ```json
+/// {
+///     "rules": [
+///         { "name": "low water pressure", "type": "threshold", "channel": "waterpressure", "below": 1.0 },
+///         { "name": "hot water too cold", "type": "hot_water", "margin": 5.0, "for_minutes": 180 },
+///         { "name": "boiler jump", "type": "rate_of_change", "channel": "ch_bo_p", "max_change": 2000.0 },
+///         { "name": "no sensor data", "type": "missing_data", "channel": "outdoor", "max_age_minutes": 60 },
+///         { "name": "legionella", "type": "legionella", "temperature": 60.0, "hold_minutes": 30, "interval_days": 7 }
+///     ],
+///     "repeat_after_minutes": 720,
+///     "sinks": [
+///         { "type": "stdout" },
+///         { "type": "smtp", "host": "localhost", "from": "senso@localhost", "to": ["me@localhost"] },
+///         { "type": "webhook", "url": "http://localhost:8080/alert" },
+///         { "type": "mqtt", "host": "localhost", "topic": "senso/alert" }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AlertConfig {
+    pub rules: Vec<Rule>,
+    #[serde(default = "default_repeat_after_minutes")]
+    pub repeat_after_minutes: i64,
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+}
+
+fn default_repeat_after_minutes() -> i64 {
+    DEFAULT_REPEAT_AFTER_MINUTES
+}
+
+impl AlertConfig {
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn build_sinks(&self) -> Vec<Box<dyn Sink>> {
+        self.sinks.iter().map(|s| s.build()).collect()
+    }
+
+    /// Rules compare against the hot water setpoint of the system
+    pub fn needs_dhw_setpoint(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|r| matches!(r.condition, Condition::HotWater { .. }))
+    }
+}
+
+/// Configured temperature of the first hot water tank
+pub fn dhw_setpoint(system: &system::Body) -> Option<f64> {
+    system
+        .dhw
+        .first()
+        .and_then(|d| d.hotwater.configuration.temperature_setpoint)
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Rule {
+    /// Alerts are deduplicated by name
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: Condition,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// Value is below or above a limit.
+    /// With `for_minutes` every value of that time has to be outside the limit.
+    Threshold {
+        channel: Channel,
+        below: Option<f64>,
+        above: Option<f64>,
+        #[serde(default)]
+        for_minutes: i64,
+    },
+    /// Hot water tank is more than `margin` below the configured setpoint.
+    /// With `for_minutes` every value of that time has to be below.
+    HotWater {
+        #[serde(default = "default_hot_water_margin")]
+        margin: f64,
+        #[serde(default)]
+        for_minutes: i64,
+    },
+    /// Difference between the two latest values is larger than `max_change`
+    RateOfChange { channel: Channel, max_change: f64 },
+    /// Latest value is older than `max_age_minutes` or there is none
+    MissingData {
+        channel: Channel,
+        max_age_minutes: i64,
+    },
+    /// Hot water tank didn't hold the disinfection temperature in the last `interval_days`
+    Legionella {
+        #[serde(default = "default_disinfection_temperature")]
+        temperature: f64,
+        #[serde(default = "default_hold_minutes")]
+        hold_minutes: i64,
+        #[serde(default = "default_interval_days")]
+        interval_days: i64,
+    },
+}
+
+fn default_hot_water_margin() -> f64 {
+    DEFAULT_HOT_WATER_MARGIN
+}
+
+fn default_disinfection_temperature() -> f64 {
+    legionella::DEFAULT_DISINFECTION_TEMPERATURE
+}
+
+fn default_hold_minutes() -> i64 {
+    legionella::DEFAULT_HOLD_MINUTES
+}
+
+fn default_interval_days() -> i64 {
+    legionella::DEFAULT_INTERVAL_DAYS
+}
+
+/// Rule that fired
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    /// None for faults of a device
+    pub channel: Option<Channel>,
+    pub time: NaiveDateTime,
+    /// value that triggered the rule, None for missing data
+    pub value: Option<f64>,
+    pub message: String,
+}
+
+impl Rule {
+    /// Checks the rule against the stored values at `now` and the hot water setpoint of the system
+    pub fn evaluate(
+        &self,
+        db: &DB,
+        now: NaiveDateTime,
+        dhw_setpoint: Option<f64>,
+    ) -> anyhow::Result<Option<Alert>> {
+        let alert = |channel: Channel, value: Option<f64>, message: String| Alert {
+            rule: self.name.clone(),
+            channel: Some(channel),
+            time: now,
+            value,
+            message,
+        };
+
+        // values of the last `for_minutes` or the latest value
+        let values = |channel: Channel, for_minutes: i64| {
+            if for_minutes > 0 {
+                db.channel_values(
+                    channel,
+                    now - Duration::minutes(for_minutes),
+                    now + Duration::seconds(1),
+                )
+            } else {
+                db.latest_channel_values(channel, 1)
+            }
+        };
+
+        Ok(match self.condition {
+            Condition::Threshold {
+                channel,
+                below,
+                above,
+                for_minutes,
+            } => {
+                let outside = |v: f64| below.is_some_and(|b| v < b) || above.is_some_and(|a| v > a);
+                let values = values(channel, for_minutes)?;
+
+                match values.last() {
+                    Some((_, latest)) if values.iter().all(|(_, v)| outside(*v)) => Some(alert(
+                        channel,
+                        Some(*latest),
+                        format!(
+                            "{} is {} outside of [{}, {}].",
+                            channel,
+                            latest,
+                            below.map_or("-".into(), |b| b.to_string()),
+                            above.map_or("-".into(), |a| a.to_string())
+                        ),
+                    )),
+                    _ => None,
+                }
+            }
+            Condition::HotWater {
+                margin,
+                for_minutes,
+            } => {
+                let setpoint = dhw_setpoint.ok_or(anyhow!("No hot water setpoint."))?;
+                let values = values(Channel::Hotwatertank, for_minutes)?;
+                match values.last() {
+                    Some((_, latest)) if values.iter().all(|(_, v)| *v < setpoint - margin) => {
+                        Some(alert(
+                            Channel::Hotwatertank,
+                            Some(*latest),
+                            format!(
+                                "{} is {} more than {} below the setpoint {}.",
+                                Channel::Hotwatertank,
+                                latest,
+                                margin,
+                                setpoint
+                            ),
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            Condition::RateOfChange {
+                channel,
+                max_change,
+            } => {
+                let values = db.latest_channel_values(channel, 2)?;
+                match values[..] {
+                    [(_, latest), (_, previous)] if (latest - previous).abs() > max_change => {
+                        Some(alert(
+                            channel,
+                            Some(latest),
+                            format!(
+                                "{} changed by {} from {} to {}.",
+                                channel,
+                                latest - previous,
+                                previous,
+                                latest
+                            ),
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            Condition::MissingData {
+                channel,
+                max_age_minutes,
+            } => match db.latest_channel_values(channel, 1)?.first() {
+                Some((time, _)) if now - *time <= Duration::minutes(max_age_minutes) => None,
+                Some((time, _)) => Some(alert(
+                    channel,
+                    None,
+                    format!("No value for {} since {}.", channel, time),
+                )),
+                None => Some(alert(channel, None, format!("No value for {}.", channel))),
+            },
+            Condition::Legionella {
+                temperature,
+                hold_minutes,
+                interval_days,
+            } => {
+                let requirement = Requirement {
+                    temperature,
+                    hold_minutes,
+                    interval_days,
+                };
+                match legionella::last_cycle(db, now, &requirement)? {
+                    Some(_) => None,
+                    None => Some(alert(
+                        Channel::Hotwatertank,
+                        None,
+                        format!(
+                            "No disinfection cycle with {}°C for {} minutes in the last {} days.",
+                            temperature, hold_minutes, interval_days
+                        ),
+                    )),
+                }
+            }
+        })
+    }
+}
+
+/// Sends the alert to all sinks.
+///
+/// Returns if at least one sink accepted it, without sinks there is nothing to deliver.
+pub fn deliver(alert: &Alert, sinks: &[Box<dyn Sink>]) -> bool {
+    let mut delivered = false;
+    for sink in sinks {
+        match sink.send(alert) {
+            Ok(()) => delivered = true,
+            Err(e) => error!("Failed to send alert \"{}\": {}", alert.rule, e),
+        }
+    }
+    delivered || sinks.is_empty()
+}
+
+/// Evaluates all rules and sends new alerts to the sinks.
+///
+/// `dhw_setpoint` is needed by hot water rules only, see [`dhw_setpoint`].
+/// An active alert is sent again after `repeat_after_minutes`.
+/// Rules that no longer fire are resolved.
+/// Returns the sent alerts, an alert that no sink accepted is not marked as notified.
+pub fn run(
+    db: &DB,
+    config: &AlertConfig,
+    sinks: &[Box<dyn Sink>],
+    now: NaiveDateTime,
+    dhw_setpoint: Option<f64>,
+) -> anyhow::Result<Vec<Alert>> {
+    let mut sent = Vec::new();
+    for rule in &config.rules {
+        let alert = match rule.evaluate(db, now, dhw_setpoint) {
+            Ok(alert) => alert,
+            Err(e) => {
+                error!("Failed to evaluate rule \"{}\": {}", rule.name, e);
+                continue;
+            }
+        };
+
+        let alert = match alert {
+            Some(alert) => alert,
+            None => {
+                if db.clear_alert_state(&rule.name)? {
+                    info!("Alert \"{}\" resolved.", rule.name);
+                }
+                continue;
+            }
+        };
+
+        let since = match db.get_alert_state(&rule.name)? {
+            Some((_, last_notified))
+                if now - last_notified < Duration::minutes(config.repeat_after_minutes) =>
+            {
+                continue
+            }
+            Some((since, _)) => since,
+            None => now,
+        };
+
+        // retried on the next run if no sink got it
+        if !deliver(&alert, sinks) {
+            continue;
+        }
+        db.set_alert_state(&rule.name, since, now, &alert.message)?;
+        sent.push(alert);
+    }
+    Ok(sent)
+}
+
+/// Evaluates all rules now and sends new alerts to the sinks of the config
+pub fn check(
+    db: &DB,
+    config: &AlertConfig,
+    dhw_setpoint: Option<f64>,
+) -> anyhow::Result<Vec<Alert>> {
+    run(
+        db,
+        config,
+        &config.build_sinks(),
+        chrono::Utc::now().naive_utc(),
+        dhw_setpoint,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    use crate::{
+        db::{SensorData, DB},
+        notify::Sink,
+        yp::YpData,
+    };
+
+    use super::{run, Alert, AlertConfig};
+
+    struct Collect(Rc<RefCell<Vec<Alert>>>);
+
+    impl Sink for Collect {
+        fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+            self.0.borrow_mut().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    struct Fail;
+
+    impl Sink for Fail {
+        fn send(&self, _: &Alert) -> anyhow::Result<()> {
+            Err(anyhow::anyhow!("unreachable"))
+        }
+    }
+
+    fn time(hour: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 3, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + Duration::hours(hour)
+    }
+
+    fn config() -> AlertConfig {
+        serde_json::from_str(
+            r#"{
+                "rules": [
+                    { "name": "pressure", "type": "threshold", "channel": "waterpressure", "below": 1.0 },
+                    { "name": "hot water", "type": "threshold", "channel": "hotwatertank", "below": 45.0, "for_minutes": 120 },
+                    { "name": "boiler", "type": "rate_of_change", "channel": "ch_bo_p", "max_change": 2000.0 },
+                    { "name": "missing", "type": "missing_data", "channel": "outdoor", "max_age_minutes": 90 }
+                ],
+                "repeat_after_minutes": 360
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn rules() {
+        let db = DB::new(None).unwrap();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let sinks: Vec<Box<dyn Sink>> = vec![Box::new(Collect(sent.clone()))];
+        let config = config();
+
+        // nothing stored, only missing data fires
+        let alerts = run(&db, &config, &sinks, time(0), None).unwrap();
+        assert_eq!(1, alerts.len());
+        assert_eq!("missing", alerts[0].rule);
+
+        for hour in 0..3 {
+            db.insert_sensor_data_at(
+                time(hour),
+                SensorData::new_raw(Some(5.0), Some(40.0 + hour as f64), Some(0.8), None),
+            )
+            .unwrap();
+        }
+        for (d, ch_bo_p) in [(0, 0.0), (24, 3000.0)] {
+            db.insert_yp_data(&YpData {
+                ts: time(d - 48),
+                ch_bo_p,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        let alerts = run(&db, &config, &sinks, time(2), None).unwrap();
+        let rules: Vec<&str> = alerts.iter().map(|a| a.rule.as_str()).collect();
+        assert_eq!(vec!["pressure", "hot water", "boiler"], rules);
+        assert_eq!(Some(0.8), alerts[0].value);
+        assert_eq!(Some(42.0), alerts[1].value);
+        assert_eq!(Some(3000.0), alerts[2].value);
+
+        // deduplicated until repeat_after_minutes
+        assert!(run(&db, &config, &sinks, time(3), None).unwrap().is_empty());
+        // missing data fires again since it was resolved in between
+        let alerts = run(&db, &config, &sinks, time(5), None).unwrap();
+        assert_eq!(1, alerts.len());
+        assert_eq!("missing", alerts[0].rule);
+        // no hot water values in the last 2 hours
+        let alerts = run(&db, &config, &sinks, time(8), None).unwrap();
+        let rules: Vec<&str> = alerts.iter().map(|a| a.rule.as_str()).collect();
+        assert_eq!(vec!["pressure", "boiler"], rules);
+
+        // hot water reached 45 within the last 2 hours
+        db.insert_sensor_data_at(
+            time(9),
+            SensorData::new_raw(Some(5.0), Some(50.0), Some(1.5), None),
+        )
+        .unwrap();
+        db.insert_sensor_data_at(
+            time(10),
+            SensorData::new_raw(Some(5.0), Some(44.0), Some(1.5), None),
+        )
+        .unwrap();
+        assert!(run(&db, &config, &sinks, time(10), None)
+            .unwrap()
+            .is_empty());
+        assert_eq!(None, db.get_alert_state("pressure").unwrap());
+        assert_eq!(None, db.get_alert_state("hot water").unwrap());
+        assert!(db.get_alert_state("boiler").unwrap().is_some());
+
+        assert_eq!(7, sent.borrow().len());
+    }
+
+    #[test]
+    fn failed_sinks() {
+        let db = DB::new(None).unwrap();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let config = config();
+
+        let failing: Vec<Box<dyn Sink>> = vec![Box::new(Fail)];
+        assert!(run(&db, &config, &failing, time(0), None)
+            .unwrap()
+            .is_empty());
+        assert_eq!(None, db.get_alert_state("missing").unwrap());
+
+        // one working sink is enough
+        let sinks: Vec<Box<dyn Sink>> = vec![Box::new(Fail), Box::new(Collect(sent.clone()))];
+        let alerts = run(&db, &config, &sinks, time(1), None).unwrap();
+        assert_eq!(1, alerts.len());
+        assert_eq!(
+            Some((time(1), time(1))),
+            db.get_alert_state("missing").unwrap()
+        );
+        assert!(run(&db, &config, &sinks, time(2), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn hot_water_rule() {
+        let db = DB::new(None).unwrap();
+        let config: AlertConfig = serde_json::from_str(
+            r#"{ "rules": [{ "name": "hot water", "type": "hot_water", "for_minutes": 60 }] }"#,
+        )
+        .unwrap();
+        assert!(config.needs_dhw_setpoint());
+
+        for (minute, hotwatertank) in [(0, 52.0), (30, 48.0), (60, 46.0)] {
+            db.insert_sensor_data_at(
+                time(0) + Duration::minutes(minute),
+                SensorData::new_raw(None, Some(hotwatertank), None, None),
+            )
+            .unwrap();
+        }
+        // 52 is within 5 of the setpoint
+        assert!(run(&db, &config, &[], time(1), Some(55.0))
+            .unwrap()
+            .is_empty());
+        let alerts = run(
+            &db,
+            &config,
+            &[],
+            time(1) + Duration::minutes(10),
+            Some(55.0),
+        )
+        .unwrap();
+        assert_eq!(1, alerts.len());
+        assert_eq!(Some(46.0), alerts[0].value);
+        // a lower setpoint is reached
+        assert!(run(
+            &db,
+            &config,
+            &[],
+            time(1) + Duration::minutes(10),
+            Some(50.0)
+        )
+        .unwrap()
+        .is_empty());
+        assert_eq!(None, db.get_alert_state("hot water").unwrap());
+
+        // without setpoint the rule is skipped
+        assert!(
+            run(&db, &config, &[], time(1) + Duration::minutes(10), None)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn legionella_rule() {
+        let db = DB::new(None).unwrap();
+        let config: AlertConfig = serde_json::from_str(
+            r#"{ "rules": [{ "name": "legionella", "type": "legionella", "hold_minutes": 20 }] }"#,
+        )
+        .unwrap();
+
+        for minute in [0, 10, 20] {
+            db.insert_sensor_data_at(
+                time(0) + Duration::minutes(minute),
+                SensorData::new_raw(None, Some(61.0), None, None),
+            )
+            .unwrap();
+        }
+        assert!(run(&db, &config, &[], time(24 * 7), None)
+            .unwrap()
+            .is_empty());
+        let alerts = run(&db, &config, &[], time(24 * 7 + 1), None).unwrap();
+        assert_eq!(1, alerts.len());
+        assert_eq!(None, alerts[0].value);
+    }
+}
@@ -284,6 +284,12 @@ impl<'a, T> Iterator for IterRows<'a, T> {
 }
 
 #[cfg(test)]
+// the tests predate these lints, keep them as they are
+#[allow(
+    clippy::map_clone,
+    clippy::clone_on_copy,
+    clippy::assertions_on_constants
+)]
 mod tests {
     use crate::array2d::Array2DError;
 
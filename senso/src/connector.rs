@@ -5,15 +5,26 @@ use std::{
 };
 
 use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDate;
 use log::{debug, info, warn};
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 
 use ureq::{Agent, AgentBuilder, Request};
 
-use crate::request::emf;
+use crate::request::{
+    emf,
+    system::{
+        DhwMode, DhwOperationMode, DhwSetpoint, FanLevel, HolidayMode, QuickModeRequest, QuickVeto,
+        VentilationMode, VentilationOperationMode, ZoneSetpoint,
+    },
+};
 
-use super::{response, urls};
+use super::{
+    response,
+    timeprogram::{ProgramKind, TimeProgram},
+    urls,
+};
 
 const SMARTPHONE_ID: &str = "rustSenso";
 
@@ -23,6 +34,26 @@ pub enum ApiError {
     TokenOutdated,
     #[error("resource State is Outdated")]
     StateOutdated,
+    #[error("{name} {value} is out of range [{min}, {max}]")]
+    OutOfRange {
+        name: &'static str,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+    #[error("Period from {from} to {to} ends before it starts")]
+    InvalidPeriod { from: NaiveDate, to: NaiveDate },
+    /// 409, e.g. the system is in a mode that doesn't allow the change
+    #[error("Conflict: \"{0}\"")]
+    Conflict(String),
+    /// 400 or 422, e.g. a value rejected by the api
+    #[error("Request rejected with status {status}: \"{body}\"")]
+    Rejected { status: u16, body: String },
+    #[error("Invalid time program: {0}")]
+    InvalidTimeProgram(String),
+    /// value unknown to this version, e.g. a typo on the command line
+    #[error("Unknown {name} \"{value}\"")]
+    UnknownValue { name: &'static str, value: String },
 }
 
 pub struct Connector {
@@ -189,6 +220,46 @@ impl Connector {
 
         Ok(resp.into_json()?)
     }
+
+    // sends body as json with given method, typed errors for rejected requests
+    fn send_api<B: Serialize>(&self, method: &str, url: &str, body: Option<&B>) -> Result<()> {
+        if !self.disable_login_check {
+            if let Err(e) = &self.login_state {
+                bail!(e.to_string())
+            }
+        }
+        let req = self.default_header(self.agent.request(method, url));
+        let result = match body {
+            Some(body) => req.send_json(body),
+            None => req.call(),
+        };
+        match result {
+            Ok(_) => {
+                debug!("{} {} succeeded.", method, url);
+                Ok(())
+            }
+            Err(e) => match e.kind() {
+                ureq::ErrorKind::HTTP => {
+                    // SAFTEY into_response should never fail since we matched on ErrorKind::HTTP
+                    let resp = e.into_response().unwrap();
+                    let status = resp.status();
+                    let body = resp.into_string().unwrap_or_default();
+                    match status {
+                        409 => bail!(ApiError::Conflict(body)),
+                        400 | 422 => bail!(ApiError::Rejected { status, body }),
+                        _ => bail!(
+                            "{} {} failed with status {}. Response: \"{}\".",
+                            method,
+                            url,
+                            status,
+                            body
+                        ),
+                    }
+                }
+                _ => Err(e.into()),
+            },
+        }
+    }
 }
 
 // PUBLIC INTERFACE //
@@ -216,10 +287,237 @@ impl Connector {
         self.call_api(self.urls.SYSTEM_STATUS(), emf::empty_query())
     }
 
+    pub fn system(&self) -> Result<response::system::Root> {
+        self.call_api(self.urls.SYSTEM(), emf::empty_query())
+    }
+
+    /// Current errors and maintenance messages of all devices
+    pub fn errors(&self) -> Result<Vec<response::hvac_state::ErrorMessage>> {
+        let state: response::hvac_state::Root =
+            self.call_api(self.urls.HVAC_STATE(), emf::empty_query())?;
+        Ok(state.body.error_messages)
+    }
+
+    /// Sets the target temperature of the hot water, see [DhwSetpoint] for the limits
+    pub fn set_dhw_setpoint(&self, dhw_id: &str, temperature: f64) -> Result<()> {
+        let body = DhwSetpoint::new(temperature)?;
+        self.send_api("PUT", &self.urls.DHW_SETPOINT(dhw_id), Some(&body))?;
+        info!("Set hot water setpoint of {} to {}.", dhw_id, temperature);
+        Ok(())
+    }
+
+    pub fn set_dhw_operation_mode(&self, dhw_id: &str, mode: DhwMode) -> Result<()> {
+        let body = DhwOperationMode {
+            operation_mode: mode,
+        };
+        self.send_api("PUT", &self.urls.DHW_OPERATION_MODE(dhw_id), Some(&body))?;
+        info!("Set hot water operation mode of {} to {}.", dhw_id, mode);
+        Ok(())
+    }
+
+    // current state of the zone after a change
+    fn zone(&self, zone_id: &str) -> Result<response::system::Zone> {
+        self.system()?
+            .body
+            .zones
+            .into_iter()
+            .find(|z| z.id == zone_id)
+            .ok_or(anyhow!("Zone \"{}\" not found.", zone_id))
+    }
+
+    /// Sets the DAY temperature of the zone's heating, see [ZoneSetpoint] for the limits.
+    /// Returns the zone after the change.
+    pub fn set_zone_setpoint(
+        &self,
+        zone_id: &str,
+        temperature: f64,
+    ) -> Result<response::system::Zone> {
+        let body = ZoneSetpoint::new(temperature)?;
+        self.send_api("PUT", &self.urls.ZONE_SETPOINT(zone_id), Some(&body))?;
+        info!("Set setpoint of zone {} to {}.", zone_id, temperature);
+        self.zone(zone_id)
+    }
+
+    /// Holds the temperature of the zone for the duration. Returns the zone after the change.
+    pub fn start_quick_veto(
+        &self,
+        zone_id: &str,
+        temperature: f64,
+        duration_minutes: i64,
+    ) -> Result<response::system::Zone> {
+        let body = QuickVeto::new(temperature, duration_minutes)?;
+        self.send_api("PUT", &self.urls.ZONE_QUICK_VETO(zone_id), Some(&body))?;
+        info!(
+            "Started quick veto of zone {} with {} for {} minutes.",
+            zone_id, temperature, duration_minutes
+        );
+        self.zone(zone_id)
+    }
+
+    /// Returns the zone after the change.
+    pub fn cancel_quick_veto(&self, zone_id: &str) -> Result<response::system::Zone> {
+        self.send_api::<()>("DELETE", &self.urls.ZONE_QUICK_VETO(zone_id), None)?;
+        info!("Canceled quick veto of zone {}.", zone_id);
+        self.zone(zone_id)
+    }
+
+    /// Sets the holiday period of the whole system from `from` until `to` inclusive.
+    /// Returns the holiday mode after the change.
+    pub fn set_holiday_mode(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        temperature: f64,
+    ) -> Result<Option<response::system::HolidayMode>> {
+        let body = HolidayMode::new(from, to, temperature)?;
+        self.send_api("PUT", self.urls.HOLIDAY_MODE(), Some(&body))?;
+        info!(
+            "Set holiday mode from {} to {} with {}.",
+            from, to, temperature
+        );
+        Ok(self.system()?.body.configuration.holidaymode)
+    }
+
+    /// Returns the holiday mode after the change.
+    pub fn clear_holiday_mode(&self) -> Result<Option<response::system::HolidayMode>> {
+        self.send_api(
+            "PUT",
+            self.urls.HOLIDAY_MODE(),
+            Some(&HolidayMode::inactive()),
+        )?;
+        info!("Cleared holiday mode.");
+        Ok(self.system()?.body.configuration.holidaymode)
+    }
+
+    /// Ventilation of the system with fan configuration and time program
+    pub fn ventilation(&self, ventilation_id: &str) -> Result<response::system::Ventilation> {
+        self.system()?
+            .body
+            .ventilation
+            .into_iter()
+            .find(|v| v.id == ventilation_id)
+            .ok_or(anyhow!("Ventilation \"{}\" not found.", ventilation_id))
+    }
+
+    /// Returns the ventilation after the change.
+    pub fn set_ventilation_operation_mode(
+        &self,
+        ventilation_id: &str,
+        mode: VentilationMode,
+    ) -> Result<response::system::Ventilation> {
+        let body = VentilationOperationMode {
+            operation_mode: mode,
+        };
+        self.send_api(
+            "PUT",
+            &self.urls.VENTILATION_OPERATION_MODE(ventilation_id),
+            Some(&body),
+        )?;
+        info!(
+            "Set operation mode of ventilation {} to {}.",
+            ventilation_id, mode
+        );
+        self.ventilation(ventilation_id)
+    }
+
+    /// Sets the fan level of the DAY setting, see [FanLevel] for the limits.
+    /// Returns the ventilation after the change.
+    pub fn set_ventilation_day_level(
+        &self,
+        ventilation_id: &str,
+        level: u8,
+    ) -> Result<response::system::Ventilation> {
+        let body = FanLevel::new(level)?;
+        self.send_api(
+            "PUT",
+            &self.urls.VENTILATION_DAY_LEVEL(ventilation_id),
+            Some(&body),
+        )?;
+        info!(
+            "Set day level of ventilation {} to {}.",
+            ventilation_id, level
+        );
+        self.ventilation(ventilation_id)
+    }
+
+    /// Sets the fan level of the NIGHT setting, see [FanLevel] for the limits.
+    /// Returns the ventilation after the change.
+    pub fn set_ventilation_night_level(
+        &self,
+        ventilation_id: &str,
+        level: u8,
+    ) -> Result<response::system::Ventilation> {
+        let body = FanLevel::new(level)?;
+        self.send_api(
+            "PUT",
+            &self.urls.VENTILATION_NIGHT_LEVEL(ventilation_id),
+            Some(&body),
+        )?;
+        info!(
+            "Set night level of ventilation {} to {}.",
+            ventilation_id, level
+        );
+        self.ventilation(ventilation_id)
+    }
+
+    /// Active quick mode of the system, None if there is none
+    pub fn quick_mode(&self) -> Result<Option<response::system::QuickModeConfiguration>> {
+        Ok(self.system()?.body.configuration.quickmode)
+    }
+
+    /// Starts a quick mode, the duration in minutes is optional. Returns the quick mode after the change.
+    pub fn start_quick_mode(
+        &self,
+        mode: response::system::QuickMode,
+        duration_minutes: Option<i64>,
+    ) -> Result<Option<response::system::QuickModeConfiguration>> {
+        let body = QuickModeRequest::new(mode.clone(), duration_minutes)?;
+        self.send_api("PUT", self.urls.QUICK_MODE(), Some(&body))?;
+        info!("Started quick mode {}.", mode);
+        self.quick_mode()
+    }
+
+    /// Returns the quick mode after the change.
+    pub fn cancel_quick_mode(&self) -> Result<Option<response::system::QuickModeConfiguration>> {
+        self.send_api::<()>("DELETE", self.urls.QUICK_MODE(), None)?;
+        info!("Canceled quick mode.");
+        self.quick_mode()
+    }
+
+    /// Replaces the whole week of a time program after validating it for the kind.
+    pub fn set_time_program(
+        &self,
+        kind: ProgramKind,
+        id: &str,
+        program: &TimeProgram,
+    ) -> Result<()> {
+        program.validate(kind)?;
+        let url = match kind {
+            ProgramKind::Zone => self.urls.ZONE_TIME_PROGRAM(id),
+            ProgramKind::Dhw => self.urls.DHW_TIME_PROGRAM(id),
+            ProgramKind::Circulation => self.urls.CIRCULATION_TIME_PROGRAM(id),
+            ProgramKind::Ventilation => self.urls.VENTILATION_TIME_PROGRAM(id),
+        };
+        self.send_api("PUT", &url, Some(&program.to_request()))?;
+        info!("Set {} time program of {}.", kind, id);
+        Ok(())
+    }
+
     pub fn live_report(&self) -> Result<response::live_report::Root> {
         self.call_api(self.urls.LIVE_REPORT(), emf::empty_query())
     }
 
+    /// Serial and firmware of the gateway
+    pub fn facility_details(&self) -> Result<response::facility_details::Root> {
+        self.call_api(self.urls.FACILITY_DETAILS(), emf::empty_query())
+    }
+
+    pub fn gateway_type(&self) -> Result<String> {
+        let gateway: response::gateway_type::Root =
+            self.call_api(self.urls.GATEWAY_TYPE(), emf::empty_query())?;
+        Ok(gateway.body.gateway_type)
+    }
+
     pub fn emf_devices(&self) -> Result<response::emf_devices::Root> {
         self.call_api(self.urls.EMF_DEVICES(), emf::empty_query())
     }
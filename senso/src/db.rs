@@ -1,18 +1,238 @@
+use std::{collections::BTreeMap, vec};
+
 use anyhow::anyhow;
-use log::{debug, info};
-use rusqlite::{params, Connection};
+use chrono::{DateTime, NaiveDateTime};
+use cli_table::Table;
+use itertools::Itertools;
+use log::{debug, info, warn};
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, Display, EnumIter, EnumString, IntoStaticStr};
+
+use crate::{
+    external::Source,
+    faults::Fault,
+    inventory::{DeviceInfo, FirmwareChange},
+    meter::MeterReading,
+    response::{self, live_report::AssociatedDeviceFunction, system::QuickMode},
+    tariff::DailyCost,
+    units::{Concentration, Dimension, Pressure, Quantity, Ratio, Temperature, Unit},
+    yp::YpData,
+};
+
+/// Rows fetched at once by the iterators over a range
+pub const PAGE_SIZE: usize = 1000;
+
+/// Stored time series, named like its column
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Deserialize,
+    Serialize,
+    AsRefStr,
+    Display,
+    EnumIter,
+    EnumString,
+    IntoStaticStr,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Channel {
+    Outdoor,
+    Hotwatertank,
+    Waterpressure,
+    Heatingcircuit,
+    /// ppm
+    Co2,
+    /// relative humidity in %
+    Humidity,
+    ChHpY,
+    ChHpP,
+    ChBoP,
+    HwHpY,
+    HwHpP,
+    HwBoP,
+    CoHpY,
+    CoHpP,
+    /// power usage of the ventilation
+    VeP,
+}
+
+/// Channels of the Temperature table
+pub const SENSOR_CHANNELS: [Channel; 6] = [
+    Channel::Outdoor,
+    Channel::Hotwatertank,
+    Channel::Waterpressure,
+    Channel::Heatingcircuit,
+    Channel::Co2,
+    Channel::Humidity,
+];
+
+/// Aggregation interval of the Temperature table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+pub enum Bucket {
+    Hourly,
+    Daily,
+}
+
+impl Bucket {
+    pub fn table(&self) -> &'static str {
+        match self {
+            Bucket::Hourly => "TemperatureHourly",
+            Bucket::Daily => "TemperatureDaily",
+        }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Bucket::Hourly => 3600,
+            Bucket::Daily => 86400,
+        }
+    }
+}
+
+/// Start of a bucket with the min, max, mean and count of each requested channel
+pub type RollupRow = (NaiveDateTime, Vec<Option<f64>>);
+
+/// Aggregate of a sensor channel over one bucket
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Rollup {
+    /// start of the bucket
+    #[table(title = "time")]
+    pub time: NaiveDateTime,
+    #[table(title = "min")]
+    pub min: f64,
+    #[table(title = "max")]
+    pub max: f64,
+    #[table(title = "mean")]
+    pub mean: f64,
+    /// samples with a value
+    #[table(title = "count")]
+    pub count: i64,
+}
 
-use crate::{response, yp::YpData};
+impl Channel {
+    /// Table the channel is stored in
+    pub fn table(&self) -> &'static str {
+        match self {
+            Channel::Outdoor
+            | Channel::Hotwatertank
+            | Channel::Waterpressure
+            | Channel::Heatingcircuit
+            | Channel::Co2
+            | Channel::Humidity => "Temperature",
+            _ => "Usage",
+        }
+    }
+
+    /// Column the channel is stored in
+    pub fn column(&self) -> &'static str {
+        self.into()
+    }
+
+    /// Yield and power are stored in Wh
+    pub fn is_energy(&self) -> bool {
+        self.dimension() == Dimension::Energy
+    }
 
-#[derive(Debug, PartialEq)]
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Channel::Outdoor | Channel::Hotwatertank | Channel::Heatingcircuit => {
+                Dimension::Temperature
+            }
+            Channel::Waterpressure => Dimension::Pressure,
+            Channel::Co2 => Dimension::Concentration,
+            Channel::Humidity => Dimension::Ratio,
+            _ => Dimension::Energy,
+        }
+    }
+
+    /// Unit the values of the channel are stored in
+    pub fn unit(&self) -> Unit {
+        Unit::base(self.dimension())
+    }
+
+    /// Stored value of the channel as a quantity
+    pub fn quantity(&self, value: f64) -> Quantity {
+        Quantity::new(value, self.unit())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct SensorData {
     outdoor_temp: Option<f64>,                        //system status
     domestic_hot_water_tank_temperature: Option<f64>, //live report; Device ID: Control_DHW => Report ID: DomesticHotWaterTankTemperature
     water_pressure_sensor: Option<f64>, //live report; Device ID: Control_SYS_senso => Report ID: WaterPressureSensor
     flow_temperature_sensor: Option<f64>, //live report; Device ID: Control_CC1 => Report ID: FlowTemperatureSensor
+    quick_mode: Option<QuickMode>, //system configuration; None if no quick mode is active or unknown
+    co2: Option<f64>,              //live report; Report of category AIR_QUALITY in ppm
+    humidity: Option<f64>, //live report; Report of category AIR_QUALITY for RELATIVE_HUMIDITY
 }
 
 impl SensorData {
+    /// Value of a sensor channel, None for usage channels
+    pub fn value(&self, channel: Channel) -> Option<f64> {
+        match channel {
+            Channel::Outdoor => self.outdoor_temp,
+            Channel::Hotwatertank => self.domestic_hot_water_tank_temperature,
+            Channel::Waterpressure => self.water_pressure_sensor,
+            Channel::Heatingcircuit => self.flow_temperature_sensor,
+            Channel::Co2 => self.co2,
+            Channel::Humidity => self.humidity,
+            _ => None,
+        }
+    }
+
+    pub fn quantity(&self, channel: Channel) -> Option<Quantity> {
+        self.value(channel).map(|v| channel.quantity(v))
+    }
+
+    pub fn outdoor_temperature(&self) -> Option<Temperature> {
+        self.outdoor_temp.map(Temperature::from_celsius)
+    }
+
+    pub fn hot_water_tank_temperature(&self) -> Option<Temperature> {
+        self.domestic_hot_water_tank_temperature
+            .map(Temperature::from_celsius)
+    }
+
+    pub fn water_pressure(&self) -> Option<Pressure> {
+        self.water_pressure_sensor.map(Pressure::from_bar)
+    }
+
+    pub fn flow_temperature(&self) -> Option<Temperature> {
+        self.flow_temperature_sensor.map(Temperature::from_celsius)
+    }
+
+    pub fn co2(&self) -> Option<Concentration> {
+        self.co2.map(Concentration::from_ppm)
+    }
+
+    pub fn humidity(&self) -> Option<Ratio> {
+        self.humidity.map(Ratio::from_percent)
+    }
+
+    /// Quick mode active when the sample was taken
+    pub fn quick_mode(&self) -> Option<QuickMode> {
+        self.quick_mode.clone()
+    }
+
+    pub fn with_quick_mode(mut self, quick_mode: Option<QuickMode>) -> SensorData {
+        self.quick_mode = quick_mode;
+        self
+    }
+
+    /// Sets co2 in ppm and relative humidity in % of a ventilation
+    pub fn with_air_quality(mut self, co2: Option<f64>, humidity: Option<f64>) -> SensorData {
+        self.co2 = co2;
+        self.humidity = humidity;
+        self
+    }
+
     pub fn new(
         status: &Result<response::status::Root, ()>,
         live_report: &Result<response::live_report::Root, ()>,
@@ -20,20 +240,30 @@ impl SensorData {
         let mut domestic_hot_water_tank_temperature = None;
         let mut water_pressure_sensor = None;
         let mut flow_temperature_sensor = None;
+        let mut co2 = None;
+        let mut humidity = None;
 
         if let Ok(data) = live_report {
             domestic_hot_water_tank_temperature = data
                 .body
                 .find_report_for_device("Control_DHW", "DomesticHotWaterTankTemperature")
-                .map(|r| r.value);
+                .and_then(|r| reading(r, Channel::Hotwatertank));
             water_pressure_sensor = data
                 .body
                 .find_report_for_device("Control_SYS_senso", "WaterPressureSensor")
-                .map(|r| r.value);
+                .and_then(|r| reading(r, Channel::Waterpressure));
             flow_temperature_sensor = data
                 .body
                 .find_report_for_device("Control_CC1", "FlowTemperatureSensor")
-                .map(|r| r.value);
+                .and_then(|r| reading(r, Channel::Heatingcircuit));
+            co2 = data
+                .body
+                .find_air_quality_report(None)
+                .and_then(|r| reading(r, Channel::Co2));
+            humidity = data
+                .body
+                .find_air_quality_report(Some(AssociatedDeviceFunction::RelativeHumidity))
+                .and_then(|r| reading(r, Channel::Humidity));
         }
 
         SensorData {
@@ -45,6 +275,9 @@ impl SensorData {
             domestic_hot_water_tank_temperature,
             water_pressure_sensor,
             flow_temperature_sensor,
+            quick_mode: None,
+            co2,
+            humidity,
         }
     }
 
@@ -59,14 +292,213 @@ impl SensorData {
             domestic_hot_water_tank_temperature,
             water_pressure_sensor,
             flow_temperature_sensor,
+            quick_mode: None,
+            co2: None,
+            humidity: None,
         }
     }
 }
 
+/// Value of a live report in the unit of the channel.
+/// None if the unit is unknown or not one of the dimension of the channel.
+fn reading(report: &response::live_report::Report, channel: Channel) -> Option<f64> {
+    match Quantity::parse(report.value, &report.unit).and_then(|q| q.get(channel.unit())) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Ignoring {} of report {}: {}.", channel, report.id, e);
+            None
+        }
+    }
+}
+
+/// Sensor data with the time it was collected
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorSample {
+    pub time: NaiveDateTime,
+    pub data: SensorData,
+}
+
+/// Latest stored value of a channel
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct LatestValue {
+    #[table(title = "channel")]
+    pub channel: Channel,
+    #[table(title = "time")]
+    pub time: NaiveDateTime,
+    #[table(title = "value")]
+    pub value: f64,
+}
+
 pub struct DB {
     conn: Connection,
 }
 
+// fetches the rows after (time, id) and before the end, returns time, id and row
+type FetchPage<T> = fn(&DB, (i64, i64), i64) -> Result<Vec<(i64, i64, T)>, anyhow::Error>;
+
+/// Iterates over the rows of a range ordered by time.
+///
+/// Rows are fetched in pages of `PAGE_SIZE`, so large ranges are not loaded at once.
+pub struct RangeIter<'a, T> {
+    db: &'a DB,
+    fetch: FetchPage<T>,
+    // time and id of the last row
+    after: (i64, i64),
+    to: i64,
+    page: vec::IntoIter<(i64, i64, T)>,
+    done: bool,
+}
+
+impl<'a, T> Iterator for RangeIter<'a, T> {
+    type Item = Result<T, anyhow::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some((time, id, row)) = self.page.next() {
+            self.after = (time, id);
+            return Some(Ok(row));
+        }
+        if self.done {
+            return None;
+        }
+
+        match (self.fetch)(self.db, self.after, self.to) {
+            Ok(page) => {
+                self.done = page.len() < PAGE_SIZE;
+                self.page = page.into_iter();
+                let (time, id, row) = self.page.next()?;
+                self.after = (time, id);
+                Some(Ok(row))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+// converts a unix timestamp of a table
+fn to_naive(time: i64, table: &str) -> Result<NaiveDateTime, anyhow::Error> {
+    DateTime::from_timestamp(time, 0)
+        .map(|t| t.naive_utc())
+        .ok_or(anyhow!("Invalid timestamp in {}.", table))
+}
+
+// id, time and data of a row of the Temperature table
+fn sensor_row(row: &Row) -> rusqlite::Result<(i64, i64, SensorData)> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        SensorData::new_raw(row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)
+            .with_quick_mode(parse_quick_mode(row.get(6)?))
+            .with_air_quality(row.get(7)?, row.get(8)?),
+    ))
+}
+
+// unknown names are logged and kept as Unknown
+fn parse_quick_mode(name: Option<String>) -> Option<QuickMode> {
+    name.map(QuickMode::from)
+}
+
+fn fetch_sensor_page(
+    db: &DB,
+    after: (i64, i64),
+    to: i64,
+) -> Result<Vec<(i64, i64, SensorSample)>, anyhow::Error> {
+    let mut stmt = db.conn.prepare(
+        r#"SELECT id, time, outdoor, hotwatertank, waterpressure, heatingcircuit, quickmode, co2, humidity FROM Temperature
+        WHERE (time > ?1 OR (time = ?1 AND id > ?2)) AND time < ?3 ORDER BY time, id LIMIT ?4;"#,
+    )?;
+    let rows = stmt.query_map((after.0, after.1, to, PAGE_SIZE), sensor_row)?;
+
+    let mut result = Vec::new();
+    for row in rows {
+        let (id, time, data) = row?;
+        result.push((
+            time,
+            id,
+            SensorSample {
+                time: to_naive(time, "Temperature")?,
+                data,
+            },
+        ));
+    }
+    Ok(result)
+}
+
+fn fetch_yp_page(
+    db: &DB,
+    after: (i64, i64),
+    to: i64,
+) -> Result<Vec<(i64, i64, YpData)>, anyhow::Error> {
+    let mut stmt = db.conn.prepare(
+        r#"SELECT id, time, ch_hp_y, ch_hp_p, ch_bo_p, ch_yp, hw_hp_y, hw_hp_p, hw_bo_p, hw_yp, total_y, total_p, total_yp, co_hp_y, co_hp_p, co_eer, ve_p
+        FROM Usage WHERE (time > ?1 OR (time = ?1 AND id > ?2)) AND time < ?3 ORDER BY time, id LIMIT ?4;"#,
+    )?;
+    let rows = stmt.query_map((after.0, after.1, to, PAGE_SIZE), |row| {
+        // columns added later can be NULL
+        let value = |i: usize| -> rusqlite::Result<f64> {
+            Ok(row.get::<_, Option<f64>>(i)?.unwrap_or_default())
+        };
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            YpData {
+                ts: NaiveDateTime::default(),
+                ch_hp_y: value(2)?,
+                ch_hp_p: value(3)?,
+                ch_bo_p: value(4)?,
+                ch_yp: value(5)?,
+                hw_hp_y: value(6)?,
+                hw_hp_p: value(7)?,
+                hw_bo_p: value(8)?,
+                hw_yp: value(9)?,
+                total_y: value(10)?,
+                total_p: value(11)?,
+                total_yp: value(12)?,
+                co_hp_y: value(13)?,
+                co_hp_p: value(14)?,
+                co_eer: value(15)?,
+                ve_p: value(16)?,
+            },
+        ))
+    })?;
+
+    let mut result = Vec::new();
+    for row in rows {
+        let (id, time, mut yp_data) = row?;
+        yp_data.ts = to_naive(time, "Usage")?;
+        result.push((time, id, yp_data));
+    }
+    Ok(result)
+}
+
+// Adds columns to an existing table, for databases created by an older version
+fn add_missing_columns(
+    conn: &Connection,
+    table: &str,
+    columns: &[(&str, &str)],
+) -> Result<(), anyhow::Error> {
+    for (column, column_type) in columns {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2;",
+            (table, column),
+            |row| row.get(0),
+        )?;
+        if !exists {
+            conn.execute(
+                &format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, column, column_type
+                ),
+                (),
+            )?;
+            info!("Added column {} to table {}.", column, table);
+        }
+    }
+    Ok(())
+}
+
 impl DB {
     // Opens sqlite at PATH or if None in memory
     pub fn new(path: Option<&str>) -> Result<DB, anyhow::Error> {
@@ -87,9 +519,18 @@ impl DB {
             outdoor REAL,
             hotwatertank REAL,
             waterpressure REAL,
-            heatingcircuit REAL)"#,
+            heatingcircuit REAL,
+            quickmode TEXT,
+            co2 REAL,
+            humidity REAL)"#,
             (),
         )?;
+        // quick mode and air quality were added later
+        add_missing_columns(
+            &conn,
+            "Temperature",
+            &[("quickmode", "TEXT"), ("co2", "REAL"), ("humidity", "REAL")],
+        )?;
 
         conn.execute(
             r#" CREATE TABLE IF NOT EXISTS Usage (
@@ -105,36 +546,239 @@ impl DB {
                 hw_yp REAL,
                 total_y INTEGER,
                 total_p INTEGER,
-                total_yp REAL)"#,
+                total_yp REAL,
+                co_hp_y INTEGER,
+                co_hp_p INTEGER,
+                co_eer REAL,
+                ve_p INTEGER)"#,
+            (),
+        )?;
+        // cooling and ventilation were added later
+        add_missing_columns(
+            &conn,
+            "Usage",
+            &[
+                ("co_hp_y", "INTEGER"),
+                ("co_hp_p", "INTEGER"),
+                ("co_eer", "REAL"),
+                ("ve_p", "INTEGER"),
+            ],
+        )?;
+
+        conn.execute(
+            r#" CREATE TABLE IF NOT EXISTS MeterReading (
+                id INTEGER PRIMARY KEY,
+                time INTEGER NOT NULL,
+                device_id TEXT NOT NULL,
+                device TEXT NOT NULL,
+                energy_type TEXT NOT NULL,
+                function TEXT NOT NULL,
+                from_ts INTEGER NOT NULL,
+                to_ts INTEGER NOT NULL,
+                value REAL NOT NULL,
+                UNIQUE(device_id, energy_type, function, to_ts))"#,
+            (),
+        )?;
+
+        conn.execute(
+            r#" CREATE TABLE IF NOT EXISTS Cost (
+                id INTEGER PRIMARY KEY,
+                time INTEGER NOT NULL UNIQUE,
+                ch_cost REAL,
+                hw_cost REAL,
+                bo_cost REAL,
+                co_cost REAL,
+                base_fee REAL,
+                total_cost REAL)"#,
+            (),
+        )?;
+
+        conn.execute(
+            r#" CREATE TABLE IF NOT EXISTS AlertState (
+                id INTEGER PRIMARY KEY,
+                rule TEXT NOT NULL UNIQUE,
+                since INTEGER NOT NULL,
+                last_notified INTEGER NOT NULL,
+                message TEXT NOT NULL)"#,
+            (),
+        )?;
+
+        conn.execute(
+            r#" CREATE TABLE IF NOT EXISTS Faults (
+                id INTEGER PRIMARY KEY,
+                code TEXT NOT NULL,
+                device TEXT NOT NULL,
+                fault_type TEXT NOT NULL,
+                title TEXT NOT NULL,
+                description TEXT NOT NULL,
+                appeared INTEGER NOT NULL,
+                cleared INTEGER,
+                notified INTEGER)"#,
+            (),
+        )?;
+        // faults of older versions were notified when they appeared
+        add_missing_columns(&conn, "Faults", &[("notified", "INTEGER DEFAULT 0")])?;
+        // at most one open fault per code and device
+        let has_index: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'index' AND name = 'FaultsOpen';",
+            (),
+            |row| row.get(0),
+        )?;
+        if !has_index {
+            // older versions could record the same open fault twice, the first one is kept
+            let rows = conn.execute(
+                r#"DELETE FROM Faults WHERE cleared IS NULL AND id NOT IN
+                    (SELECT MIN(id) FROM Faults WHERE cleared IS NULL GROUP BY code, device)"#,
+                (),
+            )?;
+            if rows > 0 {
+                info!("Removed {} duplicate open faults.", rows);
+            }
+            conn.execute(
+                r#"CREATE UNIQUE INDEX FaultsOpen ON Faults (code, device)
+                    WHERE cleared IS NULL"#,
+                (),
+            )?;
+            info!("Added index FaultsOpen to table Faults.");
+        }
+
+        conn.execute(
+            r#" CREATE TABLE IF NOT EXISTS Device (
+                id INTEGER PRIMARY KEY,
+                device_id TEXT NOT NULL,
+                device_type TEXT NOT NULL,
+                marketing_name TEXT NOT NULL,
+                serial TEXT,
+                firmware TEXT,
+                gateway TEXT,
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL)"#,
+            (),
+        )?;
+
+        conn.execute(
+            r#" CREATE TABLE IF NOT EXISTS FirmwareChange (
+                id INTEGER PRIMARY KEY,
+                time INTEGER NOT NULL,
+                device_id TEXT NOT NULL,
+                marketing_name TEXT NOT NULL,
+                from_version TEXT,
+                to_version TEXT)"#,
+            (),
+        )?;
+
+        conn.execute(
+            r#" CREATE TABLE IF NOT EXISTS Backfill (
+                id INTEGER PRIMARY KEY,
+                day INTEGER NOT NULL UNIQUE,
+                reason TEXT NOT NULL,
+                queued INTEGER NOT NULL,
+                done INTEGER)"#,
             (),
         )?;
 
+        conn.execute(
+            r#" CREATE TABLE IF NOT EXISTS ExternalEnergy (
+                id INTEGER PRIMARY KEY,
+                time INTEGER NOT NULL,
+                source TEXT NOT NULL,
+                value REAL NOT NULL,
+                UNIQUE(time, source))"#,
+            (),
+        )?;
+
+        for bucket in [Bucket::Hourly, Bucket::Daily] {
+            let columns = SENSOR_CHANNELS
+                .iter()
+                .map(|c| {
+                    format!(
+                        "{c}_min REAL, {c}_max REAL, {c}_mean REAL, {c}_count INTEGER",
+                        c = c.column()
+                    )
+                })
+                .join(",\n");
+            conn.execute(
+                &format!(
+                    r#" CREATE TABLE IF NOT EXISTS {} (
+                    id INTEGER PRIMARY KEY,
+                    time INTEGER NOT NULL UNIQUE,
+                    samples INTEGER NOT NULL,
+                    {})"#,
+                    bucket.table(),
+                    columns
+                ),
+                (),
+            )?;
+            // air quality was added later
+            let names = [Channel::Co2, Channel::Humidity]
+                .iter()
+                .flat_map(|c| ["min", "max", "mean", "count"].map(|a| format!("{}_{}", c, a)))
+                .collect_vec();
+            let added = names
+                .iter()
+                .map(|n| {
+                    (
+                        n.as_str(),
+                        if n.ends_with("count") {
+                            "INTEGER"
+                        } else {
+                            "REAL"
+                        },
+                    )
+                })
+                .collect_vec();
+            add_missing_columns(&conn, bucket.table(), &added)?;
+        }
+
         Ok(DB { conn })
     }
 
+    /// Runs `f` in a transaction, which is rolled back if `f` fails
+    pub fn in_transaction<T>(
+        &self,
+        f: impl FnOnce() -> Result<T, anyhow::Error>,
+    ) -> Result<T, anyhow::Error> {
+        let tx = self.conn.unchecked_transaction()?;
+        let result = f()?;
+        tx.commit()?;
+        Ok(result)
+    }
+
     pub fn insert_sensor_data(&self, sensor_data: SensorData) -> Result<(), anyhow::Error> {
+        self.insert_sensor_data_at(chrono::Utc::now().naive_utc(), sensor_data)
+    }
+
+    /// Inserts sensor data with the given time in UTC instead of now
+    pub fn insert_sensor_data_at(
+        &self,
+        time: NaiveDateTime,
+        sensor_data: SensorData,
+    ) -> Result<(), anyhow::Error> {
         self.conn.execute(
-            r#"INSERT INTO Temperature (id, time, outdoor, hotwatertank, waterpressure, heatingcircuit)
-            VALUES (NULL, STRFTIME('%s'), ?1, ?2, ?3, ?4)"#,
-         (sensor_data.outdoor_temp,
+            r#"INSERT INTO Temperature (id, time, outdoor, hotwatertank, waterpressure, heatingcircuit, quickmode, co2, humidity)
+            VALUES (NULL, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"#,
+         (time.and_utc().timestamp(),
+            sensor_data.outdoor_temp,
             sensor_data.domestic_hot_water_tank_temperature,
             sensor_data.water_pressure_sensor,
-            sensor_data.flow_temperature_sensor))?;
+            sensor_data.flow_temperature_sensor,
+            sensor_data.quick_mode.map(|q| q.as_ref().to_owned()),
+            sensor_data.co2,
+            sensor_data.humidity))?;
 
         info!("Inserted Sensor Data into DB");
         Ok(())
     }
 
     pub fn get_sensor_data(&self, id: usize) -> Result<SensorData, anyhow::Error> {
-        let mut stmt = self.conn.prepare("SELECT outdoor, hotwatertank, waterpressure, heatingcircuit FROM Temperature WHERE id = :id;")?;
+        let mut stmt = self.conn.prepare("SELECT outdoor, hotwatertank, waterpressure, heatingcircuit, quickmode, co2, humidity FROM Temperature WHERE id = :id;")?;
 
         let mut data_iter = stmt.query_map(params![id], |row| {
-            Ok(SensorData::new_raw(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-            ))
+            Ok(
+                SensorData::new_raw(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)
+                    .with_quick_mode(parse_quick_mode(row.get(4)?))
+                    .with_air_quality(row.get(5)?, row.get(6)?),
+            )
         })?;
 
         if let Some(data) = data_iter.next() {
@@ -147,9 +791,9 @@ impl DB {
 
     pub fn insert_yp_data(&self, yp_data: &YpData) -> Result<(), anyhow::Error> {
         self.conn.execute(
-            r#"INSERT OR REPLACE INTO Usage (id, time, ch_hp_y, ch_hp_p, ch_bo_p, ch_yp, hw_hp_y, hw_hp_p, hw_bo_p, hw_yp, total_y, total_p, total_yp)
-            VALUES (NULL, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
-         (yp_data.ts.timestamp(),
+            r#"INSERT OR REPLACE INTO Usage (id, time, ch_hp_y, ch_hp_p, ch_bo_p, ch_yp, hw_hp_y, hw_hp_p, hw_bo_p, hw_yp, total_y, total_p, total_yp, co_hp_y, co_hp_p, co_eer, ve_p)
+            VALUES (NULL, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+         (yp_data.ts.and_utc().timestamp(),
             yp_data.ch_hp_y,
             yp_data.ch_hp_p,
             yp_data.ch_bo_p,
@@ -160,9 +804,994 @@ impl DB {
             yp_data.hw_yp,
             yp_data.total_y,
             yp_data.total_p,
-            yp_data.total_yp))?;
+            yp_data.total_yp,
+            yp_data.co_hp_y,
+            yp_data.co_hp_p,
+            yp_data.co_eer,
+            yp_data.ve_p))?;
 
         info!("Inserted YP Data into DB for day: {}", yp_data.ts);
         Ok(())
     }
+
+    /// Iterates over all sensor samples in `[from, to)` ordered by time
+    pub fn iter_sensor_samples(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> RangeIter<'_, SensorSample> {
+        RangeIter {
+            db: self,
+            fetch: fetch_sensor_page,
+            after: (from.and_utc().timestamp(), i64::MIN),
+            to: to.and_utc().timestamp(),
+            page: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+
+    /// All sensor samples in `[from, to)` ordered by time
+    pub fn sensor_samples(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<SensorSample>, anyhow::Error> {
+        let result = self
+            .iter_sensor_samples(from, to)
+            .collect::<Result<Vec<_>, _>>()?;
+        debug!("Found {} Sensor Samples.", result.len());
+        Ok(result)
+    }
+
+    /// Iterates over all days of usage in `[from, to)` ordered by time
+    pub fn iter_yp_data(&self, from: NaiveDateTime, to: NaiveDateTime) -> RangeIter<'_, YpData> {
+        RangeIter {
+            db: self,
+            fetch: fetch_yp_page,
+            after: (from.and_utc().timestamp(), i64::MIN),
+            to: to.and_utc().timestamp(),
+            page: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+
+    /// All days of usage in `[from, to)` ordered by time
+    pub fn get_yp_data(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<YpData>, anyhow::Error> {
+        let result = self.iter_yp_data(from, to).collect::<Result<Vec<_>, _>>()?;
+        debug!("Found {} days of YP Data.", result.len());
+        Ok(result)
+    }
+
+    /// Values of a channel in `[from, to)` ordered by time. Rows without a value are skipped.
+    pub fn channel_values(
+        &self,
+        channel: Channel,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<(NaiveDateTime, f64)>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT time, {c} FROM {t} WHERE time >= ?1 AND time < ?2 AND {c} IS NOT NULL ORDER BY time, id;",
+            c = channel.column(),
+            t = channel.table()
+        ))?;
+        let rows = stmt.query_map(
+            (from.and_utc().timestamp(), to.and_utc().timestamp()),
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?)),
+        )?;
+        collect_time_values(rows, channel.table())
+    }
+
+    /// Latest `n` values of a channel, newest first. Rows without a value are skipped.
+    pub fn latest_channel_values(
+        &self,
+        channel: Channel,
+        n: usize,
+    ) -> Result<Vec<(NaiveDateTime, f64)>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT time, {c} FROM {t} WHERE {c} IS NOT NULL ORDER BY time DESC, id DESC LIMIT ?1;",
+            c = channel.column(),
+            t = channel.table()
+        ))?;
+        let rows = stmt.query_map([n], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?))
+        })?;
+        collect_time_values(rows, channel.table())
+    }
+
+    /// Aggregates the raw sensor samples of every bucket that starts in `[from, to)`.
+    ///
+    /// Buckets with raw samples are replaced, so rolling up again is safe.
+    /// Buckets without raw samples are kept. Returns the number of rolled up buckets.
+    pub fn rollup(
+        &self,
+        bucket: Bucket,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<usize, anyhow::Error> {
+        let names = SENSOR_CHANNELS
+            .iter()
+            .map(|c| format!("{c}_min, {c}_max, {c}_mean, {c}_count", c = c.column()))
+            .join(", ");
+        let aggregates = SENSOR_CHANNELS
+            .iter()
+            .map(|c| format!("MIN({c}), MAX({c}), AVG({c}), COUNT({c})", c = c.column()))
+            .join(", ");
+        let seconds = bucket.seconds();
+        // align to whole buckets
+        let from = from.and_utc().timestamp().div_euclid(seconds) * seconds;
+        let to = to.and_utc().timestamp().div_euclid(seconds) * seconds;
+
+        let rows = self.conn.execute(
+            &format!(
+                r#"INSERT OR REPLACE INTO {table} (id, time, samples, {names})
+                SELECT NULL, (time / {seconds}) * {seconds} AS bucket, COUNT(*), {aggregates}
+                FROM Temperature WHERE time >= ?1 AND time < ?2 GROUP BY bucket"#,
+                table = bucket.table(),
+                names = names,
+                seconds = seconds,
+                aggregates = aggregates
+            ),
+            (from, to),
+        )?;
+        info!("Rolled up {} {} buckets.", rows, bucket);
+        Ok(rows)
+    }
+
+    /// Aggregates of a sensor channel for every bucket in `[from, to)` with a value
+    pub fn rollups(
+        &self,
+        bucket: Bucket,
+        channel: Channel,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<Rollup>, anyhow::Error> {
+        if channel.table() != "Temperature" {
+            return Err(anyhow!("{} is not a sensor channel.", channel));
+        }
+        let mut stmt = self.conn.prepare(&format!(
+            r#"SELECT time, {c}_min, {c}_max, {c}_mean, {c}_count FROM {t}
+            WHERE time >= ?1 AND time < ?2 AND {c}_count > 0 ORDER BY time;"#,
+            c = channel.column(),
+            t = bucket.table()
+        ))?;
+        let rows = stmt.query_map(
+            (from.and_utc().timestamp(), to.and_utc().timestamp()),
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, f64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            },
+        )?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            let (time, min, max, mean, count) = row?;
+            result.push(Rollup {
+                time: to_naive(time, bucket.table())?,
+                min,
+                max,
+                mean,
+                count,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Min, max, mean and count of the sensor channels for every bucket in `[from, to)`
+    pub fn rollup_rows(
+        &self,
+        bucket: Bucket,
+        channels: &[Channel],
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<RollupRow>, anyhow::Error> {
+        if let Some(c) = channels.iter().find(|c| c.table() != "Temperature") {
+            return Err(anyhow!("{} is not a sensor channel.", c));
+        }
+        let names = channels
+            .iter()
+            .map(|c| format!("{c}_min, {c}_max, {c}_mean, {c}_count", c = c.column()))
+            .join(", ");
+        let mut stmt = self.conn.prepare(&format!(
+            r#"SELECT time, {names} FROM {t}
+            WHERE time >= ?1 AND time < ?2 ORDER BY time;"#,
+            names = names,
+            t = bucket.table()
+        ))?;
+        let rows = stmt.query_map(
+            (from.and_utc().timestamp(), to.and_utc().timestamp()),
+            |row| {
+                let values = (1..=channels.len() * 4)
+                    .map(|i| row.get::<_, Option<f64>>(i))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((row.get::<_, i64>(0)?, values))
+            },
+        )?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            let (time, values) = row?;
+            result.push((to_naive(time, bucket.table())?, values));
+        }
+        Ok(result)
+    }
+
+    /// Start of the newest bucket, None if nothing was rolled up yet
+    pub fn latest_rollup(&self, bucket: Bucket) -> Result<Option<NaiveDateTime>, anyhow::Error> {
+        let time: Option<i64> = self.conn.query_row(
+            &format!("SELECT MAX(time) FROM {};", bucket.table()),
+            [],
+            |row| row.get(0),
+        )?;
+        time.map(|t| to_naive(t, bucket.table())).transpose()
+    }
+
+    /// Deletes all raw sensor samples before `time`. Returns the number of deleted rows.
+    pub fn delete_sensor_data_before(&self, time: NaiveDateTime) -> Result<usize, anyhow::Error> {
+        let rows = self.conn.execute(
+            "DELETE FROM Temperature WHERE time < ?1;",
+            [time.and_utc().timestamp()],
+        )?;
+        info!("Deleted {} raw sensor samples before {}.", rows, time);
+        Ok(rows)
+    }
+
+    /// Deletes all buckets before `time`. Returns the number of deleted rows.
+    pub fn delete_rollups_before(
+        &self,
+        bucket: Bucket,
+        time: NaiveDateTime,
+    ) -> Result<usize, anyhow::Error> {
+        let rows = self.conn.execute(
+            &format!("DELETE FROM {} WHERE time < ?1;", bucket.table()),
+            [time.and_utc().timestamp()],
+        )?;
+        info!("Deleted {} {} buckets before {}.", rows, bucket, time);
+        Ok(rows)
+    }
+
+    /// Latest value of every channel with a stored value
+    pub fn latest_values(&self) -> Result<Vec<LatestValue>, anyhow::Error> {
+        let mut result = Vec::new();
+        for channel in <Channel as strum::IntoEnumIterator>::iter() {
+            if let Some((time, value)) = self.latest_channel_values(channel, 1)?.pop() {
+                result.push(LatestValue {
+                    channel,
+                    time,
+                    value,
+                });
+            }
+        }
+        Ok(result)
+    }
+
+    /// Mean of a channel for every UTC day in `[from, to)` with a value
+    pub fn daily_means(
+        &self,
+        channel: Channel,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<(NaiveDateTime, f64)>, anyhow::Error> {
+        let mut days: BTreeMap<NaiveDateTime, (f64, usize)> = BTreeMap::new();
+        for (time, value) in self.channel_values(channel, from, to)? {
+            let day = time.date().and_hms_opt(0, 0, 0).ok_or(anyhow!(""))?;
+            let (sum, count) = days.entry(day).or_default();
+            *sum += value;
+            *count += 1;
+        }
+        Ok(days
+            .into_iter()
+            .map(|(day, (sum, count))| (day, sum / count as f64))
+            .collect())
+    }
+
+    /// Inserts daily costs. Costs for the same day are replaced.
+    pub fn insert_costs(&self, costs: &[DailyCost]) -> Result<(), anyhow::Error> {
+        let mut stmt = self.conn.prepare(
+            r#"INSERT OR REPLACE INTO Cost (id, time, ch_cost, hw_cost, bo_cost, co_cost, base_fee, total_cost)
+            VALUES (NULL, ?, ?, ?, ?, ?, ?, ?)"#,
+        )?;
+
+        for cost in costs {
+            stmt.execute((
+                cost.ts.and_utc().timestamp(),
+                cost.ch_cost,
+                cost.hw_cost,
+                cost.bo_cost,
+                cost.co_cost,
+                cost.base_fee,
+                cost.total_cost,
+            ))?;
+        }
+
+        info!("Inserted {} days of Costs into DB", costs.len());
+        Ok(())
+    }
+
+    /// Inserts the energy in Wh per interval, replacing intervals with the same start
+    pub fn insert_external_energy(
+        &self,
+        source: Source,
+        intervals: &[(NaiveDateTime, f64)],
+    ) -> Result<(), anyhow::Error> {
+        let mut stmt = self.conn.prepare(
+            "INSERT OR REPLACE INTO ExternalEnergy (id, time, source, value) VALUES (NULL, ?, ?, ?)",
+        )?;
+        for (time, value) in intervals {
+            stmt.execute((time.and_utc().timestamp(), source.as_ref(), value))?;
+        }
+        info!(
+            "Inserted {} intervals of {} into DB",
+            intervals.len(),
+            source
+        );
+        Ok(())
+    }
+
+    /// Energy per interval of the source starting in `[from, to)` ordered by time
+    pub fn external_energy(
+        &self,
+        source: Source,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<(NaiveDateTime, f64)>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT time, value FROM ExternalEnergy WHERE source = ?1 AND time >= ?2 AND time < ?3 ORDER BY time;",
+        )?;
+        let rows = stmt.query_map(
+            (
+                source.as_ref(),
+                from.and_utc().timestamp(),
+                to.and_utc().timestamp(),
+            ),
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?)),
+        )?;
+        collect_time_values(rows, "ExternalEnergy")
+    }
+
+    /// All daily costs in `[from, to)` ordered by time
+    pub fn get_costs(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<DailyCost>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(
+            r#"SELECT time, ch_cost, hw_cost, bo_cost, co_cost, base_fee, total_cost
+            FROM Cost WHERE time >= ?1 AND time < ?2 ORDER BY time;"#,
+        )?;
+        let rows = stmt.query_map(
+            (from.and_utc().timestamp(), to.and_utc().timestamp()),
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, f64>(3)?,
+                    row.get::<_, f64>(4)?,
+                    row.get::<_, f64>(5)?,
+                    row.get::<_, f64>(6)?,
+                ))
+            },
+        )?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            let (time, ch_cost, hw_cost, bo_cost, co_cost, base_fee, total_cost) = row?;
+            result.push(DailyCost {
+                ts: to_naive(time, "Cost")?,
+                ch_cost,
+                hw_cost,
+                bo_cost,
+                co_cost,
+                base_fee,
+                total_cost,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Inserts meter readings. A reading with the same device, energy type, function and `to` is replaced.
+    pub fn insert_meter_readings(&self, readings: &[MeterReading]) -> Result<(), anyhow::Error> {
+        let mut stmt = self.conn.prepare(
+            r#"INSERT OR REPLACE INTO MeterReading (id, time, device_id, device, energy_type, function, from_ts, to_ts, value)
+            VALUES (NULL, STRFTIME('%s'), ?, ?, ?, ?, ?, ?, ?)"#,
+        )?;
+
+        for reading in readings {
+            stmt.execute((
+                &reading.device_id,
+                reading.device.as_ref(),
+                reading.energy_type.as_ref(),
+                reading.function.as_ref(),
+                reading.from.and_utc().timestamp(),
+                reading.to.and_utc().timestamp(),
+                reading.value,
+            ))?;
+        }
+
+        info!("Inserted {} Meter Readings into DB", readings.len());
+        Ok(())
+    }
+
+    /// All stored meter readings ordered by `to`
+    pub fn get_meter_readings(&self) -> Result<Vec<MeterReading>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT device_id, device, energy_type, function, from_ts, to_ts, value FROM MeterReading ORDER BY to_ts;",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, f64>(6)?,
+            ))
+        })?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            let (device_id, device, energy_type, function, from, to, value) = row?;
+            result.push(MeterReading {
+                device_id,
+                device: device.parse()?,
+                energy_type: energy_type.parse()?,
+                function: function.parse()?,
+                from: to_naive(from, "MeterReading")?,
+                to: to_naive(to, "MeterReading")?,
+                value,
+            });
+        }
+        debug!("Found {} Meter Readings.", result.len());
+        Ok(result)
+    }
+
+    /// Start and last notification of an active alert
+    pub fn get_alert_state(
+        &self,
+        rule: &str,
+    ) -> Result<Option<(NaiveDateTime, NaiveDateTime)>, anyhow::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT since, last_notified FROM AlertState WHERE rule = ?1;")?;
+        let mut rows = stmt.query_map([rule], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+        })?;
+
+        match rows.next() {
+            Some(row) => {
+                let (since, last_notified) = row?;
+                Ok(Some((
+                    to_naive(since, "AlertState")?,
+                    to_naive(last_notified, "AlertState")?,
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Stores an active alert. The state of the same rule is replaced.
+    pub fn set_alert_state(
+        &self,
+        rule: &str,
+        since: NaiveDateTime,
+        last_notified: NaiveDateTime,
+        message: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            r#"INSERT OR REPLACE INTO AlertState (id, rule, since, last_notified, message)
+            VALUES (NULL, ?, ?, ?, ?)"#,
+            (
+                rule,
+                since.and_utc().timestamp(),
+                last_notified.and_utc().timestamp(),
+                message,
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Removes the state of a resolved alert. Returns true if the alert was active.
+    pub fn clear_alert_state(&self, rule: &str) -> Result<bool, anyhow::Error> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM AlertState WHERE rule = ?1;", [rule])?
+            > 0)
+    }
+
+    /// Stores a new open fault
+    pub fn insert_fault(&self, fault: &Fault) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            r#"INSERT INTO Faults (id, code, device, fault_type, title, description, appeared, cleared)
+            VALUES (NULL, ?, ?, ?, ?, ?, ?, ?)"#,
+            (
+                &fault.code,
+                &fault.device,
+                fault.fault_type.as_str(),
+                &fault.title,
+                &fault.description,
+                fault.appeared.and_utc().timestamp(),
+                fault.cleared.map(|t| t.and_utc().timestamp()),
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Sets the cleared time of the open fault with the same code and device
+    pub fn clear_fault(&self, fault: &Fault, cleared: NaiveDateTime) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            "UPDATE Faults SET cleared = ?1 WHERE code = ?2 AND device = ?3 AND cleared IS NULL;",
+            (cleared.and_utc().timestamp(), &fault.code, &fault.device),
+        )?;
+        Ok(())
+    }
+
+    /// Marks the open fault with the same code and device as sent to the sinks
+    pub fn set_fault_notified(
+        &self,
+        fault: &Fault,
+        notified: NaiveDateTime,
+    ) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            "UPDATE Faults SET notified = ?1 WHERE code = ?2 AND device = ?3 AND cleared IS NULL;",
+            (notified.and_utc().timestamp(), &fault.code, &fault.device),
+        )?;
+        Ok(())
+    }
+
+    /// Faults in the order they were recorded, only the ones not cleared yet if `open_only`
+    pub fn get_faults(&self, open_only: bool) -> Result<Vec<Fault>, anyhow::Error> {
+        self.query_faults(if open_only { "cleared IS NULL" } else { "TRUE" })
+    }
+
+    /// Open faults that no sink accepted yet
+    pub fn get_unnotified_faults(&self) -> Result<Vec<Fault>, anyhow::Error> {
+        self.query_faults("cleared IS NULL AND notified IS NULL")
+    }
+
+    fn query_faults(&self, condition: &str) -> Result<Vec<Fault>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            r#"SELECT code, device, fault_type, title, description, appeared, cleared FROM Faults
+            WHERE {} ORDER BY id;"#,
+            condition
+        ))?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                Fault {
+                    code: row.get(0)?,
+                    device: row.get(1)?,
+                    fault_type: row.get::<_, String>(2)?.into(),
+                    title: row.get(3)?,
+                    description: row.get(4)?,
+                    appeared: NaiveDateTime::default(),
+                    cleared: None,
+                },
+                row.get::<_, i64>(5)?,
+                row.get::<_, Option<i64>>(6)?,
+            ))
+        })?;
+
+        rows.map(|row| {
+            let (fault, appeared, cleared) = row?;
+            Ok(Fault {
+                appeared: to_naive(appeared, "Faults")?,
+                cleared: cleared.map(|t| to_naive(t, "Faults")).transpose()?,
+                ..fault
+            })
+        })
+        .collect()
+    }
+
+    /// Stores a new version of a device
+    pub fn insert_device(&self, device: &DeviceInfo) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            r#"INSERT INTO Device (id, device_id, device_type, marketing_name, serial, firmware, gateway, first_seen, last_seen)
+            VALUES (NULL, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+            (
+                &device.id,
+                &device.device_type,
+                &device.marketing_name,
+                &device.serial,
+                &device.firmware,
+                &device.gateway,
+                device.first_seen.and_utc().timestamp(),
+                device.last_seen.and_utc().timestamp(),
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Sets the last seen time of the latest version of a device
+    pub fn touch_device(
+        &self,
+        device_id: &str,
+        last_seen: NaiveDateTime,
+    ) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            "UPDATE Device SET last_seen = ?1 WHERE id = (SELECT MAX(id) FROM Device WHERE device_id = ?2);",
+            (last_seen.and_utc().timestamp(), device_id),
+        )?;
+        Ok(())
+    }
+
+    /// Latest version of every device
+    pub fn get_devices(&self) -> Result<Vec<DeviceInfo>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(
+            r#"SELECT device_id, device_type, marketing_name, serial, firmware, gateway, first_seen, last_seen
+            FROM Device WHERE id IN (SELECT MAX(id) FROM Device GROUP BY device_id) ORDER BY device_id;"#,
+        )?;
+        let rows = stmt.query_map((), |row| {
+            Ok((
+                DeviceInfo {
+                    id: row.get(0)?,
+                    device_type: row.get(1)?,
+                    marketing_name: row.get(2)?,
+                    serial: row.get(3)?,
+                    firmware: row.get(4)?,
+                    gateway: row.get(5)?,
+                    first_seen: NaiveDateTime::default(),
+                    last_seen: NaiveDateTime::default(),
+                },
+                row.get::<_, i64>(6)?,
+                row.get::<_, i64>(7)?,
+            ))
+        })?;
+
+        rows.map(|row| {
+            let (device, first_seen, last_seen) = row?;
+            Ok(DeviceInfo {
+                first_seen: to_naive(first_seen, "Device")?,
+                last_seen: to_naive(last_seen, "Device")?,
+                ..device
+            })
+        })
+        .collect()
+    }
+
+    pub fn insert_firmware_change(&self, change: &FirmwareChange) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            r#"INSERT INTO FirmwareChange (id, time, device_id, marketing_name, from_version, to_version)
+            VALUES (NULL, ?, ?, ?, ?, ?)"#,
+            (
+                change.time.and_utc().timestamp(),
+                &change.device_id,
+                &change.marketing_name,
+                &change.from,
+                &change.to,
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// All firmware changes ordered by time
+    pub fn get_firmware_changes(&self) -> Result<Vec<FirmwareChange>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(
+            r#"SELECT time, device_id, marketing_name, from_version, to_version
+            FROM FirmwareChange ORDER BY time, id;"#,
+        )?;
+        let rows = stmt.query_map((), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                FirmwareChange {
+                    time: NaiveDateTime::default(),
+                    device_id: row.get(1)?,
+                    marketing_name: row.get(2)?,
+                    from: row.get(3)?,
+                    to: row.get(4)?,
+                },
+            ))
+        })?;
+
+        rows.map(|row| {
+            let (time, change) = row?;
+            Ok(FirmwareChange {
+                time: to_naive(time, "FirmwareChange")?,
+                ..change
+            })
+        })
+        .collect()
+    }
+
+    /// Timestamps of `table` in `[from, to)` that are stored more than once, with their count.
+    ///
+    /// `table` is not escaped and must be a valid table name.
+    pub fn duplicate_times(
+        &self,
+        table: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<(NaiveDateTime, i64)>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            r#"SELECT time, COUNT(*) FROM {} WHERE time >= ?1 AND time < ?2
+            GROUP BY time HAVING COUNT(*) > 1 ORDER BY time;"#,
+            table
+        ))?;
+        let rows = stmt.query_map(
+            (from.and_utc().timestamp(), to.and_utc().timestamp()),
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        )?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            let (time, count) = row?;
+            result.push((to_naive(time, table)?, count));
+        }
+        Ok(result)
+    }
+
+    /// Queues a day of usage to be retrieved again. Returns false if the day is already queued.
+    pub fn queue_backfill(&self, day: NaiveDateTime, reason: &str) -> Result<bool, anyhow::Error> {
+        Ok(self.conn.execute(
+            r#"INSERT OR IGNORE INTO Backfill (id, day, reason, queued, done)
+            VALUES (NULL, ?, ?, STRFTIME('%s'), NULL)"#,
+            (day.and_utc().timestamp(), reason),
+        )? > 0)
+    }
+
+    /// Days queued for backfill that are not done, ordered by day
+    pub fn pending_backfills(&self) -> Result<Vec<NaiveDateTime>, anyhow::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT day FROM Backfill WHERE done IS NULL ORDER BY day;")?;
+        let rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            result.push(to_naive(row?, "Backfill")?);
+        }
+        Ok(result)
+    }
+
+    /// Marks a queued day as done
+    pub fn finish_backfill(&self, day: NaiveDateTime) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            "UPDATE Backfill SET done = STRFTIME('%s') WHERE day = ?1;",
+            [day.and_utc().timestamp()],
+        )?;
+        info!("Backfilled usage for day: {}", day);
+        Ok(())
+    }
+}
+
+// converts rows of unix timestamp and value
+fn collect_time_values(
+    rows: impl Iterator<Item = rusqlite::Result<(i64, f64)>>,
+    table: &str,
+) -> Result<Vec<(NaiveDateTime, f64)>, anyhow::Error> {
+    let mut result = Vec::new();
+    for row in rows {
+        let (time, value) = row?;
+        result.push((to_naive(time, table)?, value));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+    use rusqlite::Connection;
+
+    use crate::{response::system::QuickMode, yp::YpData};
+
+    use super::{Channel, SensorData, DB, PAGE_SIZE};
+
+    fn time(minute: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + Duration::minutes(minute)
+    }
+
+    #[test]
+    fn query_api() {
+        let db = DB::new(None).unwrap();
+        // more than one page, two samples per minute
+        let n = PAGE_SIZE as i64 + 10;
+        for minute in 0..n {
+            let value = minute as f64;
+            for _ in 0..2 {
+                db.insert_sensor_data_at(
+                    time(minute),
+                    SensorData::new_raw(Some(value), None, Some(1.5), None),
+                )
+                .unwrap();
+            }
+        }
+        db.insert_yp_data(&YpData {
+            ts: time(0),
+            ch_hp_y: 1000.0,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let samples: Vec<_> = db
+            .iter_sensor_samples(time(0), time(n))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(2 * n as usize, samples.len());
+        assert!(samples.windows(2).all(|w| w[0].time <= w[1].time));
+        assert_eq!(Some(5.0), samples[10].data.value(Channel::Outdoor));
+        assert_eq!(None, samples[10].data.value(Channel::Hotwatertank));
+
+        // range is [from, to)
+        assert_eq!(4, db.sensor_samples(time(1), time(3)).unwrap().len());
+
+        let yp = db.get_yp_data(time(0), time(1)).unwrap();
+        assert_eq!(time(0), yp[0].ts);
+        assert_eq!(Some(1000.0), yp[0].value(Channel::ChHpY));
+
+        let latest = db.latest_values().unwrap();
+        let channels: Vec<Channel> = latest.iter().map(|l| l.channel).collect();
+        // the inserted sensor channels and all usage columns, they are never NULL
+        let expected: Vec<Channel> = <Channel as strum::IntoEnumIterator>::iter()
+            .filter(|c| {
+                matches!(c, Channel::Outdoor | Channel::Waterpressure) || c.table() == "Usage"
+            })
+            .collect();
+        assert_eq!(expected, channels);
+        assert_eq!(time(n - 1), latest[0].time);
+        assert_eq!((n - 1) as f64, latest[0].value);
+
+        let means = db
+            .daily_means(Channel::Waterpressure, time(0), time(n))
+            .unwrap();
+        assert_eq!(vec![(time(0), 1.5)], means);
+    }
+
+    #[test]
+    fn quick_mode() {
+        let db = DB::new(None).unwrap();
+        let party = SensorData::new_raw(Some(5.0), None, None, None)
+            .with_quick_mode(Some(QuickMode::QmParty));
+        db.insert_sensor_data_at(time(0), party.clone()).unwrap();
+        db.insert_sensor_data_at(time(1), SensorData::new_raw(Some(5.0), None, None, None))
+            .unwrap();
+        db.conn
+            .execute(
+                "INSERT INTO Temperature (time, quickmode) VALUES (?1, 'QM_UNKNOWN')",
+                [time(2).and_utc().timestamp()],
+            )
+            .unwrap();
+
+        let samples = db.sensor_samples(time(0), time(3)).unwrap();
+        assert_eq!(party, samples[0].data);
+        assert_eq!(None, samples[1].data.quick_mode());
+        // unknown to this version, kept as stored
+        assert_eq!(
+            Some(QuickMode::Unknown("QM_UNKNOWN".into())),
+            samples[2].data.quick_mode()
+        );
+        assert_eq!(
+            Some(QuickMode::QmParty),
+            db.get_sensor_data(1).unwrap().quick_mode()
+        );
+    }
+
+    #[test]
+    fn migrate_usage_table() {
+        // unique per test run, parallel runs don't share the file
+        let path = std::env::temp_dir().join(format!(
+            "senso_migrate_usage_table_{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        // Usage table without cooling columns
+        let conn = Connection::open(&path).unwrap();
+        conn.execute(
+            r#" CREATE TABLE Usage (
+                id INTEGER PRIMARY KEY,
+                time INTEGER NOT NULL UNIQUE,
+                ch_hp_y INTEGER,
+                ch_hp_p INTEGER,
+                ch_bo_p INTEGER,
+                ch_yp REAL,
+                hw_hp_y INTEGER,
+                hw_hp_p INTEGER,
+                hw_bo_p INTEGER,
+                hw_yp REAL,
+                total_y INTEGER,
+                total_p INTEGER,
+                total_yp REAL)"#,
+            (),
+        )
+        .unwrap();
+        drop(conn);
+
+        // open twice, second time nothing to migrate
+        DB::new(path.to_str()).unwrap();
+        let db = DB::new(path.to_str()).unwrap();
+        let columns: i64 = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('Usage') WHERE name LIKE 'co_%';",
+                (),
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(3, columns);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn migrate_faults_table() {
+        let path = std::env::temp_dir().join(format!(
+            "senso_migrate_faults_table_{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        // Faults table without index and notified column, the open fault is recorded twice
+        let conn = Connection::open(&path).unwrap();
+        conn.execute(
+            r#" CREATE TABLE Faults (
+                id INTEGER PRIMARY KEY,
+                code TEXT NOT NULL,
+                device TEXT NOT NULL,
+                fault_type TEXT NOT NULL,
+                title TEXT NOT NULL,
+                description TEXT NOT NULL,
+                appeared INTEGER NOT NULL,
+                cleared INTEGER)"#,
+            (),
+        )
+        .unwrap();
+        for cleared in [Some(1), None, None] {
+            conn.execute(
+                "INSERT INTO Faults VALUES (NULL, 'F.28', 'VWL', 'ERROR', 't', 'd', 0, ?1)",
+                [cleared],
+            )
+            .unwrap();
+        }
+        drop(conn);
+
+        DB::new(path.to_str()).unwrap();
+        let db = DB::new(path.to_str()).unwrap();
+        assert_eq!(1, db.get_faults(true).unwrap().len());
+        assert_eq!(2, db.get_faults(false).unwrap().len());
+        // already sent by the older version
+        assert!(db.get_unnotified_faults().unwrap().is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn sensor_data_units() {
+        let live_report: crate::response::live_report::Root = serde_json::from_str(
+            r#"{"body": {"devices": [
+                {"_id": "Control_DHW", "name": "DHW", "reports": [
+                    {"_id": "DomesticHotWaterTankTemperature", "name": "Tank",
+                     "value": 122.0, "unit": "°F", "measurement_category": "TEMPERATURE"}]},
+                {"_id": "Control_SYS_senso", "name": "senso", "reports": [
+                    {"_id": "WaterPressureSensor", "name": "Pressure",
+                     "value": 150.0, "unit": "kPa", "measurement_category": "PRESSURE"},
+                    {"_id": "Co2", "name": "Co2",
+                     "value": 800.0, "unit": "ppb", "measurement_category": "AIR_QUALITY"}]},
+                {"_id": "Control_CC1", "name": "CC1", "reports": [
+                    {"_id": "FlowTemperatureSensor", "name": "Flow",
+                     "value": 1.2, "unit": "bar", "measurement_category": "TEMPERATURE"}]}
+            ]}, "meta": {"resourceState": []}}"#,
+        )
+        .unwrap();
+        let data = SensorData::new(&Err(()), &Ok(live_report));
+
+        assert_eq!(Some(50.0), data.value(Channel::Hotwatertank));
+        assert_eq!(Some(1.5), data.value(Channel::Waterpressure));
+        assert_eq!(Some(150.0), data.water_pressure().map(|p| p.kpa()));
+        // unknown unit and unit of another dimension are ignored
+        assert_eq!(None, data.co2());
+        assert_eq!(None, data.flow_temperature());
+    }
 }
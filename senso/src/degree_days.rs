@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use cli_table::Table;
+
+use crate::{
+    db::{Channel, DB},
+    spf::Period,
+};
+
+/// Default base temperature in °C. Days with a mean outdoor temperature below it need heating.
+pub const DEFAULT_BASE_TEMPERATURE: f64 = 15.0;
+
+/// Heating degrees of one day with the given mean outdoor temperature
+pub fn heating_degrees(mean_outdoor: f64, base_temperature: f64) -> f64 {
+    (base_temperature - mean_outdoor).max(0.0)
+}
+
+/// Heating energy of one day with its heating degree days
+#[derive(Debug, PartialEq, Table)]
+pub struct DegreeDay {
+    #[table(title = "day")]
+    pub day: NaiveDateTime,
+    /// mean of all outdoor samples of the day in °C
+    #[table(title = "mean outdoor")]
+    pub mean_outdoor: f64,
+    #[table(title = "hdd")]
+    pub hdd: f64,
+    /// power usage of heat pump and boiler for central heating
+    #[table(title = "ch_p")]
+    pub ch_p: f64,
+}
+
+/// Heating energy normalized by the heating degree days of a period
+#[derive(Debug, PartialEq, Table)]
+pub struct Normalized {
+    #[table(title = "from")]
+    pub from: NaiveDateTime,
+    #[table(title = "to")]
+    pub to: NaiveDateTime,
+    /// days with outdoor temperature and usage
+    #[table(title = "days")]
+    pub days: usize,
+    #[table(title = "hdd")]
+    pub hdd: f64,
+    #[table(title = "ch_p")]
+    pub ch_p: f64,
+    /// 0.0 if there are no heating degree days
+    #[table(title = "ch_p/hdd")]
+    pub ch_p_per_hdd: f64,
+}
+
+/// Weather adjusted comparison of a period with a reference period
+#[derive(Debug, PartialEq, Table)]
+pub struct Comparison {
+    #[table(title = "reference from")]
+    pub reference_from: NaiveDateTime,
+    #[table(title = "from")]
+    pub from: NaiveDateTime,
+    #[table(title = "reference hdd")]
+    pub reference_hdd: f64,
+    #[table(title = "hdd")]
+    pub hdd: f64,
+    #[table(title = "reference ch_p")]
+    pub reference_ch_p: f64,
+    #[table(title = "ch_p")]
+    pub ch_p: f64,
+    /// ch_p with the weather of the reference period
+    #[table(title = "adjusted ch_p")]
+    pub adjusted_ch_p: f64,
+    /// change of the weather adjusted heating energy in percent, negative is better
+    #[table(title = "change %")]
+    pub change_percent: f64,
+}
+
+/// Heating degree days and heating energy for every day of the period with outdoor samples and usage
+pub fn daily(db: &DB, period: Period, base_temperature: f64) -> anyhow::Result<Vec<DegreeDay>> {
+    let (from, to) = period.range()?;
+
+    let ch_p: HashMap<NaiveDateTime, f64> = db
+        .get_yp_data(from, to)?
+        .iter()
+        .map(|u| (u.ts, u.ch_hp_p + u.ch_bo_p))
+        .collect();
+
+    Ok(db
+        .daily_means(Channel::Outdoor, from, to)?
+        .into_iter()
+        .filter_map(|(day, mean_outdoor)| {
+            Some(DegreeDay {
+                day,
+                mean_outdoor,
+                hdd: heating_degrees(mean_outdoor, base_temperature),
+                ch_p: *ch_p.get(&day)?,
+            })
+        })
+        .collect())
+}
+
+/// Heating energy per heating degree day over the period
+pub fn normalize(db: &DB, period: Period, base_temperature: f64) -> anyhow::Result<Normalized> {
+    let (from, to) = period.range()?;
+    let days = daily(db, period, base_temperature)?;
+
+    let hdd: f64 = days.iter().map(|d| d.hdd).sum();
+    let ch_p: f64 = days.iter().map(|d| d.ch_p).sum();
+
+    Ok(Normalized {
+        from,
+        to,
+        days: days.len(),
+        hdd,
+        ch_p,
+        ch_p_per_hdd: if hdd == 0.0 { 0.0 } else { ch_p / hdd },
+    })
+}
+
+/// Compare the heating energy of a period with a reference period, adjusted for the weather
+pub fn compare(
+    db: &DB,
+    reference: Period,
+    period: Period,
+    base_temperature: f64,
+) -> anyhow::Result<Comparison> {
+    let r = normalize(db, reference, base_temperature)?;
+    let p = normalize(db, period, base_temperature)?;
+
+    let adjusted_ch_p = p.ch_p_per_hdd * r.hdd;
+    let change_percent = if r.ch_p_per_hdd == 0.0 {
+        0.0
+    } else {
+        (((p.ch_p_per_hdd / r.ch_p_per_hdd) - 1.0) * 10000.0).round() / 100.0
+    };
+
+    Ok(Comparison {
+        reference_from: r.from,
+        from: p.from,
+        reference_hdd: r.hdd,
+        hdd: p.hdd,
+        reference_ch_p: r.ch_p,
+        ch_p: p.ch_p,
+        adjusted_ch_p,
+        change_percent,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use crate::{
+        db::{SensorData, DB},
+        spf::Period,
+        yp::YpData,
+    };
+
+    use super::{compare, heating_degrees, normalize};
+
+    fn day(y: i32, d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, 1, d)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn degree_days() {
+        assert_eq!(10.0, heating_degrees(5.0, 15.0));
+        assert_eq!(0.0, heating_degrees(20.0, 15.0));
+
+        let db = DB::new(None).unwrap();
+        // 2022: 10 hdd per day, 2023: 5 hdd per day
+        for (year, outdoor, ch_hp_p) in [(2022, [4.0, 6.0], 10000.0), (2023, [9.0, 11.0], 4000.0)] {
+            for d in 1..=2 {
+                for (hour, o) in outdoor.iter().enumerate() {
+                    db.insert_sensor_data_at(
+                        day(year, d) + chrono::Duration::hours(hour as i64),
+                        SensorData::new_raw(Some(*o), None, None, None),
+                    )
+                    .unwrap();
+                }
+                db.insert_yp_data(&YpData {
+                    ts: day(year, d),
+                    ch_hp_p,
+                    ch_bo_p: 1000.0,
+                    ..Default::default()
+                })
+                .unwrap();
+            }
+        }
+
+        let n = normalize(&db, Period::Year { year: 2022 }, 15.0).unwrap();
+        assert_eq!(2, n.days);
+        assert_eq!(20.0, n.hdd);
+        assert_eq!(22000.0, n.ch_p);
+        assert_eq!(1100.0, n.ch_p_per_hdd);
+
+        let c = compare(
+            &db,
+            Period::Year { year: 2022 },
+            Period::Year { year: 2023 },
+            15.0,
+        )
+        .unwrap();
+        // 1000 per hdd in 2023
+        assert_eq!(20000.0, c.adjusted_ch_p);
+        assert_eq!(-9.09, c.change_percent);
+    }
+}
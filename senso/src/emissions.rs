@@ -0,0 +1,295 @@
+use std::{collections::BTreeMap, fs};
+
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use cli_table::Table;
+
+use crate::{db::DB, spf::Period, yp::YpData};
+
+/// Emissions in g CO2 per kWh of burned natural gas
+pub const DEFAULT_GAS_FACTOR: f64 = 201.0;
+/// Share of the burned gas a gas boiler turns into heat
+pub const DEFAULT_GAS_BOILER_EFFICIENCY: f64 = 0.9;
+
+/// Emission factors of the grid in g CO2 per kWh
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmissionFactors {
+    Constant(f64),
+    /// Hourly or daily factors, each valid until the next one
+    Series(BTreeMap<NaiveDateTime, f64>),
+}
+
+impl EmissionFactors {
+    /// Reads a series from a csv file with the columns `time,g_per_kwh`.
+    ///
+    /// time is either a day `2023-01-31` or an hour `2023-01-31 13:00`.
+    /// A header line and empty lines are skipped.
+    pub fn from_csv(path: &str) -> anyhow::Result<Self> {
+        Self::parse_csv(&fs::read_to_string(path)?)
+    }
+
+    fn parse_csv(content: &str) -> anyhow::Result<Self> {
+        let mut series = BTreeMap::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (time, factor) = line
+                .split_once(',')
+                .ok_or(anyhow!("Missing ',' in line {}.", i + 1))?;
+            let time = match parse_time(time.trim()) {
+                Some(t) => t,
+                // header
+                None if i == 0 => continue,
+                None => return Err(anyhow!("Invalid time \"{}\" in line {}.", time, i + 1)),
+            };
+            series.insert(time, factor.trim().parse::<f64>()?);
+        }
+        if series.is_empty() {
+            return Err(anyhow!("No emission factors in csv."));
+        }
+        Ok(Self::Series(series))
+    }
+
+    /// Mean factor of the day.
+    ///
+    /// Only daily usage is known, so hourly factors are averaged assuming the usage is spread evenly over the day.
+    pub fn daily_factor(&self, day: NaiveDate) -> anyhow::Result<f64> {
+        match self {
+            EmissionFactors::Constant(factor) => Ok(*factor),
+            EmissionFactors::Series(series) => {
+                let from = day.and_hms_opt(0, 0, 0).ok_or(anyhow!(""))?;
+                let to = from + Duration::days(1);
+                // factor valid at the start of the day
+                let start = series
+                    .range(..=from)
+                    .next_back()
+                    .map(|(_, f)| (from, *f))
+                    .into_iter();
+                let changes: Vec<(NaiveDateTime, f64)> = start
+                    .chain(series.range(from..to).map(|(t, f)| (*t, *f)))
+                    .collect();
+                if changes.is_empty() {
+                    return Err(anyhow!("No emission factor for {}.", day));
+                }
+
+                // weighted by the time each factor is valid
+                let mut sum = 0.0;
+                let mut seconds = 0;
+                for (i, (time, factor)) in changes.iter().enumerate() {
+                    let end = changes.get(i + 1).map_or(to, |(t, _)| *t);
+                    let valid = (end - *time).num_seconds();
+                    sum += factor * valid as f64;
+                    seconds += valid;
+                }
+                Ok(sum / seconds as f64)
+            }
+        }
+    }
+}
+
+pub(crate) fn parse_time(s: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+}
+
+/// Gas boiler that would provide the same heat
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GasBoiler {
+    /// g CO2 per kWh of burned gas
+    pub factor: f64,
+    pub efficiency: f64,
+}
+
+impl Default for GasBoiler {
+    fn default() -> Self {
+        Self {
+            factor: DEFAULT_GAS_FACTOR,
+            efficiency: DEFAULT_GAS_BOILER_EFFICIENCY,
+        }
+    }
+}
+
+/// Emissions of one day in kg CO2
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct DailyEmissions {
+    #[table(title = "ts")]
+    pub ts: NaiveDateTime,
+    /// consumed power of heat pump and boiler in Wh
+    #[table(title = "power")]
+    pub power: f64,
+    /// delivered heat in Wh
+    #[table(title = "heat")]
+    pub heat: f64,
+    /// g CO2 per kWh
+    #[table(title = "factor")]
+    pub factor: f64,
+    #[table(title = "emissions")]
+    pub emissions: f64,
+    /// emissions of a gas boiler for the same heat
+    #[table(title = "gas boiler")]
+    pub gas_boiler: f64,
+    #[table(title = "avoided")]
+    pub avoided: f64,
+}
+
+impl DailyEmissions {
+    pub fn new(
+        yp_data: &YpData,
+        factors: &EmissionFactors,
+        gas: &GasBoiler,
+    ) -> anyhow::Result<Self> {
+        let factor = factors.daily_factor(yp_data.ts.date())?;
+        let bo_p = yp_data.ch_bo_p + yp_data.hw_bo_p;
+        let power = yp_data.ch_hp_p + yp_data.hw_hp_p + bo_p;
+        // the boiler turns power 1:1 into heat
+        let heat = yp_data.ch_hp_y + yp_data.ch_hp_p + yp_data.hw_hp_y + yp_data.hw_hp_p + bo_p;
+        // Wh * g/kWh = mg, round with precision of 4 places after 0.0000
+        let kg = |mg: f64| ((mg / 1_000_000.0) * 10000.0).round() / 10000.0;
+        let emissions = kg(power * factor);
+        let gas_boiler = kg(heat / gas.efficiency * gas.factor);
+
+        Ok(Self {
+            ts: yp_data.ts,
+            power,
+            heat,
+            factor,
+            emissions,
+            gas_boiler,
+            avoided: ((gas_boiler - emissions) * 10000.0).round() / 10000.0,
+        })
+    }
+}
+
+/// Summed emissions of a period in kg CO2
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Emissions {
+    #[table(title = "from")]
+    pub from: NaiveDateTime,
+    #[table(title = "to")]
+    pub to: NaiveDateTime,
+    #[table(title = "days")]
+    pub days: usize,
+    #[table(title = "power")]
+    pub power: f64,
+    #[table(title = "heat")]
+    pub heat: f64,
+    #[table(title = "emissions")]
+    pub emissions: f64,
+    #[table(title = "gas boiler")]
+    pub gas_boiler: f64,
+    #[table(title = "avoided")]
+    pub avoided: f64,
+}
+
+/// Emissions for every stored day of usage in the period
+pub fn daily(
+    db: &DB,
+    period: Period,
+    factors: &EmissionFactors,
+    gas: &GasBoiler,
+) -> anyhow::Result<Vec<DailyEmissions>> {
+    let (from, to) = period.range()?;
+    db.get_yp_data(from, to)?
+        .iter()
+        .map(|yp_data| DailyEmissions::new(yp_data, factors, gas))
+        .collect()
+}
+
+/// Sum of the emissions in the period
+pub fn aggregate(
+    db: &DB,
+    period: Period,
+    factors: &EmissionFactors,
+    gas: &GasBoiler,
+) -> anyhow::Result<Emissions> {
+    let (from, to) = period.range()?;
+    let days = daily(db, period, factors, gas)?;
+    let sum = |f: fn(&DailyEmissions) -> f64| days.iter().map(f).sum();
+
+    Ok(Emissions {
+        from,
+        to,
+        days: days.len(),
+        power: sum(|d| d.power),
+        heat: sum(|d| d.heat),
+        emissions: sum(|d| d.emissions),
+        gas_boiler: sum(|d| d.gas_boiler),
+        avoided: sum(|d| d.avoided),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use crate::{db::DB, spf::Period, yp::YpData};
+
+    use super::{aggregate, EmissionFactors, GasBoiler};
+
+    fn day(d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 1, d)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn factor_series() {
+        let factors = EmissionFactors::parse_csv(
+            "time,g_per_kwh\n2023-01-01,400\n2023-01-02 00:00,300\n2023-01-02 12:00,500\n",
+        )
+        .unwrap();
+        assert!(factors
+            .daily_factor(NaiveDate::from_ymd_opt(2022, 12, 31).unwrap())
+            .is_err());
+        assert_eq!(400.0, factors.daily_factor(day(1).date()).unwrap());
+        assert_eq!(400.0, factors.daily_factor(day(2).date()).unwrap());
+        // last factor stays valid
+        assert_eq!(500.0, factors.daily_factor(day(3).date()).unwrap());
+
+        assert!(EmissionFactors::parse_csv("time,g_per_kwh\n2023-01-01;400\n").is_err());
+        assert!(EmissionFactors::parse_csv("time,g_per_kwh\n").is_err());
+    }
+
+    #[test]
+    fn avoided_emissions() {
+        let db = DB::new(None).unwrap();
+        for d in 1..=2 {
+            db.insert_yp_data(&YpData {
+                ts: day(d),
+                ch_hp_y: 6000.0,
+                ch_hp_p: 2000.0,
+                hw_bo_p: 1000.0,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        let gas = GasBoiler {
+            factor: 180.0,
+            efficiency: 0.9,
+        };
+        let result = aggregate(
+            &db,
+            Period::Year { year: 2023 },
+            &EmissionFactors::Constant(400.0),
+            &gas,
+        )
+        .unwrap();
+        assert_eq!(2, result.days);
+        assert_eq!(18000.0, result.heat);
+        // 6 kWh * 400 g
+        assert_eq!(2.4, result.emissions);
+        // 18 kWh / 0.9 * 180 g
+        assert_eq!(3.6, result.gas_boiler);
+        assert_eq!(1.2, result.avoided);
+    }
+}
@@ -0,0 +1,497 @@
+use std::{io::Write, sync::Arc};
+
+use anyhow::anyhow;
+use chrono::{NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use parquet::{
+    basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType},
+    data_type::{DoubleType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::types::Type,
+};
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, Display, EnumString};
+
+use crate::{
+    db::{Bucket, Channel, DB},
+    units::{Dimension, UnitSet},
+};
+
+/// Rows per parquet row group
+const ROW_GROUP_SIZE: usize = 10_000;
+
+/// Tables that can be exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum ExportTable {
+    Temperature,
+    Usage,
+    /// min, max, mean and count of the sensor channels per hour
+    TemperatureHourly,
+    /// min, max, mean and count of the sensor channels per day
+    TemperatureDaily,
+}
+
+impl ExportTable {
+    /// Name of the table in the database
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportTable::Temperature => "Temperature",
+            ExportTable::Usage => "Usage",
+            ExportTable::TemperatureHourly => Bucket::Hourly.table(),
+            ExportTable::TemperatureDaily => Bucket::Daily.table(),
+        }
+    }
+
+    /// Table the channels of this table are stored in
+    fn channel_table(&self) -> &'static str {
+        match self {
+            ExportTable::Usage => "Usage",
+            _ => "Temperature",
+        }
+    }
+
+    /// All channels of the table
+    pub fn channels(&self) -> Vec<Channel> {
+        Channel::iter()
+            .filter(|c| c.table() == self.channel_table())
+            .collect()
+    }
+
+    // name and dimension of the exported columns of the channels, counts have no dimension
+    fn columns(&self, channels: &[Channel]) -> Vec<(String, Option<Dimension>)> {
+        match self {
+            ExportTable::Temperature | ExportTable::Usage => channels
+                .iter()
+                .map(|c| (c.column().to_string(), Some(c.dimension())))
+                .collect(),
+            ExportTable::TemperatureHourly | ExportTable::TemperatureDaily => channels
+                .iter()
+                .flat_map(|c| {
+                    ["min", "max", "mean", "count"].map(|a| {
+                        let dimension = (a != "count").then(|| c.dimension());
+                        (format!("{}_{}", c.column(), a), dimension)
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Format {
+    Csv,
+    /// newline delimited json
+    Ndjson,
+    Parquet,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    pub table: ExportTable,
+    /// all channels of the table if empty
+    pub channels: Vec<Channel>,
+    pub format: Format,
+    /// timestamps are written in this timezone
+    pub timezone: Tz,
+    /// values are converted to these units, stored units for other dimensions
+    pub units: UnitSet,
+}
+
+impl ExportOptions {
+    fn channels(&self) -> anyhow::Result<Vec<Channel>> {
+        if self.channels.is_empty() {
+            return Ok(self.table.channels());
+        }
+        match self
+            .channels
+            .iter()
+            .find(|c| c.table() != self.table.channel_table())
+        {
+            Some(c) => Err(anyhow!("{} is not a column of {}.", c, self.table.name())),
+            None => Ok(self.channels.clone()),
+        }
+    }
+}
+
+type Row = (NaiveDateTime, Vec<Option<f64>>);
+
+// rows of the table with the values of the columns in the requested unit
+fn rows<'a>(
+    db: &'a DB,
+    options: &ExportOptions,
+    channels: &'a [Channel],
+    columns: &[(String, Option<Dimension>)],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> anyhow::Result<Box<dyn Iterator<Item = anyhow::Result<Row>> + 'a>> {
+    let units = options.units.clone();
+    let dimensions: Vec<_> = columns.iter().map(|(_, d)| *d).collect();
+    let convert = move |(time, values): Row| -> Row {
+        let values = values
+            .into_iter()
+            .zip(&dimensions)
+            .map(|(v, d)| v.map(|v| d.map_or(v, |d| units.convert(d, v))))
+            .collect();
+        (time, values)
+    };
+    let values = |value: &dyn Fn(Channel) -> Option<f64>| -> Vec<Option<f64>> {
+        channels.iter().map(|c| value(*c)).collect()
+    };
+    Ok(match options.table {
+        ExportTable::Temperature => Box::new(
+            db.iter_sensor_samples(from, to)
+                .map(move |s| s.map(|s| convert((s.time, values(&|c| s.data.value(c)))))),
+        ),
+        ExportTable::Usage => Box::new(
+            db.iter_yp_data(from, to)
+                .map(move |y| y.map(|y| convert((y.ts, values(&|c| y.value(c)))))),
+        ),
+        ExportTable::TemperatureHourly => Box::new(
+            db.rollup_rows(Bucket::Hourly, channels, from, to)?
+                .into_iter()
+                .map(move |r| Ok(convert(r))),
+        ),
+        ExportTable::TemperatureDaily => Box::new(
+            db.rollup_rows(Bucket::Daily, channels, from, to)?
+                .into_iter()
+                .map(move |r| Ok(convert(r))),
+        ),
+    })
+}
+
+/// Writes the rows of the table in `[from, to)`. Returns the number of written rows.
+pub fn export<W: Write + Send>(
+    db: &DB,
+    options: &ExportOptions,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    writer: W,
+) -> anyhow::Result<usize> {
+    let channels = options.channels()?;
+    let columns = options.table.columns(&channels);
+    let rows = rows(db, options, &channels, &columns, from, to)?;
+    let names: Vec<&str> = columns.iter().map(|(n, _)| n.as_str()).collect();
+    match options.format {
+        Format::Csv => write_csv(rows, &names, options.timezone, writer),
+        Format::Ndjson => write_ndjson(rows, &names, options.timezone, writer),
+        Format::Parquet => write_parquet(rows, &names, options.timezone, writer),
+    }
+}
+
+fn local_time(time: NaiveDateTime, timezone: Tz) -> String {
+    timezone.from_utc_datetime(&time).to_rfc3339()
+}
+
+fn write_csv(
+    rows: impl Iterator<Item = anyhow::Result<Row>>,
+    columns: &[&str],
+    timezone: Tz,
+    writer: impl Write,
+) -> anyhow::Result<usize> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(std::iter::once("time").chain(columns.iter().copied()))?;
+    let mut count = 0;
+    for row in rows {
+        let (time, values) = row?;
+        writer.write_record(
+            std::iter::once(local_time(time, timezone)).chain(
+                values
+                    .iter()
+                    .map(|v| v.map_or(String::new(), |v| v.to_string())),
+            ),
+        )?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+fn write_ndjson(
+    rows: impl Iterator<Item = anyhow::Result<Row>>,
+    columns: &[&str],
+    timezone: Tz,
+    mut writer: impl Write,
+) -> anyhow::Result<usize> {
+    let mut count = 0;
+    for row in rows {
+        let (time, values) = row?;
+        let mut object = serde_json::Map::new();
+        object.insert("time".into(), local_time(time, timezone).into());
+        for (column, value) in columns.iter().zip(values) {
+            object.insert(column.to_string(), value.into());
+        }
+        serde_json::to_writer(&mut writer, &object)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+// Timestamps in UTC are adjusted to UTC, other timezones are written as local wall clock time
+fn parquet_schema(columns: &[&str], timezone: Tz) -> anyhow::Result<Type> {
+    let mut fields = vec![Arc::new(
+        Type::primitive_type_builder("time", PhysicalType::INT64)
+            .with_repetition(Repetition::REQUIRED)
+            .with_logical_type(Some(LogicalType::Timestamp {
+                is_adjusted_to_u_t_c: timezone == Tz::UTC,
+                unit: TimeUnit::MILLIS(Default::default()),
+            }))
+            .build()?,
+    )];
+    for column in columns {
+        fields.push(Arc::new(
+            Type::primitive_type_builder(column, PhysicalType::DOUBLE)
+                .with_repetition(Repetition::OPTIONAL)
+                .build()?,
+        ));
+    }
+    Ok(Type::group_type_builder("schema")
+        .with_fields(fields)
+        .build()?)
+}
+
+fn write_parquet<W: Write + Send>(
+    rows: impl Iterator<Item = anyhow::Result<Row>>,
+    columns: &[&str],
+    timezone: Tz,
+    writer: W,
+) -> anyhow::Result<usize> {
+    let mut writer = SerializedFileWriter::new(
+        writer,
+        Arc::new(parquet_schema(columns, timezone)?),
+        Arc::new(WriterProperties::builder().build()),
+    )?;
+
+    let mut count = 0;
+    let mut group: Vec<Row> = Vec::with_capacity(ROW_GROUP_SIZE);
+    let mut rows = rows.peekable();
+    while rows.peek().is_some() {
+        group.clear();
+        for row in rows.by_ref().take(ROW_GROUP_SIZE) {
+            group.push(row?);
+        }
+
+        let mut row_group = writer.next_row_group()?;
+        // SAFETY the schema has the time column first
+        let mut column = row_group.next_column()?.unwrap();
+        let times: Vec<i64> = group
+            .iter()
+            .map(|(t, _)| {
+                timezone
+                    .from_utc_datetime(t)
+                    .naive_local()
+                    .and_utc()
+                    .timestamp_millis()
+            })
+            .collect();
+        column
+            .typed::<Int64Type>()
+            .write_batch(&times, None, None)?;
+        column.close()?;
+
+        for i in 0..columns.len() {
+            let mut column = row_group
+                .next_column()?
+                .ok_or(anyhow!("Parquet schema is missing column {}.", i))?;
+            let values: Vec<f64> = group.iter().filter_map(|(_, v)| v[i]).collect();
+            let levels: Vec<i16> = group.iter().map(|(_, v)| v[i].is_some() as i16).collect();
+            column
+                .typed::<DoubleType>()
+                .write_batch(&values, Some(&levels), None)?;
+            column.close()?;
+        }
+        row_group.close()?;
+        count += group.len();
+    }
+    writer.close()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+    use chrono_tz::Tz;
+    use parquet::{
+        file::reader::{FileReader, SerializedFileReader},
+        record::Field,
+    };
+
+    use crate::{
+        db::{Bucket, Channel, SensorData, DB},
+        units::{Unit, UnitSet},
+        yp::YpData,
+    };
+
+    use super::{export, ExportOptions, ExportTable, Format};
+
+    fn time(day: u32, minute: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 7, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + Duration::minutes(minute)
+    }
+
+    fn db() -> DB {
+        let db = DB::new(None).unwrap();
+        db.insert_sensor_data_at(
+            time(1, 0),
+            SensorData::new_raw(Some(20.5), Some(50.0), None, Some(30.0)),
+        )
+        .unwrap();
+        db.insert_sensor_data_at(
+            time(1, 15),
+            SensorData::new_raw(Some(21.0), None, Some(1.5), Some(31.0)),
+        )
+        .unwrap();
+        db.insert_yp_data(&YpData {
+            ts: time(1, 0),
+            ch_hp_y: 4000.0,
+            ch_hp_p: 1250.0,
+            ..Default::default()
+        })
+        .unwrap();
+        db
+    }
+
+    fn options(table: ExportTable, channels: Vec<Channel>, format: Format) -> ExportOptions {
+        ExportOptions {
+            table,
+            channels,
+            format,
+            timezone: Tz::UTC,
+            units: UnitSet::default(),
+        }
+    }
+
+    fn to_string(
+        db: &DB,
+        options: &ExportOptions,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> String {
+        let mut out = Vec::new();
+        export(db, options, from, to, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv() {
+        let db = db();
+        let mut options = options(
+            ExportTable::Temperature,
+            vec![Channel::Outdoor, Channel::Waterpressure],
+            Format::Csv,
+        );
+        options.timezone = "Europe/Berlin".parse().unwrap();
+        assert_eq!(
+            "time,outdoor,waterpressure\n\
+            2023-07-01T02:00:00+02:00,20.5,\n\
+            2023-07-01T02:15:00+02:00,21,1.5\n",
+            to_string(&db, &options, time(1, 0), time(2, 0))
+        );
+
+        options.units = UnitSet::new(&[Unit::Fahrenheit, Unit::KiloPascal]).unwrap();
+        assert_eq!(
+            "time,outdoor,waterpressure\n\
+            2023-07-01T02:00:00+02:00,68.9,\n\
+            2023-07-01T02:15:00+02:00,69.8,150\n",
+            to_string(&db, &options, time(1, 0), time(2, 0))
+        );
+
+        // usage column of the temperature table
+        options.channels = vec![Channel::ChHpY];
+        assert!(export(&db, &options, time(1, 0), time(2, 0), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn ndjson() {
+        let db = db();
+        let mut options = options(ExportTable::Usage, Vec::new(), Format::Ndjson);
+        options.units = UnitSet::new(&[Unit::KiloWattHour]).unwrap();
+        let out = to_string(&db, &options, time(1, 0), time(2, 0));
+        assert_eq!(1, out.lines().count());
+        let row: serde_json::Value = serde_json::from_str(out.lines().next().unwrap()).unwrap();
+        assert_eq!("2023-07-01T00:00:00+00:00", row["time"]);
+        assert_eq!(4.0, row["ch_hp_y"]);
+        assert_eq!(1.25, row["ch_hp_p"]);
+        assert_eq!(0.0, row["co_hp_p"]);
+    }
+
+    #[test]
+    fn rollup() {
+        let db = db();
+        db.rollup(Bucket::Hourly, time(1, 0), time(2, 0)).unwrap();
+        let mut options = options(
+            ExportTable::TemperatureHourly,
+            vec![Channel::Outdoor, Channel::Waterpressure],
+            Format::Csv,
+        );
+        options.units = UnitSet::new(&[Unit::KiloPascal]).unwrap();
+        assert_eq!(
+            "time,outdoor_min,outdoor_max,outdoor_mean,outdoor_count,\
+            waterpressure_min,waterpressure_max,waterpressure_mean,waterpressure_count\n\
+            2023-07-01T00:00:00+00:00,20.5,21,20.75,2,150,150,150,1\n",
+            to_string(&db, &options, time(1, 0), time(2, 0))
+        );
+
+        // not rolled up yet
+        options.table = ExportTable::TemperatureDaily;
+        assert_eq!(
+            "time,outdoor_min,outdoor_max,outdoor_mean,outdoor_count,\
+            waterpressure_min,waterpressure_max,waterpressure_mean,waterpressure_count\n",
+            to_string(&db, &options, time(1, 0), time(2, 0))
+        );
+
+        options.channels = vec![Channel::ChHpY];
+        assert!(export(&db, &options, time(1, 0), time(2, 0), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn parquet() {
+        let db = db();
+        let mut out = Vec::new();
+        let count = export(
+            &db,
+            &options(ExportTable::Temperature, Vec::new(), Format::Parquet),
+            time(1, 0),
+            time(2, 0),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(2, count);
+
+        let reader = SerializedFileReader::new(Bytes::from(out)).unwrap();
+        assert_eq!(2, reader.metadata().file_metadata().num_rows());
+        let rows: Vec<_> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        let columns: Vec<_> = rows[0].get_column_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            vec![
+                "time",
+                "outdoor",
+                "hotwatertank",
+                "waterpressure",
+                "heatingcircuit",
+                "co2",
+                "humidity"
+            ],
+            columns
+        );
+        let fields: Vec<_> = rows[1].get_column_iter().map(|(_, f)| f.clone()).collect();
+        assert_eq!(
+            Field::TimestampMillis(time(1, 15).and_utc().timestamp_millis()),
+            fields[0]
+        );
+        assert_eq!(Field::Double(21.0), fields[1]);
+        assert_eq!(Field::Null, fields[2]);
+        assert_eq!(Field::Double(1.5), fields[3]);
+    }
+}
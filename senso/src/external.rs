@@ -0,0 +1,393 @@
+use std::{collections::BTreeMap, fs};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, NaiveDateTime, Timelike};
+use cli_table::Table;
+use log::warn;
+use serde::Deserialize;
+use strum_macros::{AsRefStr, Display, EnumString};
+
+use crate::{
+    db::DB,
+    spf::Period,
+    units::{Energy, Unit},
+};
+
+/// Origin of externally measured energy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Source {
+    /// electricity meter of the heat pump
+    Meter,
+    /// production of the photovoltaic system
+    Pv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum ImportFormat {
+    /// `time,value` with an optional header
+    Csv,
+    /// newline delimited json of raw SunSpec model registers
+    Sunspec,
+}
+
+/// Registers of one SunSpec model block, without the ID and L registers
+#[derive(Debug, Clone, Deserialize)]
+struct SunSpecBlock {
+    time: String,
+    model: u16,
+    registers: Vec<u16>,
+}
+
+impl SunSpecBlock {
+    /// Lifetime energy in Wh. Inverters report the produced, meters the imported energy.
+    fn energy(&self) -> anyhow::Result<f64> {
+        // offsets of the acc32 value and its scale factor
+        let (offset, sf_offset) = match self.model {
+            101..=103 => (22, 24),
+            201..=204 => (44, 52),
+            _ => return Err(anyhow!("Unsupported SunSpec model {}.", self.model)),
+        };
+        let register = |i: usize| {
+            self.registers.get(i).copied().ok_or(anyhow!(
+                "SunSpec model {} block has only {} registers.",
+                self.model,
+                self.registers.len()
+            ))
+        };
+        let value = ((register(offset)? as u32) << 16) | register(offset + 1)? as u32;
+        let sf = register(sf_offset)? as i16;
+        Ok(value as f64 * 10f64.powi(sf as i32))
+    }
+}
+
+/// Energy in Wh per interval, keyed by the start of the interval
+pub type Intervals = Vec<(NaiveDateTime, f64)>;
+
+/// Reads the file and returns the energy per interval.
+///
+/// `cumulative` values are meter readings, the difference to the next reading is the energy of the interval.
+/// SunSpec registers are always cumulative and in Wh.
+pub fn read(
+    path: &str,
+    format: ImportFormat,
+    cumulative: bool,
+    unit: Unit,
+) -> anyhow::Result<Intervals> {
+    let content = fs::read_to_string(path)?;
+    match format {
+        ImportFormat::Csv => {
+            let values = parse_csv(&content, unit)?;
+            if cumulative {
+                Ok(deltas(values))
+            } else {
+                Ok(values)
+            }
+        }
+        ImportFormat::Sunspec => Ok(deltas(parse_sunspec(&content)?)),
+    }
+}
+
+/// Imports the file into the ExternalEnergy table. Returns the number of intervals.
+///
+/// Intervals that were imported before are replaced.
+pub fn import(
+    db: &DB,
+    source: Source,
+    path: &str,
+    format: ImportFormat,
+    cumulative: bool,
+    unit: Unit,
+) -> anyhow::Result<usize> {
+    let intervals = read(path, format, cumulative, unit)?;
+    db.insert_external_energy(source, &intervals)?;
+    Ok(intervals.len())
+}
+
+fn parse_csv(content: &str, unit: Unit) -> anyhow::Result<Intervals> {
+    let mut result = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (time, value) = line
+            .split_once(',')
+            .ok_or(anyhow!("Missing ',' in line {}.", i + 1))?;
+        let time = match parse_time(time.trim()) {
+            Some(t) => t,
+            // header
+            None if i == 0 => continue,
+            None => return Err(anyhow!("Invalid time \"{}\" in line {}.", time, i + 1)),
+        };
+        result.push((time, Energy::new(value.trim().parse::<f64>()?, unit)?.wh()));
+    }
+    if result.is_empty() {
+        return Err(anyhow!("No values in csv."));
+    }
+    Ok(result)
+}
+
+fn parse_sunspec(content: &str) -> anyhow::Result<Intervals> {
+    let mut result = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let block: SunSpecBlock = serde_json::from_str(line)?;
+        let time = parse_time(&block.time).ok_or(anyhow!(
+            "Invalid time \"{}\" in line {}.",
+            block.time,
+            i + 1
+        ))?;
+        result.push((time, block.energy()?));
+    }
+    if result.is_empty() {
+        return Err(anyhow!("No SunSpec blocks in dump."));
+    }
+    Ok(result)
+}
+
+// RFC 3339 with offset, unix seconds or UTC
+fn parse_time(s: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.naive_utc())
+        .ok()
+        .or_else(|| {
+            s.parse::<i64>()
+                .ok()
+                .and_then(|t| DateTime::from_timestamp(t, 0))
+                .map(|t| t.naive_utc())
+        })
+        .or_else(|| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").ok())
+        .or_else(|| crate::emissions::parse_time(s))
+}
+
+// energy between consecutive readings, a reset of the counter starts again from the next reading
+fn deltas(mut readings: Intervals) -> Intervals {
+    readings.sort_by_key(|(t, _)| *t);
+    let mut result = Vec::new();
+    for pair in readings.windows(2) {
+        let ((from, a), (_, b)) = (pair[0], pair[1]);
+        if b < a {
+            warn!(
+                "Meter reading decreased at {}, skipping the interval.",
+                from
+            );
+            continue;
+        }
+        result.push((from, b - a));
+    }
+    result
+}
+
+/// Comparison of one day of usage with the external meter and PV production
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct CrossCheck {
+    #[table(title = "day")]
+    pub day: NaiveDateTime,
+    /// consumption estimated by the heat pump in Wh
+    #[table(title = "emf power")]
+    pub emf_power: f64,
+    #[table(title = "meter", display_fn = "display_option")]
+    pub meter: Option<f64>,
+    /// relative difference of the estimate to the meter
+    #[table(title = "emf error", display_fn = "display_option")]
+    pub emf_error: Option<f64>,
+    #[table(title = "pv", display_fn = "display_option")]
+    pub pv: Option<f64>,
+    /// consumption covered by PV in Wh
+    #[table(title = "pv used", display_fn = "display_option")]
+    pub pv_used: Option<f64>,
+    /// share of the consumption covered by PV
+    #[table(title = "pv coverage", display_fn = "display_option")]
+    pub pv_coverage: Option<f64>,
+}
+
+fn display_option(value: &Option<f64>) -> impl std::fmt::Display {
+    value.map_or("-".to_string(), |v| v.to_string())
+}
+
+fn round(value: f64) -> f64 {
+    (value * 10000.0).round() / 10000.0
+}
+
+// energy per hour of the day
+fn hourly(intervals: &[(NaiveDateTime, f64)]) -> BTreeMap<NaiveDateTime, f64> {
+    let mut result = BTreeMap::new();
+    for (time, value) in intervals {
+        // SAFETY full hours always exist
+        let hour = time.date().and_hms_opt(time.hour(), 0, 0).unwrap();
+        *result.entry(hour).or_insert(0.0) += value;
+    }
+    result
+}
+
+/// Compares every day of usage in the period with the stored meter and PV intervals.
+///
+/// The consumption is the meter if available, otherwise the estimate of the heat pump spread evenly over the day.
+/// PV covers at most the consumption of every hour, the rest of the production is not counted.
+/// Intervals are counted on the day and hour they start in.
+pub fn cross_check(db: &DB, period: Period) -> anyhow::Result<Vec<CrossCheck>> {
+    let (from, to) = period.range()?;
+    let meter = db.external_energy(Source::Meter, from, to)?;
+    let pv = db.external_energy(Source::Pv, from, to)?;
+
+    let mut result = Vec::new();
+    for yp_data in db.get_yp_data(from, to)? {
+        let day = yp_data.ts;
+        let in_day = |intervals: &[(NaiveDateTime, f64)]| {
+            intervals
+                .iter()
+                .filter(|(t, _)| *t >= day && *t < day + Duration::days(1))
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        let day_meter = in_day(&meter);
+        let day_pv = in_day(&pv);
+        let sum = |intervals: &[(NaiveDateTime, f64)]| {
+            (!intervals.is_empty()).then(|| intervals.iter().map(|(_, v)| v).sum::<f64>())
+        };
+
+        let emf_power = yp_data.total_p + yp_data.co_hp_p;
+        let meter_sum = sum(&day_meter);
+        let emf_error = meter_sum
+            .filter(|m| *m > 0.0)
+            .map(|m| round((emf_power - m) / m));
+
+        let consumption = if day_meter.is_empty() {
+            // SAFETY full hours always exist
+            (0..24)
+                .map(|h| (day.date().and_hms_opt(h, 0, 0).unwrap(), emf_power / 24.0))
+                .collect()
+        } else {
+            hourly(&day_meter)
+        };
+        let total = meter_sum.unwrap_or(emf_power);
+        let pv_used = (!day_pv.is_empty()).then(|| {
+            hourly(&day_pv)
+                .iter()
+                .map(|(hour, pv)| pv.min(consumption.get(hour).copied().unwrap_or(0.0)))
+                .sum::<f64>()
+        });
+
+        result.push(CrossCheck {
+            day,
+            emf_power,
+            meter: meter_sum.map(round),
+            emf_error,
+            pv: sum(&day_pv).map(round),
+            pv_used: pv_used.map(round),
+            pv_coverage: pv_used
+                .filter(|_| total > 0.0)
+                .map(|used| round(used / total)),
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    use crate::{db::DB, spf::Period, units::Unit, yp::YpData};
+
+    use super::{cross_check, deltas, parse_csv, parse_sunspec, Source};
+
+    fn time(day: u32, hour: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 6, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + Duration::hours(hour)
+    }
+
+    fn block(time: &str, model: u16, offset: usize, value: u32, sf: i16) -> String {
+        let mut registers = vec![0u16; 60];
+        registers[offset] = (value >> 16) as u16;
+        registers[offset + 1] = value as u16;
+        registers[offset + if model < 200 { 2 } else { 8 }] = sf as u16;
+        serde_json::json!({"time": time, "model": model, "registers": registers}).to_string()
+    }
+
+    #[test]
+    fn sunspec() {
+        let dump = [
+            block("2023-06-01T12:00:00+02:00", 103, 22, 100_000, 0),
+            block("2023-06-01T12:15:00+02:00", 103, 22, 100_500, 0),
+        ]
+        .join("\n");
+        let readings = parse_sunspec(&dump).unwrap();
+        assert_eq!(
+            vec![
+                (time(1, 10), 100_000.0),
+                (time(1, 10) + Duration::minutes(15), 100_500.0)
+            ],
+            readings
+        );
+        assert_eq!(vec![(time(1, 10), 500.0)], deltas(readings));
+
+        // meter with scale factor 10
+        let meter = parse_sunspec(&block("1685620800", 203, 44, 1234, 1)).unwrap();
+        assert_eq!(vec![(time(1, 12), 12340.0)], meter);
+
+        assert!(parse_sunspec(&block("1685620800", 160, 44, 1234, 1)).is_err());
+    }
+
+    #[test]
+    fn meter_and_pv() {
+        let db = DB::new(None).unwrap();
+        for (day, total_p) in [(1, 4000.0), (2, 2400.0)] {
+            db.insert_yp_data(&YpData {
+                ts: time(day, 0),
+                total_p,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        // cumulative in kWh, counter reset at the end
+        let meter = parse_csv(
+            "time,kwh\n\
+            2023-06-01T00:00:00+00:00,100\n\
+            2023-06-01 12:00,102\n\
+            2023-06-02,105\n\
+            2023-06-02T01:00:00+00:00,1\n",
+            Unit::KiloWattHour,
+        )
+        .unwrap();
+        db.insert_external_energy(Source::Meter, &deltas(meter))
+            .unwrap();
+        let pv = parse_csv(
+            "2023-06-01 12:00,1000\n2023-06-01 13:00,500\n2023-06-02 12:30,500\n",
+            Unit::WattHour,
+        )
+        .unwrap();
+        db.insert_external_energy(Source::Pv, &pv).unwrap();
+        // imported again
+        db.insert_external_energy(Source::Pv, &pv).unwrap();
+
+        let result = cross_check(
+            &db,
+            Period::Range {
+                from: time(1, 0).date(),
+                to: time(3, 0).date(),
+            },
+        )
+        .unwrap();
+        assert_eq!(2, result.len());
+        assert_eq!(Some(5000.0), result[0].meter);
+        assert_eq!(Some(-0.2), result[0].emf_error);
+        assert_eq!(Some(1500.0), result[0].pv);
+        // no consumption at 13:00
+        assert_eq!(Some(1000.0), result[0].pv_used);
+        assert_eq!(Some(0.2), result[0].pv_coverage);
+
+        // estimate spread over the day
+        assert_eq!(None, result[1].meter);
+        assert_eq!(None, result[1].emf_error);
+        assert_eq!(Some(100.0), result[1].pv_used);
+        assert_eq!(Some(0.0417), result[1].pv_coverage);
+    }
+}
@@ -0,0 +1,259 @@
+use chrono::NaiveDateTime;
+use cli_table::Table;
+use log::{info, warn};
+
+use crate::{
+    alert::{self, Alert},
+    db::DB,
+    notify::Sink,
+    response::hvac_state::{ErrorMessage, ErrorType},
+};
+
+/// Error or maintenance message of a device.
+///
+/// A fault is open from the time it appeared until it is no longer reported by the api.
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Fault {
+    #[table(title = "code")]
+    pub code: String,
+    #[table(title = "device")]
+    pub device: String,
+    #[table(title = "type")]
+    pub fault_type: ErrorType,
+    #[table(title = "title")]
+    pub title: String,
+    #[table(skip)]
+    pub description: String,
+    #[table(title = "appeared")]
+    pub appeared: NaiveDateTime,
+    #[table(title = "cleared", display_fn = "display_option")]
+    pub cleared: Option<NaiveDateTime>,
+}
+
+fn display_option(value: &Option<NaiveDateTime>) -> impl std::fmt::Display {
+    value.map_or("-".to_string(), |v| v.to_string())
+}
+
+impl Fault {
+    pub fn from_error(error: &ErrorMessage) -> Fault {
+        Fault {
+            code: error.status_code.clone(),
+            device: error.device_name.clone(),
+            fault_type: error.type_field.clone(),
+            title: error.title.clone(),
+            description: error.description.clone(),
+            appeared: error.timestamp.naive_utc(),
+            cleared: None,
+        }
+    }
+
+    /// Faults are identified by code and device
+    pub fn is_same(&self, other: &Fault) -> bool {
+        self.code == other.code && self.device == other.device
+    }
+
+    pub fn to_alert(&self) -> Alert {
+        Alert {
+            rule: format!("fault {} {}", self.device, self.code),
+            channel: None,
+            time: self.appeared,
+            value: None,
+            message: format!(
+                "{} {} on {}: {}",
+                self.fault_type, self.code, self.device, self.title
+            ),
+        }
+    }
+}
+
+/// Faults that appeared or cleared by an update
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FaultChanges {
+    pub appeared: Vec<Fault>,
+    pub cleared: Vec<Fault>,
+}
+
+/// Records the currently reported errors.
+///
+/// New faults are stored as open, open faults that are no longer reported are cleared at `now`.
+/// A fault reported more than once is recorded once.
+pub fn update(
+    db: &DB,
+    errors: &[ErrorMessage],
+    now: NaiveDateTime,
+) -> anyhow::Result<FaultChanges> {
+    let mut current: Vec<Fault> = Vec::new();
+    for fault in errors.iter().map(Fault::from_error) {
+        if !current.iter().any(|f| f.is_same(&fault)) {
+            current.push(fault);
+        }
+    }
+    db.in_transaction(|| {
+        let open = db.get_faults(true)?;
+        let mut changes = FaultChanges::default();
+        for fault in current
+            .iter()
+            .filter(|f| !open.iter().any(|o| o.is_same(f)))
+        {
+            db.insert_fault(fault)?;
+            warn!(
+                "Fault {} {} on {} appeared: {}",
+                fault.fault_type, fault.code, fault.device, fault.title
+            );
+            changes.appeared.push(fault.clone());
+        }
+        for fault in open
+            .into_iter()
+            .filter(|o| !current.iter().any(|f| f.is_same(o)))
+        {
+            db.clear_fault(&fault, now)?;
+            info!("Fault {} on {} cleared.", fault.code, fault.device);
+            changes.cleared.push(Fault {
+                cleared: Some(now),
+                ..fault
+            });
+        }
+        Ok(changes)
+    })
+}
+
+/// Sends an alert for every open fault that wasn't delivered yet to the sinks.
+///
+/// A fault is sent again on the next call until a sink accepts it. Returns the sent alerts.
+pub fn notify(db: &DB, sinks: &[Box<dyn Sink>], now: NaiveDateTime) -> anyhow::Result<Vec<Alert>> {
+    let mut sent = Vec::new();
+    for fault in db.get_unnotified_faults()? {
+        let alert = fault.to_alert();
+        if alert::deliver(&alert, sinks) {
+            db.set_fault_notified(&fault, now)?;
+            sent.push(alert);
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    use crate::{alert::Alert, db::DB, notify::Sink, response::hvac_state::ErrorMessage};
+
+    use super::{notify, update};
+
+    struct Collect(Rc<RefCell<Vec<Alert>>>);
+
+    impl Sink for Collect {
+        fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+            self.0.borrow_mut().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    struct Fail;
+
+    impl Sink for Fail {
+        fn send(&self, _: &Alert) -> anyhow::Result<()> {
+            Err(anyhow::anyhow!("unreachable"))
+        }
+    }
+
+    fn time(hour: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 3, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + Duration::hours(hour)
+    }
+
+    fn error(code: &str, device: &str, error_type: &str) -> ErrorMessage {
+        serde_json::from_value(serde_json::json!({
+            "type": error_type,
+            "statusCode": code,
+            "deviceName": device,
+            "title": format!("{} title", code),
+            "description": "description",
+            "timestamp": time(0).and_utc().timestamp_millis()
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn history() {
+        let db = DB::new(None).unwrap();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let sinks: Vec<Box<dyn Sink>> = vec![Box::new(Collect(sent.clone()))];
+
+        let errors = vec![
+            error("F.28", "VWL 101/6", "ERROR"),
+            error("M.20", "VWL 101/6", "MAINTENANCE"),
+        ];
+        let changes = update(&db, &errors, time(1)).unwrap();
+        assert_eq!(2, changes.appeared.len());
+        assert_eq!(time(0), changes.appeared[0].appeared);
+        assert_eq!(2, notify(&db, &sinks, time(1)).unwrap().len());
+        assert_eq!(2, sent.borrow().len());
+        assert_eq!(
+            "ERROR F.28 on VWL 101/6: F.28 title",
+            sent.borrow()[0].message
+        );
+
+        // still reported, nothing new
+        let changes = update(&db, &errors, time(2)).unwrap();
+        assert!(changes.appeared.is_empty() && changes.cleared.is_empty());
+        assert!(notify(&db, &sinks, time(2)).unwrap().is_empty());
+
+        // F.28 cleared, same code on another device appeared
+        let errors = vec![
+            error("M.20", "VWL 101/6", "MAINTENANCE"),
+            error("F.28", "VRC 720", "ERROR"),
+        ];
+        let changes = update(&db, &errors, time(3)).unwrap();
+        assert_eq!(1, changes.appeared.len());
+        assert_eq!("VRC 720", changes.appeared[0].device);
+        assert_eq!(1, changes.cleared.len());
+        assert_eq!(Some(time(3)), changes.cleared[0].cleared);
+
+        assert_eq!(2, db.get_faults(true).unwrap().len());
+        let all = db.get_faults(false).unwrap();
+        assert_eq!(3, all.len());
+        assert_eq!(Some(time(3)), all[0].cleared);
+
+        // appears again after it was cleared
+        let changes = update(&db, &[error("F.28", "VWL 101/6", "ERROR")], time(4)).unwrap();
+        assert_eq!(1, changes.appeared.len());
+        assert_eq!(2, changes.cleared.len());
+        assert_eq!(4, db.get_faults(false).unwrap().len());
+    }
+
+    #[test]
+    fn reported_twice() {
+        let db = DB::new(None).unwrap();
+        let errors = vec![
+            error("F.28", "VWL 101/6", "ERROR"),
+            error("F.28", "VWL 101/6", "ERROR"),
+        ];
+        let changes = update(&db, &errors, time(1)).unwrap();
+        assert_eq!(1, changes.appeared.len());
+        assert_eq!(1, db.get_faults(true).unwrap().len());
+
+        // only one open fault per code and device
+        assert!(db.insert_fault(&changes.appeared[0]).is_err());
+    }
+
+    #[test]
+    fn retry_until_delivered() {
+        let db = DB::new(None).unwrap();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        update(&db, &[error("F.28", "VWL 101/6", "ERROR")], time(1)).unwrap();
+
+        let failing: Vec<Box<dyn Sink>> = vec![Box::new(Fail)];
+        assert!(notify(&db, &failing, time(1)).unwrap().is_empty());
+
+        let sinks: Vec<Box<dyn Sink>> = vec![Box::new(Fail), Box::new(Collect(sent.clone()))];
+        assert_eq!(1, notify(&db, &sinks, time(2)).unwrap().len());
+        assert!(notify(&db, &sinks, time(3)).unwrap().is_empty());
+        assert_eq!(1, sent.borrow().len());
+    }
+}
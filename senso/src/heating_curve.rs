@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use cli_table::Table;
+
+use crate::{
+    db::{Channel, DB},
+    spf::Period,
+    yp::calc_yp,
+};
+
+/// Room temperature in °C the heating curve is relative to
+pub const REFERENCE_ROOM_TEMPERATURE: f64 = 20.0;
+/// Default outdoor temperature in °C above which samples are ignored, since the heating is usually off
+pub const DEFAULT_HEATING_LIMIT: f64 = 15.0;
+/// Default deviation in K from the fitted curve that flags a sample
+pub const DEFAULT_MAX_DEVIATION: f64 = 5.0;
+
+/// Linear heating curve
+///
+/// flow = offset + slope * (REFERENCE_ROOM_TEMPERATURE - outdoor)
+#[derive(Debug, Clone, Copy, PartialEq, Table)]
+pub struct HeatingCurve {
+    /// K flow temperature per K outdoor temperature
+    #[table(title = "slope")]
+    pub slope: f64,
+    /// flow temperature in °C at the reference room temperature
+    #[table(title = "offset")]
+    pub offset: f64,
+}
+
+impl HeatingCurve {
+    /// expected flow temperature for the outdoor temperature
+    pub fn flow(&self, outdoor: f64) -> f64 {
+        self.offset + self.slope * (REFERENCE_ROOM_TEMPERATURE - outdoor)
+    }
+
+    /// same curve with a lower flow temperature
+    pub fn lowered(&self, kelvin: f64) -> Self {
+        Self {
+            slope: self.slope,
+            offset: self.offset - kelvin,
+        }
+    }
+}
+
+/// Heating curve fitted over stored samples
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Fit {
+    #[table(title = "slope")]
+    pub slope: f64,
+    #[table(title = "offset")]
+    pub offset: f64,
+    /// coefficient of determination
+    #[table(title = "r²")]
+    pub r_squared: f64,
+    #[table(title = "samples")]
+    pub samples: usize,
+}
+
+impl Fit {
+    pub fn curve(&self) -> HeatingCurve {
+        HeatingCurve {
+            slope: self.slope,
+            offset: self.offset,
+        }
+    }
+}
+
+/// Sample that strays far from the fitted curve
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Deviation {
+    #[table(title = "time")]
+    pub time: NaiveDateTime,
+    #[table(title = "outdoor")]
+    pub outdoor: f64,
+    #[table(title = "flow")]
+    pub flow: f64,
+    #[table(title = "expected")]
+    pub expected: f64,
+    /// flow - expected
+    #[table(title = "deviation")]
+    pub deviation: f64,
+}
+
+/// Estimated effect of a different heating curve on the central heating yp
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct CopEstimate {
+    /// days with usage and flow temperature
+    #[table(title = "days")]
+    pub days: usize,
+    /// change of the daily yp per K flow temperature
+    #[table(title = "yp/K")]
+    pub yp_per_kelvin: f64,
+    /// mean change of the flow temperature with the proposed curve
+    #[table(title = "flow change")]
+    pub flow_change: f64,
+    /// yp of the period with summed yield and power
+    #[table(title = "current yp")]
+    pub current_yp: f64,
+    #[table(title = "estimated yp")]
+    pub estimated_yp: f64,
+}
+
+// slope, offset and r² of y = offset + slope * x
+fn linear_regression(points: &[(f64, f64)]) -> Option<(f64, f64, f64)> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let var_x: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let var_y: f64 = points.iter().map(|p| (p.1 - mean_y).powi(2)).sum();
+    let cov: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    if var_x == 0.0 {
+        return None;
+    }
+    let slope = cov / var_x;
+    let offset = mean_y - slope * mean_x;
+    let r_squared = if var_y == 0.0 {
+        1.0
+    } else {
+        cov * cov / (var_x * var_y)
+    };
+    Some((slope, offset, r_squared))
+}
+
+// outdoor and flow temperature of all samples below the heating limit
+fn samples(
+    db: &DB,
+    period: Period,
+    heating_limit: f64,
+) -> anyhow::Result<Vec<(NaiveDateTime, f64, f64)>> {
+    let (from, to) = period.range()?;
+    Ok(db
+        .sensor_samples(from, to)?
+        .into_iter()
+        .filter_map(|s| {
+            Some((
+                s.time,
+                s.data.value(Channel::Outdoor)?,
+                s.data.value(Channel::Heatingcircuit)?,
+            ))
+        })
+        .filter(|(_, outdoor, _)| *outdoor < heating_limit)
+        .collect())
+}
+
+/// Fit the heating curve over all samples of the period below the heating limit
+pub fn fit(db: &DB, period: Period, heating_limit: f64) -> anyhow::Result<Fit> {
+    let points: Vec<(f64, f64)> = samples(db, period, heating_limit)?
+        .iter()
+        .map(|(_, outdoor, flow)| (REFERENCE_ROOM_TEMPERATURE - outdoor, *flow))
+        .collect();
+
+    let (slope, offset, r_squared) = linear_regression(&points).ok_or(anyhow!(
+        "Not enough samples with different outdoor temperatures to fit a heating curve."
+    ))?;
+
+    Ok(Fit {
+        slope,
+        offset,
+        r_squared,
+        samples: points.len(),
+    })
+}
+
+/// All samples of the period below the heating limit that deviate more than `max_deviation` K from the curve
+pub fn deviations(
+    db: &DB,
+    period: Period,
+    heating_limit: f64,
+    curve: &HeatingCurve,
+    max_deviation: f64,
+) -> anyhow::Result<Vec<Deviation>> {
+    Ok(samples(db, period, heating_limit)?
+        .into_iter()
+        .map(|(time, outdoor, flow)| {
+            let expected = curve.flow(outdoor);
+            Deviation {
+                time,
+                outdoor,
+                flow,
+                expected,
+                deviation: flow - expected,
+            }
+        })
+        .filter(|d| d.deviation.abs() > max_deviation)
+        .collect())
+}
+
+/// Estimate the central heating yp with a proposed heating curve.
+///
+/// The daily yp is regressed over the daily mean flow temperature.
+/// The mean flow change is the difference of both curves over the samples of the period.
+pub fn estimate_cop(
+    db: &DB,
+    period: Period,
+    heating_limit: f64,
+    current: &HeatingCurve,
+    proposed: &HeatingCurve,
+) -> anyhow::Result<CopEstimate> {
+    let (from, to) = period.range()?;
+
+    // daily yield and power of central heating
+    let usage: HashMap<NaiveDateTime, (f64, f64)> = db
+        .get_yp_data(from, to)?
+        .iter()
+        .map(|u| (u.ts, (u.ch_hp_y, u.ch_hp_p + u.ch_bo_p)))
+        .collect();
+
+    let mut sum_y = 0.0;
+    let mut sum_p = 0.0;
+    let mut points = Vec::new();
+    for (day, flow) in db.daily_means(Channel::Heatingcircuit, from, to)? {
+        let (day_y, day_p) = match usage.get(&day) {
+            Some((y, p)) if *p > 0.0 => (*y, *p),
+            _ => continue,
+        };
+        sum_y += day_y;
+        sum_p += day_p;
+        points.push((flow, calc_yp(day_y, day_p)));
+    }
+
+    let (yp_per_kelvin, _, _) = linear_regression(&points).ok_or(anyhow!(
+        "Not enough days with different flow temperatures to estimate the yp."
+    ))?;
+
+    let samples = samples(db, period, heating_limit)?;
+    let flow_change = if samples.is_empty() {
+        0.0
+    } else {
+        samples
+            .iter()
+            .map(|(_, outdoor, _)| proposed.flow(*outdoor) - current.flow(*outdoor))
+            .sum::<f64>()
+            / samples.len() as f64
+    };
+
+    let current_yp = calc_yp(sum_y, sum_p);
+    Ok(CopEstimate {
+        days: points.len(),
+        yp_per_kelvin,
+        flow_change,
+        current_yp,
+        estimated_yp: ((current_yp + yp_per_kelvin * flow_change) * 10000.0).round() / 10000.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    use crate::{
+        db::{SensorData, DB},
+        spf::Period,
+        yp::YpData,
+    };
+
+    use super::{deviations, estimate_cop, fit, HeatingCurve};
+
+    fn day(d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 1, d)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn heating_curve() {
+        let db = DB::new(None).unwrap();
+        let curve = HeatingCurve {
+            slope: 0.8,
+            offset: 25.0,
+        };
+        let period = Period::Month {
+            year: 2023,
+            month: 1,
+        };
+
+        // day 1 to 4 with flow on the curve, warmer days have a lower flow and a better yp
+        for d in 1..=4 {
+            for hour in 0..4 {
+                let outdoor = -5.0 + d as f64 + hour as f64;
+                db.insert_sensor_data_at(
+                    day(d) + Duration::hours(hour),
+                    SensorData::new_raw(Some(outdoor), None, None, Some(curve.flow(outdoor))),
+                )
+                .unwrap();
+            }
+            db.insert_yp_data(&YpData {
+                ts: day(d),
+                ch_hp_y: 2000.0 + 1000.0 * d as f64,
+                ch_hp_p: 1000.0,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        // heating is off
+        db.insert_sensor_data_at(
+            day(5),
+            SensorData::new_raw(Some(18.0), None, None, Some(60.0)),
+        )
+        .unwrap();
+        // far from the curve
+        db.insert_sensor_data_at(
+            day(6),
+            SensorData::new_raw(Some(0.0), None, None, Some(50.0)),
+        )
+        .unwrap();
+
+        let result = fit(&db, period, 15.0).unwrap();
+        assert_eq!(17, result.samples);
+        assert!(result.r_squared < 1.0);
+
+        let flagged = deviations(&db, period, 15.0, &curve, 5.0).unwrap();
+        assert_eq!(1, flagged.len());
+        assert_eq!(day(6), flagged[0].time);
+        assert_eq!(9.0, flagged[0].deviation);
+
+        // lower curve => better yp
+        let estimate = estimate_cop(&db, period, 15.0, &curve, &curve.lowered(2.0)).unwrap();
+        assert_eq!(4, estimate.days);
+        assert_eq!(-2.0, estimate.flow_change);
+        assert!(estimate.yp_per_kelvin < 0.0);
+        assert!(estimate.estimated_yp > estimate.current_yp);
+    }
+}
@@ -0,0 +1,243 @@
+use chrono::NaiveDateTime;
+use cli_table::Table;
+use log::{info, warn};
+
+use crate::{
+    connector::Connector,
+    db::DB,
+    response::{emf_devices, facility_details},
+};
+
+/// Type of the gateway in the inventory, emf devices have the type of the api
+pub const GATEWAY: &str = "GATEWAY";
+
+/// Device of the facility, unchanged from `first_seen` until `last_seen`
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct DeviceInfo {
+    #[table(title = "id")]
+    pub id: String,
+    #[table(title = "type")]
+    pub device_type: String,
+    #[table(title = "name")]
+    pub marketing_name: String,
+    #[table(title = "serial", display_fn = "display_option")]
+    pub serial: Option<String>,
+    #[table(title = "firmware", display_fn = "display_option")]
+    pub firmware: Option<String>,
+    #[table(title = "gateway", display_fn = "display_option")]
+    pub gateway: Option<String>,
+    #[table(title = "first seen")]
+    pub first_seen: NaiveDateTime,
+    #[table(title = "last seen")]
+    pub last_seen: NaiveDateTime,
+}
+
+/// Firmware of a device changed between two collections
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct FirmwareChange {
+    #[table(title = "time")]
+    pub time: NaiveDateTime,
+    #[table(title = "id")]
+    pub device_id: String,
+    #[table(title = "name")]
+    pub marketing_name: String,
+    #[table(title = "from", display_fn = "display_option")]
+    pub from: Option<String>,
+    #[table(title = "to", display_fn = "display_option")]
+    pub to: Option<String>,
+}
+
+fn display_option(value: &Option<String>) -> impl std::fmt::Display {
+    value.clone().unwrap_or("-".to_string())
+}
+
+impl DeviceInfo {
+    /// Same device with the same attributes, ignoring when it was seen
+    pub fn is_unchanged(&self, other: &DeviceInfo) -> bool {
+        self.id == other.id
+            && self.device_type == other.device_type
+            && self.marketing_name == other.marketing_name
+            && self.serial == other.serial
+            && self.firmware == other.firmware
+            && self.gateway == other.gateway
+    }
+}
+
+/// Gateway and emf devices seen at `now`
+pub fn from_api(
+    details: &facility_details::Body,
+    gateway_type: &str,
+    emf_devices: &emf_devices::Root,
+    now: NaiveDateTime,
+) -> Vec<DeviceInfo> {
+    let gateway = DeviceInfo {
+        id: details.serial_number.clone(),
+        device_type: GATEWAY.into(),
+        marketing_name: gateway_type.into(),
+        serial: Some(details.serial_number.clone()),
+        firmware: Some(details.firmware_version.clone()),
+        gateway: Some(gateway_type.into()),
+        first_seen: now,
+        last_seen: now,
+    };
+    let devices = emf_devices.body.iter().map(|d| DeviceInfo {
+        id: d.id.clone(),
+        device_type: d.type_field.to_string(),
+        marketing_name: d.marketing_name.clone(),
+        serial: d.serial_number.clone(),
+        firmware: d.firmware_version.clone(),
+        gateway: Some(gateway_type.into()),
+        first_seen: now,
+        last_seen: now,
+    });
+    std::iter::once(gateway).chain(devices).collect()
+}
+
+/// Calls remote api for given connector and returns the current inventory with the already fetched emf devices
+pub fn collect(
+    conn: &Connector,
+    emf_devices: &emf_devices::Root,
+    now: NaiveDateTime,
+) -> anyhow::Result<Vec<DeviceInfo>> {
+    Ok(from_api(
+        &conn.facility_details()?.body,
+        &conn.gateway_type()?,
+        emf_devices,
+        now,
+    ))
+}
+
+/// Records the devices of a collection.
+///
+/// Unchanged devices are marked as seen, changed or new devices are inserted as a new version.
+/// Returns the firmware changes, which are stored as events too.
+pub fn update(db: &DB, devices: &[DeviceInfo]) -> anyhow::Result<Vec<FirmwareChange>> {
+    db.in_transaction(|| {
+        let known = db.get_devices()?;
+        let mut changes = Vec::new();
+        for device in devices {
+            let previous = known.iter().find(|k| k.id == device.id);
+            match previous {
+                Some(previous) if previous.is_unchanged(device) => {
+                    db.touch_device(&device.id, device.last_seen)?
+                }
+                Some(previous) => {
+                    db.insert_device(device)?;
+                    if previous.firmware != device.firmware {
+                        let change = FirmwareChange {
+                            time: device.last_seen,
+                            device_id: device.id.clone(),
+                            marketing_name: device.marketing_name.clone(),
+                            from: previous.firmware.clone(),
+                            to: device.firmware.clone(),
+                        };
+                        warn!(
+                            "Firmware of {} ({}) changed from {} to {}.",
+                            change.marketing_name,
+                            change.device_id,
+                            display_option(&change.from),
+                            display_option(&change.to)
+                        );
+                        db.insert_firmware_change(&change)?;
+                        changes.push(change);
+                    } else {
+                        info!("Device {} changed.", device.id);
+                    }
+                }
+                None => {
+                    db.insert_device(device)?;
+                    info!("New device {} ({}).", device.marketing_name, device.id);
+                }
+            }
+        }
+        Ok(changes)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    use crate::{db::DB, response::emf_devices};
+
+    use super::{from_api, update, GATEWAY};
+
+    fn time(day: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 3, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + Duration::days(day)
+    }
+
+    fn emf_devices(firmware: &str) -> emf_devices::Root {
+        serde_json::from_value(serde_json::json!({
+            "body": [
+                {
+                    "id": "NoneGateway-LL_HMU03_0351_HP_Platform_Outdoor_Monobloc_PR_EBUS",
+                    "marketingName": "VWL 55/6 A 230V",
+                    "serialNumber": "21213400100211330938018877N5",
+                    "firmwareVersion": firmware,
+                    "type": "HEAT_PUMP",
+                    "reports": []
+                },
+                {
+                    "id": "NoneGateway-LL_VWZ02_0351_HP_Platform_Indoor_Monobloc_PR_EBUS",
+                    "marketingName": "VWZ MEH 97/6",
+                    "type": "BOILER",
+                    "reports": []
+                }
+            ],
+            "meta": {}
+        }))
+        .unwrap()
+    }
+
+    fn details() -> crate::response::facility_details::Body {
+        serde_json::from_value(serde_json::json!({
+            "serialNumber": "21223900202609620938071939N6",
+            "firmwareVersion": "0357.40.32"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn firmware_history() {
+        let db = DB::new(None).unwrap();
+
+        let devices = from_api(&details(), "VR920", &emf_devices("0351.03.01"), time(0));
+        assert_eq!(3, devices.len());
+        assert_eq!(GATEWAY, devices[0].device_type);
+        assert_eq!("VR920", devices[0].marketing_name);
+        assert_eq!("HEAT_PUMP", devices[1].device_type);
+        assert_eq!(None, devices[2].firmware);
+        assert!(update(&db, &devices).unwrap().is_empty());
+
+        // unchanged, only seen again
+        let devices = from_api(&details(), "VR920", &emf_devices("0351.03.01"), time(1));
+        assert!(update(&db, &devices).unwrap().is_empty());
+        let stored = db.get_devices().unwrap();
+        assert_eq!(3, stored.len());
+        assert!(stored
+            .iter()
+            .all(|d| d.first_seen == time(0) && d.last_seen == time(1)));
+
+        // firmware update of the heat pump
+        let devices = from_api(&details(), "VR920", &emf_devices("0351.04.02"), time(2));
+        let changes = update(&db, &devices).unwrap();
+        assert_eq!(1, changes.len());
+        assert_eq!(devices[1].id, changes[0].device_id);
+        assert_eq!(Some("0351.03.01".to_string()), changes[0].from);
+        assert_eq!(Some("0351.04.02".to_string()), changes[0].to);
+        assert_eq!(changes, db.get_firmware_changes().unwrap());
+
+        let heat_pump = db
+            .get_devices()
+            .unwrap()
+            .into_iter()
+            .find(|d| d.id == devices[1].id)
+            .unwrap();
+        assert_eq!(Some("0351.04.02".to_string()), heat_pump.firmware);
+        assert_eq!(time(2), heat_pump.first_seen);
+    }
+}
@@ -0,0 +1,252 @@
+use chrono::{Duration, NaiveDateTime};
+use cli_table::Table;
+
+use crate::{
+    db::{Channel, DB},
+    quality::DEFAULT_MAX_GAP_MINUTES,
+    spf::Period,
+};
+
+/// Default temperature in °C the hot water tank has to reach
+pub const DEFAULT_DISINFECTION_TEMPERATURE: f64 = 60.0;
+/// Default minutes the temperature has to be held
+pub const DEFAULT_HOLD_MINUTES: i64 = 30;
+/// Default days in which at least one disinfection cycle is needed
+pub const DEFAULT_INTERVAL_DAYS: i64 = 7;
+
+/// Requirements for the thermal disinfection of the hot water tank
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Requirement {
+    pub temperature: f64,
+    pub hold_minutes: i64,
+    pub interval_days: i64,
+}
+
+impl Default for Requirement {
+    fn default() -> Self {
+        Self {
+            temperature: DEFAULT_DISINFECTION_TEMPERATURE,
+            hold_minutes: DEFAULT_HOLD_MINUTES,
+            interval_days: DEFAULT_INTERVAL_DAYS,
+        }
+    }
+}
+
+/// Consecutive samples of the hot water tank at or above the disinfection temperature
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Cycle {
+    #[table(title = "start")]
+    pub start: NaiveDateTime,
+    #[table(title = "end")]
+    pub end: NaiveDateTime,
+    /// time between the first and the last sample
+    #[table(title = "minutes")]
+    pub minutes: i64,
+    #[table(title = "max temperature")]
+    pub max_temperature: f64,
+}
+
+/// Compliance of one interval
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Compliance {
+    #[table(title = "from")]
+    pub from: NaiveDateTime,
+    #[table(title = "to")]
+    pub to: NaiveDateTime,
+    /// cycles that held the temperature long enough
+    #[table(title = "cycles")]
+    pub cycles: usize,
+    #[table(title = "first cycle", display_fn = "display_option")]
+    pub first_cycle: Option<NaiveDateTime>,
+    #[table(title = "compliant")]
+    pub compliant: bool,
+}
+
+fn display_option(value: &Option<NaiveDateTime>) -> impl std::fmt::Display {
+    value.map_or("-".to_string(), |v| v.to_string())
+}
+
+/// All disinfection cycles in `[from, to)` that held the temperature long enough.
+///
+/// A cycle ends with the first sample below the temperature or a gap longer than the expected sample interval,
+/// the temperature is unknown in between.
+/// Its duration is measured between the first and the last sample at or above the temperature.
+pub fn cycles(
+    db: &DB,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    requirement: &Requirement,
+) -> anyhow::Result<Vec<Cycle>> {
+    let mut result = Vec::new();
+    let mut current: Option<Cycle> = None;
+    for (time, temperature) in db.channel_values(Channel::Hotwatertank, from, to)? {
+        if current
+            .as_ref()
+            .is_some_and(|c| time - c.end > Duration::minutes(DEFAULT_MAX_GAP_MINUTES))
+        {
+            result.extend(current.take());
+        }
+        if temperature >= requirement.temperature {
+            let cycle = current.get_or_insert(Cycle {
+                start: time,
+                end: time,
+                minutes: 0,
+                max_temperature: temperature,
+            });
+            cycle.end = time;
+            cycle.minutes = (time - cycle.start).num_minutes();
+            cycle.max_temperature = cycle.max_temperature.max(temperature);
+        } else if let Some(cycle) = current.take() {
+            result.push(cycle);
+        }
+    }
+    result.extend(current);
+
+    Ok(result
+        .into_iter()
+        .filter(|c| c.minutes >= requirement.hold_minutes)
+        .collect())
+}
+
+/// Compliance history of the period in intervals of `interval_days`.
+///
+/// The last interval ends with the period and may be shorter.
+pub fn history(
+    db: &DB,
+    period: Period,
+    requirement: &Requirement,
+) -> anyhow::Result<Vec<Compliance>> {
+    let (from, to) = period.range()?;
+    let cycles = cycles(db, from, to, requirement)?;
+
+    let mut result = Vec::new();
+    let mut start = from;
+    while start < to {
+        let end = (start + Duration::days(requirement.interval_days.max(1))).min(to);
+        let in_interval: Vec<&Cycle> = cycles
+            .iter()
+            .filter(|c| c.start >= start && c.start < end)
+            .collect();
+        result.push(Compliance {
+            from: start,
+            to: end,
+            cycles: in_interval.len(),
+            first_cycle: in_interval.first().map(|c| c.start),
+            compliant: !in_interval.is_empty(),
+        });
+        start = end;
+    }
+    Ok(result)
+}
+
+/// Last disinfection cycle in the `interval_days` before `now`, None if the cycle was missed.
+///
+/// The window starts `hold_minutes` earlier, so a cycle in progress at its start is held completely.
+pub fn last_cycle(
+    db: &DB,
+    now: NaiveDateTime,
+    requirement: &Requirement,
+) -> anyhow::Result<Option<Cycle>> {
+    Ok(cycles(
+        db,
+        now - Duration::days(requirement.interval_days)
+            - Duration::minutes(requirement.hold_minutes),
+        now + Duration::seconds(1),
+        requirement,
+    )?
+    .pop())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    use crate::{
+        db::{SensorData, DB},
+        spf::Period,
+    };
+
+    use super::{cycles, history, last_cycle, Requirement};
+
+    fn time(day: u32, minute: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 5, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + Duration::minutes(minute)
+    }
+
+    fn insert(db: &DB, time: NaiveDateTime, hotwatertank: f64) {
+        db.insert_sensor_data_at(
+            time,
+            SensorData::new_raw(None, Some(hotwatertank), None, None),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn compliance_history() {
+        let db = DB::new(None).unwrap();
+        let requirement = Requirement::default();
+
+        // day 2: 60° held for 40 minutes
+        for (minute, t) in [(0, 55.0), (10, 60.0), (30, 62.0), (50, 61.0), (60, 50.0)] {
+            insert(&db, time(2, minute), t);
+        }
+        // day 9: 60° only held for 20 minutes
+        for (minute, t) in [(0, 60.0), (10, 61.0), (20, 60.0), (30, 58.0)] {
+            insert(&db, time(9, minute), t);
+        }
+
+        let result = history(
+            &db,
+            Period::Month {
+                year: 2023,
+                month: 5,
+            },
+            &requirement,
+        )
+        .unwrap();
+        assert_eq!(5, result.len());
+        assert!(result[0].compliant);
+        assert_eq!(Some(time(2, 10)), result[0].first_cycle);
+        assert!(!result[1].compliant);
+        // 29th to 31st
+        assert_eq!(time(29, 0), result[4].from);
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2023, 6, 1).unwrap(),
+            result[4].to.date()
+        );
+
+        let cycle = last_cycle(&db, time(8, 0), &requirement).unwrap().unwrap();
+        assert_eq!(40, cycle.minutes);
+        assert_eq!(62.0, cycle.max_temperature);
+        // cycle started before the interval, but within the hold time
+        assert!(last_cycle(&db, time(9, 40), &requirement)
+            .unwrap()
+            .is_some());
+        assert!(last_cycle(&db, time(10, 0), &requirement)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn gap_ends_cycle() {
+        let db = DB::new(None).unwrap();
+        let requirement = Requirement::default();
+
+        // no samples for an hour, the temperature in between is unknown
+        for (minute, t) in [(0, 61.0), (10, 62.0), (70, 61.0), (80, 60.0), (90, 50.0)] {
+            insert(&db, time(2, minute), t);
+        }
+        let result = cycles(&db, time(2, 0), time(3, 0), &requirement).unwrap();
+        assert!(result.is_empty());
+
+        for minute in [20, 30, 40, 50, 60] {
+            insert(&db, time(2, minute), 60.0);
+        }
+        let result = cycles(&db, time(2, 0), time(3, 0), &requirement).unwrap();
+        assert_eq!(1, result.len());
+        assert_eq!(80, result[0].minutes);
+    }
+}
@@ -1,8 +1,25 @@
+pub mod alert;
 pub mod array2d;
 pub mod connector;
 pub mod db;
+pub mod degree_days;
+pub mod emissions;
+pub mod export;
+pub mod external;
+pub mod faults;
+pub mod heating_curve;
+pub mod inventory;
+pub mod legionella;
+pub mod meter;
+pub mod notify;
+pub mod quality;
 pub mod request;
 pub mod response;
+pub mod retention;
+pub mod spf;
+pub mod tariff;
+pub mod timeprogram;
+pub mod units;
 pub mod urls;
 pub mod yp;
 
@@ -0,0 +1,268 @@
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDateTime};
+use cli_table::Table;
+use iso8601_timestamp::Timestamp;
+use itertools::Itertools;
+use strum_macros::Display;
+
+use crate::{
+    db::{Channel, DB},
+    response::emf_devices::{self, EmfDevice, EmfFunction, EnergyType},
+};
+
+/// Lifetime meter reading of one device for an energy type and function.
+///
+/// The reading is cumulative, counting from `from` until `to`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterReading {
+    pub device_id: String,
+    pub device: EmfDevice,
+    pub energy_type: EnergyType,
+    pub function: EmfFunction,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub value: f64,
+}
+
+impl MeterReading {
+    /// Flattens the reports of all emf devices into meter readings
+    pub fn from_emf_devices(emf_devices: &emf_devices::Root) -> anyhow::Result<Vec<MeterReading>> {
+        let mut result = Vec::new();
+        for device in &emf_devices.body {
+            for report in &device.reports {
+                result.push(MeterReading {
+                    device_id: device.id.clone(),
+                    device: device.type_field.clone(),
+                    energy_type: report.energy_type.clone(),
+                    function: report.function.clone(),
+                    from: timestamp_to_naive(report.from)?,
+                    to: timestamp_to_naive(report.to)?,
+                    value: report.current_meter_reading,
+                });
+            }
+        }
+        Ok(result)
+    }
+}
+
+// convert iso8601 Timestamp to NaiveDateTime in UTC
+fn timestamp_to_naive(ts: Timestamp) -> anyhow::Result<NaiveDateTime> {
+    DateTime::from_timestamp(ts.duration_since(Timestamp::UNIX_EPOCH).whole_seconds(), 0)
+        .map(|t| t.naive_utc())
+        .ok_or(anyhow!("out-of-range timestamp in meter reading"))
+}
+
+/// Channel that holds the daily values for given device, energy type and function.
+///
+/// Returns None if the daily values are not stored.
+pub fn usage_channel(
+    device: EmfDevice,
+    energy_type: EnergyType,
+    function: EmfFunction,
+) -> Option<Channel> {
+    use EmfDevice::*;
+    use EmfFunction::*;
+    use EnergyType::*;
+    match (function, device, energy_type) {
+        (CentralHeating, HeatPump, EnvironmentalYield) => Some(Channel::ChHpY),
+        (CentralHeating, HeatPump, ConsumedElectricalPower) => Some(Channel::ChHpP),
+        (CentralHeating, Boiler, ConsumedElectricalPower) => Some(Channel::ChBoP),
+        (DomesticHotWater, HeatPump, EnvironmentalYield) => Some(Channel::HwHpY),
+        (DomesticHotWater, HeatPump, ConsumedElectricalPower) => Some(Channel::HwHpP),
+        (DomesticHotWater, Boiler, ConsumedElectricalPower) => Some(Channel::HwBoP),
+        (Cooling, HeatPump, EnvironmentalYield) => Some(Channel::CoHpY),
+        (Cooling, HeatPump, ConsumedElectricalPower) => Some(Channel::CoHpP),
+        (EmfFunction::Ventilation, EmfDevice::Ventilation, ConsumedElectricalPower) => {
+            Some(Channel::VeP)
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+pub enum ReconcileStatus {
+    Ok,
+    /// daily usage is missing values compared to the meter
+    Gap,
+    /// daily usage counts more than the meter
+    DoubleCounting,
+}
+
+/// Comparison of the summed daily usage with the change of the meter readings in the same period
+#[derive(Debug, Table)]
+pub struct Reconciliation {
+    #[table(title = "function")]
+    pub function: EmfFunction,
+    #[table(title = "device")]
+    pub device: EmfDevice,
+    #[table(title = "energy type")]
+    pub energy_type: EnergyType,
+    #[table(title = "from")]
+    pub from: NaiveDateTime,
+    #[table(title = "to")]
+    pub to: NaiveDateTime,
+    #[table(title = "meter delta")]
+    pub meter_delta: f64,
+    #[table(title = "usage sum")]
+    pub usage_sum: f64,
+    #[table(title = "difference")]
+    pub difference: f64,
+    #[table(title = "status")]
+    pub status: ReconcileStatus,
+}
+
+/// Compare the stored daily usage with the stored meter readings.
+///
+/// Readings of devices with the same type are summed up, since the Usage table doesn't distinguish between them.
+/// The daily usage is summed over `[first reading, last reading)`.
+/// Readings are not aligned to day boundaries, while the daily usage is only known per day.
+/// So the usage of the days of the first and last reading is counted fully or not at all,
+/// which is an error of up to one day of usage at each end. Choose `tolerance` accordingly
+/// or compare over long periods.
+/// `tolerance` is the accepted relative difference to the meter delta, e.g. 0.05 for 5%.
+pub fn reconcile(db: &DB, tolerance: f64) -> anyhow::Result<Vec<Reconciliation>> {
+    let readings = db.get_meter_readings()?;
+
+    let mut result = Vec::new();
+    for ((function, device, energy_type), group) in &readings
+        .iter()
+        .sorted_by_key(|r| {
+            (
+                r.function.as_ref(),
+                r.device.as_ref(),
+                r.energy_type.as_ref(),
+            )
+        })
+        .group_by(|r| (r.function.clone(), r.device.clone(), r.energy_type.clone()))
+    {
+        let channel = match usage_channel(device.clone(), energy_type.clone(), function.clone()) {
+            Some(c) => c,
+            None => continue,
+        };
+        let group = group.collect_vec();
+
+        // first and last reading of every device in this group
+        let mut meter_delta = 0.0;
+        let mut from: Option<NaiveDateTime> = None;
+        let mut to: Option<NaiveDateTime> = None;
+        for (_, device_readings) in &group
+            .iter()
+            .sorted_by_key(|r| (r.device_id.as_str(), r.to))
+            .group_by(|r| r.device_id.as_str())
+        {
+            let device_readings = device_readings.collect_vec();
+            // SAFTEY group_by never yields an empty group
+            let first = device_readings.first().unwrap();
+            let last = device_readings.last().unwrap();
+            if first.to == last.to {
+                continue;
+            }
+            meter_delta += last.value - first.value;
+            from = Some(from.map_or(first.to, |f| f.min(first.to)));
+            to = Some(to.map_or(last.to, |t| t.max(last.to)));
+        }
+
+        // need at least two readings to compare
+        let (from, to) = match (from, to) {
+            (Some(from), Some(to)) => (from, to),
+            _ => continue,
+        };
+
+        let usage_sum: f64 = db
+            .channel_values(channel, from, to)?
+            .iter()
+            .map(|(_, v)| v)
+            .sum();
+        let difference = usage_sum - meter_delta;
+        let status = if difference.abs() <= meter_delta.abs() * tolerance {
+            ReconcileStatus::Ok
+        } else if difference < 0.0 {
+            ReconcileStatus::Gap
+        } else {
+            ReconcileStatus::DoubleCounting
+        };
+
+        result.push(Reconciliation {
+            function,
+            device,
+            energy_type,
+            from,
+            to,
+            meter_delta,
+            usage_sum,
+            difference,
+            status,
+        });
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use crate::{
+        db::DB,
+        response::emf_devices::{EmfDevice, EmfFunction, EnergyType},
+        yp::YpData,
+    };
+
+    use super::{reconcile, MeterReading, ReconcileStatus};
+
+    fn day(d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 3, d)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    fn reading(to: u32, value: f64) -> MeterReading {
+        MeterReading {
+            device_id: "hp".into(),
+            device: EmfDevice::HeatPump,
+            energy_type: EnergyType::ConsumedElectricalPower,
+            function: EmfFunction::CentralHeating,
+            from: day(1),
+            to: day(to),
+            value,
+        }
+    }
+
+    fn usage(d: u32, ch_hp_p: f64) -> YpData {
+        YpData {
+            ts: day(d),
+            ch_hp_p,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reconcile_test() {
+        let db = DB::new(None).unwrap();
+        db.insert_meter_readings(&[reading(10, 10000.0), reading(13, 16000.0)])
+            .unwrap();
+
+        // day 12 is missing
+        db.insert_yp_data(&usage(10, 2000.0)).unwrap();
+        db.insert_yp_data(&usage(11, 2000.0)).unwrap();
+        // day 13 is outside of the compared period
+        db.insert_yp_data(&usage(13, 2000.0)).unwrap();
+
+        let result = reconcile(&db, 0.05).unwrap();
+        assert_eq!(1, result.len());
+        assert_eq!(6000.0, result[0].meter_delta);
+        assert_eq!(4000.0, result[0].usage_sum);
+        assert_eq!(ReconcileStatus::Gap, result[0].status);
+
+        // day 12 is counted
+        db.insert_yp_data(&usage(12, 2000.0)).unwrap();
+        let result = reconcile(&db, 0.05).unwrap();
+        assert_eq!(ReconcileStatus::Ok, result[0].status);
+
+        // day 12 is counted twice
+        db.insert_yp_data(&usage(12, 4000.0)).unwrap();
+        let result = reconcile(&db, 0.05).unwrap();
+        assert_eq!(ReconcileStatus::DoubleCounting, result[0].status);
+    }
+}
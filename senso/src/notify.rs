@@ -0,0 +1,481 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use log::info;
+use serde::Deserialize;
+
+use crate::alert::Alert;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Destination alerts are sent to
+pub trait Sink {
+    fn send(&self, alert: &Alert) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    Stdout,
+    /// Local SMTP relay without authentication
+    Smtp {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        from: String,
+        to: Vec<String>,
+    },
+    /// Alert is posted as json
+    Webhook {
+        url: String,
+    },
+    /// Alert is published as json with QoS 0
+    Mqtt {
+        host: String,
+        #[serde(default = "default_mqtt_port")]
+        port: u16,
+        topic: String,
+        #[serde(default = "default_client_id")]
+        client_id: String,
+    },
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_client_id() -> String {
+    "senso".into()
+}
+
+impl SinkConfig {
+    pub fn build(&self) -> Box<dyn Sink> {
+        match self.clone() {
+            SinkConfig::Stdout => Box::new(Stdout),
+            SinkConfig::Smtp {
+                host,
+                port,
+                from,
+                to,
+            } => Box::new(Smtp {
+                host,
+                port,
+                from,
+                to,
+            }),
+            SinkConfig::Webhook { url } => Box::new(Webhook { url }),
+            SinkConfig::Mqtt {
+                host,
+                port,
+                topic,
+                client_id,
+            } => Box::new(Mqtt {
+                host,
+                port,
+                topic,
+                client_id,
+            }),
+        }
+    }
+}
+
+pub struct Stdout;
+
+impl Sink for Stdout {
+    fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        println!("{} [{}] {}", alert.time, alert.rule, alert.message);
+        Ok(())
+    }
+}
+
+pub struct Smtp {
+    pub host: String,
+    pub port: u16,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl Smtp {
+    // sends a command and checks the reply code
+    fn command(
+        stream: &mut TcpStream,
+        reader: &mut BufReader<TcpStream>,
+        command: &str,
+        expected: &str,
+    ) -> anyhow::Result<()> {
+        if !command.is_empty() {
+            stream.write_all(command.as_bytes())?;
+            stream.write_all(b"\r\n")?;
+        }
+        // multiline replies have a '-' after the code
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            if !line.starts_with(expected) {
+                return Err(anyhow!("SMTP server replied \"{}\".", line.trim_end()));
+            }
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+
+    // line breaks in an address would inject commands or headers
+    fn address(address: &str) -> anyhow::Result<&str> {
+        if address.contains(['\r', '\n']) {
+            return Err(anyhow!(
+                "Invalid mail address \"{}\".",
+                address.escape_debug()
+            ));
+        }
+        Ok(address)
+    }
+}
+
+// header values must be a single line
+fn header_value(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+impl Sink for Smtp {
+    fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        let from = Self::address(&self.from)?;
+        let to = self
+            .to
+            .iter()
+            .map(|to| Self::address(to))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        Self::command(&mut stream, &mut reader, "", "220")?;
+        Self::command(&mut stream, &mut reader, "HELO senso", "250")?;
+        Self::command(
+            &mut stream,
+            &mut reader,
+            &format!("MAIL FROM:<{}>", from),
+            "250",
+        )?;
+        for to in &to {
+            Self::command(
+                &mut stream,
+                &mut reader,
+                &format!("RCPT TO:<{}>", to),
+                "250",
+            )?;
+        }
+        Self::command(&mut stream, &mut reader, "DATA", "354")?;
+
+        let mut data = format!(
+            "From: {}\r\nTo: {}\r\nSubject: [senso] {}\r\n\r\n",
+            from,
+            to.join(", "),
+            header_value(&alert.rule)
+        );
+        for line in format!("{}\n{}", alert.time, alert.message).lines() {
+            // lines starting with a dot are escaped
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push('.');
+        Self::command(&mut stream, &mut reader, &data, "250")?;
+        Self::command(&mut stream, &mut reader, "QUIT", "221")?;
+
+        info!("Sent alert \"{}\" by mail.", alert.rule);
+        Ok(())
+    }
+}
+
+pub struct Webhook {
+    pub url: String,
+}
+
+impl Sink for Webhook {
+    fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        ureq::post(&self.url)
+            .timeout(TIMEOUT)
+            .send_json(serde_json::to_value(alert)?)?;
+        info!("Sent alert \"{}\" to webhook.", alert.rule);
+        Ok(())
+    }
+}
+
+pub struct Mqtt {
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+    pub client_id: String,
+}
+
+// largest length the 4 bytes of the variable length header can encode
+const MQTT_MAX_REMAINING_LENGTH: usize = 268_435_455;
+
+// MQTT 3.1.1 packet with variable length header
+fn mqtt_packet(packet_type: u8, body: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if body.len() > MQTT_MAX_REMAINING_LENGTH {
+        return Err(anyhow!(
+            "MQTT packet of {} bytes is longer than {} bytes.",
+            body.len(),
+            MQTT_MAX_REMAINING_LENGTH
+        ));
+    }
+    let mut packet = vec![packet_type];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    Ok(packet)
+}
+
+// length prefixed utf-8 string
+fn mqtt_string(s: &str) -> anyhow::Result<Vec<u8>> {
+    let len = u16::try_from(s.len()).map_err(|_| {
+        anyhow!(
+            "MQTT string of {} bytes is longer than {} bytes.",
+            s.len(),
+            u16::MAX
+        )
+    })?;
+    let mut result = len.to_be_bytes().to_vec();
+    result.extend_from_slice(s.as_bytes());
+    Ok(result)
+}
+
+impl Mqtt {
+    fn connect_packet(&self) -> anyhow::Result<Vec<u8>> {
+        let mut body = mqtt_string("MQTT")?;
+        // protocol level 4, clean session, keep alive 60s
+        body.extend_from_slice(&[4, 0x02, 0, 60]);
+        body.extend(mqtt_string(&self.client_id)?);
+        mqtt_packet(0x10, &body)
+    }
+
+    fn publish_packet(&self, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut body = mqtt_string(&self.topic)?;
+        body.extend_from_slice(payload);
+        mqtt_packet(0x30, &body)
+    }
+}
+
+impl Sink for Mqtt {
+    fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        // rejected before connecting
+        let connect = self.connect_packet()?;
+        let publish = self.publish_packet(&serde_json::to_vec(alert)?)?;
+
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+
+        stream.write_all(&connect)?;
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack)?;
+        if connack[0] != 0x20 || connack[3] != 0 {
+            return Err(anyhow!(
+                "MQTT broker refused connection with code {}.",
+                connack[3]
+            ));
+        }
+
+        stream.write_all(&publish)?;
+        stream.write_all(&mqtt_packet(0xE0, &[])?)?;
+
+        info!("Published alert \"{}\" to {}.", alert.rule, self.topic);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use chrono::NaiveDate;
+
+    use crate::{alert::Alert, db::Channel};
+
+    use super::{header_value, mqtt_packet, Mqtt, Sink, Smtp, Webhook};
+
+    fn alert() -> Alert {
+        Alert {
+            rule: "pressure".into(),
+            channel: Some(Channel::Waterpressure),
+            time: NaiveDate::from_ymd_opt(2023, 3, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+            value: Some(0.8),
+            message: ".waterpressure is 0.8".into(),
+        }
+    }
+
+    #[test]
+    fn mqtt_remaining_length() {
+        assert_eq!(vec![0xE0, 0], mqtt_packet(0xE0, &[]).unwrap());
+        let packet = mqtt_packet(0x30, &[0; 321]).unwrap();
+        // 321 = 65 + 2 * 128
+        assert_eq!(vec![0x30, 0xC1, 0x02], packet[..3].to_vec());
+        assert_eq!(324, packet.len());
+    }
+
+    #[test]
+    fn mqtt_too_long() {
+        let err = Mqtt {
+            host: "127.0.0.1".into(),
+            port: 0,
+            topic: "a".repeat(usize::from(u16::MAX) + 1),
+            client_id: "senso".into(),
+        }
+        .send(&alert())
+        .unwrap_err();
+        assert_eq!(
+            "MQTT string of 65536 bytes is longer than 65535 bytes.",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn mqtt_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            let mut connect = [0u8; 19];
+            stream.read_exact(&mut connect).unwrap();
+            stream.write_all(&[0x20, 0x02, 0, 0]).unwrap();
+            stream.read_to_end(&mut received).unwrap();
+            (connect.to_vec(), received)
+        });
+
+        let mqtt = Mqtt {
+            host: "127.0.0.1".into(),
+            port,
+            topic: "senso/alert".into(),
+            client_id: "senso".into(),
+        };
+        mqtt.send(&alert()).unwrap();
+
+        let (connect, received) = broker.join().unwrap();
+        assert_eq!(mqtt.connect_packet().unwrap(), connect);
+        assert_eq!(0x30, received[0]);
+        assert!(String::from_utf8_lossy(&received).contains("\"rule\":\"pressure\""));
+        assert_eq!(vec![0xE0, 0], received[received.len() - 2..].to_vec());
+    }
+
+    #[test]
+    fn smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut lines = Vec::new();
+            stream.write_all(b"220 localhost\r\n").unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        b""
+                    }
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    b"221 bye\r\n"
+                } else if line.starts_with("HELO") {
+                    b"250-localhost\r\n250 ok\r\n"
+                } else {
+                    b"250 ok\r\n"
+                };
+                stream.write_all(reply).unwrap();
+                lines.push(line);
+            }
+            lines
+        });
+
+        Smtp {
+            host: "127.0.0.1".into(),
+            port,
+            from: "senso@localhost".into(),
+            to: vec!["a@localhost".into(), "b@localhost".into()],
+        }
+        .send(&alert())
+        .unwrap();
+
+        let lines = server.join().unwrap();
+        assert!(lines.contains(&"RCPT TO:<b@localhost>".to_string()));
+        assert!(lines.contains(&"Subject: [senso] pressure".to_string()));
+        assert!(lines.contains(&"..waterpressure is 0.8".to_string()));
+        assert_eq!(Some(&"QUIT".to_string()), lines.last());
+    }
+
+    #[test]
+    fn smtp_line_breaks() {
+        assert_eq!(
+            "pressure  Bcc: x@localhost",
+            header_value("pressure\r\nBcc: x@localhost")
+        );
+        // rejected before connecting
+        let err = Smtp {
+            host: "127.0.0.1".into(),
+            port: 0,
+            from: "senso@localhost".into(),
+            to: vec!["a@localhost>\r\nRCPT TO:<b@localhost".into()],
+        }
+        .send(&alert())
+        .unwrap_err();
+        assert!(err.to_string().starts_with("Invalid mail address"));
+    }
+
+    #[test]
+    fn webhook() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/alert")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"rule": "pressure", "channel": "waterpressure", "value": 0.8}"#.into(),
+            ))
+            .with_status(200)
+            .create();
+
+        Webhook {
+            url: format!("{}/alert", server.url()),
+        }
+        .send(&alert())
+        .unwrap();
+        mock.assert();
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, NaiveDateTime};
+use cli_table::Table;
+use itertools::Itertools;
+use strum_macros::{AsRefStr, Display};
+
+use crate::{
+    db::{Channel, SensorSample, DB, SENSOR_CHANNELS},
+    spf::Period,
+};
+
+/// Default minutes between two samples of the sensor above which samples are missing
+pub const DEFAULT_MAX_GAP_MINUTES: i64 = 30;
+
+/// Channel and `[min, max]` of plausible values
+pub const PLAUSIBLE_RANGES: [(Channel, f64, f64); 15] = [
+    (Channel::Outdoor, -50.0, 50.0),
+    (Channel::Hotwatertank, 0.0, 100.0),
+    (Channel::Waterpressure, 0.0, 4.0),
+    (Channel::Heatingcircuit, 0.0, 100.0),
+    (Channel::Co2, 0.0, 10000.0),
+    (Channel::Humidity, 0.0, 100.0),
+    (Channel::ChHpY, 0.0, f64::MAX),
+    (Channel::ChHpP, 0.0, f64::MAX),
+    (Channel::ChBoP, 0.0, f64::MAX),
+    (Channel::HwHpY, 0.0, f64::MAX),
+    (Channel::HwHpP, 0.0, f64::MAX),
+    (Channel::HwBoP, 0.0, f64::MAX),
+    (Channel::CoHpY, 0.0, f64::MAX),
+    (Channel::CoHpP, 0.0, f64::MAX),
+    (Channel::VeP, 0.0, f64::MAX),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsRefStr, Display)]
+pub enum IssueKind {
+    /// no sample of the sensor for longer than the max gap
+    MissingSamples,
+    /// consecutive samples without a value for a column
+    NullRun,
+    /// day without a row in the Usage table
+    MissingUsageDay,
+    /// value outside of the plausible range
+    Implausible,
+    /// timestamp stored more than once
+    DuplicateTimestamp,
+}
+
+/// Single problem of the stored time series
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Finding {
+    #[table(title = "kind")]
+    pub kind: IssueKind,
+    #[table(title = "table")]
+    pub table: &'static str,
+    #[table(title = "column")]
+    pub column: &'static str,
+    #[table(title = "from")]
+    pub from: NaiveDateTime,
+    #[table(title = "to")]
+    pub to: NaiveDateTime,
+    /// affected rows, days for missing usage
+    #[table(title = "count")]
+    pub count: i64,
+    #[table(title = "detail")]
+    pub detail: String,
+}
+
+/// Number of findings of one kind
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Summary {
+    #[table(title = "kind")]
+    pub kind: IssueKind,
+    #[table(title = "findings")]
+    pub findings: usize,
+    #[table(title = "count")]
+    pub count: i64,
+}
+
+/// Checks the stored time series of the period.
+///
+/// Days of the period in the future are not checked for usage.
+pub fn check(db: &DB, period: Period, max_gap_minutes: i64) -> anyhow::Result<Vec<Finding>> {
+    let (from, to) = period.range()?;
+    let mut result = Vec::new();
+
+    // sampling gaps and NULL runs
+    let samples = db.sensor_samples(from, to)?;
+    result.extend(missing_samples(&samples, max_gap_minutes));
+    // channels without any value, e.g. air quality without ventilation, are not collected
+    for channel in SENSOR_CHANNELS {
+        if samples.iter().any(|s| s.data.value(channel).is_some()) {
+            result.extend(null_runs(channel, &samples));
+        }
+    }
+
+    // days without usage
+    let today = chrono::Utc::now().naive_utc().date().and_hms_opt(0, 0, 0);
+    let usage_to = today.map_or(to, |t| t.min(to));
+    let days: HashSet<NaiveDateTime> = db
+        .get_yp_data(from, usage_to)?
+        .into_iter()
+        .map(|y| y.ts)
+        .collect();
+    let mut day = from;
+    while day < usage_to {
+        if !days.contains(&day) {
+            result.push(Finding {
+                kind: IssueKind::MissingUsageDay,
+                table: "Usage",
+                column: "",
+                from: day,
+                to: day + Duration::days(1),
+                count: 1,
+                detail: String::new(),
+            });
+        }
+        day += Duration::days(1);
+    }
+
+    for (channel, min, max) in PLAUSIBLE_RANGES {
+        for (time, value) in db.channel_values(channel, from, to)? {
+            if value < min || value > max {
+                result.push(Finding {
+                    kind: IssueKind::Implausible,
+                    table: channel.table(),
+                    column: channel.column(),
+                    from: time,
+                    to: time,
+                    count: 1,
+                    detail: format!("{} outside of [{}, {}]", value, min, max),
+                });
+            }
+        }
+    }
+
+    for table in ["Temperature", "Usage"] {
+        for (time, count) in db.duplicate_times(table, from, to)? {
+            result.push(Finding {
+                kind: IssueKind::DuplicateTimestamp,
+                table,
+                column: "",
+                from: time,
+                to: time,
+                count,
+                detail: String::new(),
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+fn missing_samples(samples: &[SensorSample], max_gap_minutes: i64) -> Vec<Finding> {
+    samples
+        .iter()
+        .map(|s| &s.time)
+        .tuple_windows()
+        .filter(|(a, b)| **b - **a > Duration::minutes(max_gap_minutes))
+        .map(|(a, b)| Finding {
+            kind: IssueKind::MissingSamples,
+            table: "Temperature",
+            column: "",
+            from: *a,
+            to: *b,
+            count: (*b - *a).num_minutes() / max_gap_minutes.max(1),
+            detail: format!("{} minutes without sample", (*b - *a).num_minutes()),
+        })
+        .collect()
+}
+
+fn null_runs(channel: Channel, samples: &[SensorSample]) -> Vec<Finding> {
+    let mut result = Vec::new();
+    for (is_null, run) in &samples.iter().group_by(|s| s.data.value(channel).is_none()) {
+        if !is_null {
+            continue;
+        }
+        let run = run.collect_vec();
+        // SAFTEY group_by never yields an empty group
+        let first = run.first().unwrap();
+        let last = run.last().unwrap();
+        result.push(Finding {
+            kind: IssueKind::NullRun,
+            table: channel.table(),
+            column: channel.column(),
+            from: first.time,
+            to: last.time,
+            count: run.len() as i64,
+            detail: String::new(),
+        });
+    }
+    result
+}
+
+/// Number of findings and affected rows per kind
+pub fn summarize(findings: &[Finding]) -> Vec<Summary> {
+    findings
+        .iter()
+        .sorted_by(|a, b| a.kind.as_ref().cmp(b.kind.as_ref()))
+        .group_by(|f| f.kind)
+        .into_iter()
+        .map(|(kind, group)| {
+            let group = group.collect_vec();
+            Summary {
+                kind,
+                findings: group.len(),
+                count: group.iter().map(|f| f.count).sum(),
+            }
+        })
+        .collect()
+}
+
+/// Queues the days without usage for backfill. Returns the number of newly queued days.
+pub fn queue_backfills(db: &DB, findings: &[Finding]) -> anyhow::Result<usize> {
+    let mut queued = 0;
+    for finding in findings
+        .iter()
+        .filter(|f| f.kind == IssueKind::MissingUsageDay)
+    {
+        if db.queue_backfill(finding.from, finding.kind.as_ref())? {
+            queued += 1;
+        }
+    }
+    Ok(queued)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    use crate::{
+        db::{SensorData, DB},
+        spf::Period,
+        yp::YpData,
+    };
+
+    use super::{check, queue_backfills, summarize, IssueKind};
+
+    fn time(day: u32, minute: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 1, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + Duration::minutes(minute)
+    }
+
+    #[test]
+    fn quality_report() {
+        let db = DB::new(None).unwrap();
+        let ok = || SensorData::new_raw(Some(5.0), Some(50.0), Some(1.5), Some(35.0));
+
+        db.insert_sensor_data_at(time(1, 0), ok()).unwrap();
+        // status and live report failed
+        db.insert_sensor_data_at(time(1, 15), SensorData::new_raw(None, None, None, None))
+            .unwrap();
+        db.insert_sensor_data_at(time(1, 30), SensorData::new_raw(None, None, None, None))
+            .unwrap();
+        // gap of 2 hours, pressure too high, stored twice
+        db.insert_sensor_data_at(
+            time(1, 150),
+            SensorData::new_raw(Some(5.0), Some(50.0), Some(4.5), Some(35.0)),
+        )
+        .unwrap();
+        db.insert_sensor_data_at(time(1, 150), ok()).unwrap();
+
+        // day 2 is missing, negative yield on day 3
+        db.insert_yp_data(&YpData {
+            ts: time(1, 0),
+            ..Default::default()
+        })
+        .unwrap();
+        db.insert_yp_data(&YpData {
+            ts: time(3, 0),
+            ch_hp_y: -100.0,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let period = Period::Range {
+            from: time(1, 0).date(),
+            to: time(4, 0).date(),
+        };
+        let findings = check(&db, period, 30).unwrap();
+        let count = |kind: IssueKind| findings.iter().filter(|f| f.kind == kind).count();
+        assert_eq!(1, count(IssueKind::MissingSamples));
+        assert_eq!(4, count(IssueKind::NullRun));
+        assert_eq!(1, count(IssueKind::MissingUsageDay));
+        assert_eq!(2, count(IssueKind::Implausible));
+        assert_eq!(1, count(IssueKind::DuplicateTimestamp));
+
+        let summary = summarize(&findings);
+        assert_eq!(5, summary.len());
+        let null_runs = summary
+            .iter()
+            .find(|s| s.kind == IssueKind::NullRun)
+            .unwrap();
+        assert_eq!(8, null_runs.count);
+
+        assert_eq!(1, queue_backfills(&db, &findings).unwrap());
+        // already queued
+        assert_eq!(0, queue_backfills(&db, &findings).unwrap());
+        assert_eq!(vec![time(2, 0)], db.pending_backfills().unwrap());
+        db.finish_backfill(time(2, 0)).unwrap();
+        assert!(db.pending_backfills().unwrap().is_empty());
+    }
+}
@@ -85,6 +85,358 @@ pub mod emf {
     }
 }
 
+pub mod system {
+    use chrono::NaiveDate;
+    use serde::Serialize;
+    use strum_macros::{AsRefStr, Display, EnumString};
+
+    use crate::{
+        connector::ApiError,
+        response::system::{QuickMode, Setting},
+    };
+
+    /// Limits of the hot water temperature setpoint in °C
+    pub const DHW_SETPOINT_MIN: f64 = 35.0;
+    pub const DHW_SETPOINT_MAX: f64 = 70.0;
+
+    // checks that value is in [min, max]
+    pub(crate) fn check_range(
+        name: &'static str,
+        value: f64,
+        min: f64,
+        max: f64,
+    ) -> Result<f64, ApiError> {
+        if value.is_nan() || value < min || value > max {
+            return Err(ApiError::OutOfRange {
+                name,
+                value,
+                min,
+                max,
+            });
+        }
+        Ok(value)
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    pub struct DhwSetpoint {
+        temperature_setpoint: f64,
+    }
+
+    impl DhwSetpoint {
+        pub fn new(temperature: f64) -> Result<Self, ApiError> {
+            Ok(Self {
+                temperature_setpoint: check_range(
+                    "hot water setpoint",
+                    temperature,
+                    DHW_SETPOINT_MIN,
+                    DHW_SETPOINT_MAX,
+                )?,
+            })
+        }
+    }
+
+    /// Operating modes the hot water can be set to
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, AsRefStr, Display, EnumString)]
+    #[serde(rename_all = "UPPERCASE")]
+    #[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+    pub enum DhwMode {
+        Auto,
+        On,
+        Off,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    pub struct DhwOperationMode {
+        pub operation_mode: DhwMode,
+    }
+
+    /// Limits of the fan level of a ventilation
+    pub const FAN_LEVEL_MIN: u8 = 1;
+    pub const FAN_LEVEL_MAX: u8 = 6;
+
+    /// Operating modes the ventilation can be set to
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, AsRefStr, Display, EnumString)]
+    #[serde(rename_all = "UPPERCASE")]
+    #[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+    pub enum VentilationMode {
+        Auto,
+        Day,
+        Night,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    pub struct VentilationOperationMode {
+        pub operation_mode: VentilationMode,
+    }
+
+    /// Fan stage used in DAY or NIGHT setting
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    pub struct FanLevel {
+        level: u8,
+    }
+
+    impl FanLevel {
+        pub fn new(level: u8) -> Result<Self, ApiError> {
+            Ok(Self {
+                level: check_range(
+                    "fan level",
+                    level as f64,
+                    FAN_LEVEL_MIN as f64,
+                    FAN_LEVEL_MAX as f64,
+                )? as u8,
+            })
+        }
+    }
+
+    /// Limits of zone and holiday temperatures in °C
+    pub const ZONE_SETPOINT_MIN: f64 = 5.0;
+    pub const ZONE_SETPOINT_MAX: f64 = 30.0;
+    /// Limits of the quick veto duration in minutes
+    pub const QUICK_VETO_MIN_MINUTES: i64 = 30;
+    pub const QUICK_VETO_MAX_MINUTES: i64 = 1440;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    pub struct ZoneSetpoint {
+        setpoint_temperature: f64,
+    }
+
+    impl ZoneSetpoint {
+        pub fn new(temperature: f64) -> Result<Self, ApiError> {
+            Ok(Self {
+                setpoint_temperature: check_range(
+                    "zone setpoint",
+                    temperature,
+                    ZONE_SETPOINT_MIN,
+                    ZONE_SETPOINT_MAX,
+                )?,
+            })
+        }
+    }
+
+    /// Temporary setpoint of a zone
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    pub struct QuickVeto {
+        setpoint_temperature: f64,
+        /// minutes
+        duration: i64,
+    }
+
+    impl QuickVeto {
+        pub fn new(temperature: f64, duration_minutes: i64) -> Result<Self, ApiError> {
+            Ok(Self {
+                setpoint_temperature: check_range(
+                    "quick veto setpoint",
+                    temperature,
+                    ZONE_SETPOINT_MIN,
+                    ZONE_SETPOINT_MAX,
+                )?,
+                duration: check_range(
+                    "quick veto duration",
+                    duration_minutes as f64,
+                    QUICK_VETO_MIN_MINUTES as f64,
+                    QUICK_VETO_MAX_MINUTES as f64,
+                )? as i64,
+            })
+        }
+    }
+
+    /// System wide holiday period, dates are inclusive
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct HolidayMode {
+        active: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        start_date: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        end_date: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        temperature_setpoint: Option<f64>,
+    }
+
+    impl HolidayMode {
+        pub fn new(from: NaiveDate, to: NaiveDate, temperature: f64) -> Result<Self, ApiError> {
+            if to < from {
+                return Err(ApiError::InvalidPeriod { from, to });
+            }
+            Ok(Self {
+                active: true,
+                start_date: Some(from.format("%Y-%m-%d").to_string()),
+                end_date: Some(to.format("%Y-%m-%d").to_string()),
+                temperature_setpoint: Some(check_range(
+                    "holiday setpoint",
+                    temperature,
+                    ZONE_SETPOINT_MIN,
+                    ZONE_SETPOINT_MAX,
+                )?),
+            })
+        }
+
+        pub fn inactive() -> Self {
+            Self {
+                active: false,
+                start_date: None,
+                end_date: None,
+                temperature_setpoint: None,
+            }
+        }
+    }
+
+    /// Limits of the quick mode duration in minutes
+    pub const QUICK_MODE_MIN_MINUTES: i64 = 30;
+    pub const QUICK_MODE_MAX_MINUTES: i64 = 1440;
+
+    /// Quick mode of the whole system, without duration it runs until canceled or the default of the mode
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct QuickModeRequest {
+        quickmode: QuickMode,
+        /// minutes
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration: Option<i64>,
+    }
+
+    impl QuickModeRequest {
+        pub fn new(quickmode: QuickMode, duration_minutes: Option<i64>) -> Result<Self, ApiError> {
+            if let QuickMode::Unknown(value) = quickmode {
+                return Err(ApiError::UnknownValue {
+                    name: "quick mode",
+                    value,
+                });
+            }
+            let duration = duration_minutes
+                .map(|d| {
+                    check_range(
+                        "quick mode duration",
+                        d as f64,
+                        QUICK_MODE_MIN_MINUTES as f64,
+                        QUICK_MODE_MAX_MINUTES as f64,
+                    )
+                    .map(|d| d as i64)
+                })
+                .transpose()?;
+            Ok(Self {
+                quickmode,
+                duration,
+            })
+        }
+    }
+
+    /// Whole week of a time program
+    #[derive(Debug, Clone, Default, PartialEq, Serialize)]
+    pub struct TimeProgram {
+        pub monday: Vec<TimeSlot>,
+        pub tuesday: Vec<TimeSlot>,
+        pub wednesday: Vec<TimeSlot>,
+        pub thursday: Vec<TimeSlot>,
+        pub friday: Vec<TimeSlot>,
+        pub saturday: Vec<TimeSlot>,
+        pub sunday: Vec<TimeSlot>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct TimeSlot {
+        #[serde(rename = "startTime")]
+        pub start_time: String,
+        #[serde(rename = "endTime", skip_serializing_if = "Option::is_none")]
+        pub end_time: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub setting: Option<Setting>,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use chrono::NaiveDate;
+
+        use crate::{connector::ApiError, response::system::QuickMode};
+
+        use super::{
+            DhwMode, DhwOperationMode, DhwSetpoint, FanLevel, HolidayMode, QuickModeRequest,
+            QuickVeto, VentilationMode, VentilationOperationMode,
+        };
+
+        #[test]
+        fn dhw_requests() {
+            assert_eq!(
+                r#"{"temperature_setpoint":48.5}"#,
+                serde_json::to_string(&DhwSetpoint::new(48.5).unwrap()).unwrap()
+            );
+            assert!(matches!(
+                DhwSetpoint::new(80.0),
+                Err(ApiError::OutOfRange { value, .. }) if value == 80.0
+            ));
+            assert!(DhwSetpoint::new(f64::NAN).is_err());
+
+            assert_eq!(
+                r#"{"operation_mode":"OFF"}"#,
+                serde_json::to_string(&DhwOperationMode {
+                    operation_mode: "off".parse::<DhwMode>().unwrap()
+                })
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn zone_requests() {
+            assert_eq!(
+                r#"{"setpoint_temperature":22.0,"duration":180}"#,
+                serde_json::to_string(&QuickVeto::new(22.0, 180).unwrap()).unwrap()
+            );
+            assert!(QuickVeto::new(22.0, 5).is_err());
+            assert!(QuickVeto::new(35.0, 60).is_err());
+
+            let day = |d| NaiveDate::from_ymd_opt(2023, 8, d).unwrap();
+            assert_eq!(
+                r#"{"active":true,"start_date":"2023-08-01","end_date":"2023-08-14","temperature_setpoint":15.0}"#,
+                serde_json::to_string(&HolidayMode::new(day(1), day(14), 15.0).unwrap()).unwrap()
+            );
+            assert!(matches!(
+                HolidayMode::new(day(14), day(1), 15.0),
+                Err(ApiError::InvalidPeriod { .. })
+            ));
+            assert_eq!(
+                r#"{"active":false}"#,
+                serde_json::to_string(&HolidayMode::inactive()).unwrap()
+            );
+        }
+
+        #[test]
+        fn ventilation_requests() {
+            assert_eq!(
+                r#"{"operation_mode":"NIGHT"}"#,
+                serde_json::to_string(&VentilationOperationMode {
+                    operation_mode: "night".parse::<VentilationMode>().unwrap()
+                })
+                .unwrap()
+            );
+            assert_eq!(
+                r#"{"level":3}"#,
+                serde_json::to_string(&FanLevel::new(3).unwrap()).unwrap()
+            );
+            assert!(FanLevel::new(0).is_err());
+            assert!(FanLevel::new(7).is_err());
+        }
+
+        #[test]
+        fn quick_mode_request() {
+            let party = "qm_party".parse::<QuickMode>().unwrap();
+            assert_eq!(
+                r#"{"quickmode":"QM_PARTY","duration":120}"#,
+                serde_json::to_string(&QuickModeRequest::new(party.clone(), Some(120)).unwrap())
+                    .unwrap()
+            );
+            assert_eq!(
+                r#"{"quickmode":"QM_HOTWATER_BOOST"}"#,
+                serde_json::to_string(
+                    &QuickModeRequest::new(QuickMode::QmHotwaterBoost, None).unwrap()
+                )
+                .unwrap()
+            );
+            assert!(QuickModeRequest::new(party, Some(5)).is_err());
+            // typo on the command line
+            let unknown = "qm_partyy".parse::<QuickMode>().unwrap();
+            assert!(QuickModeRequest::new(unknown, None).is_err());
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
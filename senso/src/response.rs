@@ -4,6 +4,79 @@ use serde::Deserialize;
 // https://transform.tools/json-to-rust-serde
 // Property Name Format snake_case
 
+/// Enum of api values with an `Unknown(String)` fallback.
+///
+/// Values added by Vaillant later are logged and kept as `Unknown`, so they don't fail the whole response.
+macro_rules! api_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $value:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize)]
+        #[serde(from = "String")]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+            /// value unknown to this version
+            Unknown(String),
+        }
+
+        impl $name {
+            /// Value like in the api
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $($name::$variant => $value,)*
+                    $name::Unknown(value) => value,
+                }
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                self.as_str()
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        /// ascii case insensitive, e.g. for command line arguments
+        impl std::str::FromStr for $name {
+            type Err = std::convert::Infallible;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $(if s.eq_ignore_ascii_case($value) {
+                    return Ok($name::$variant);
+                })*
+                Ok($name::Unknown(s.to_owned()))
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(value: String) -> Self {
+                match value.as_str() {
+                    $($value => $name::$variant,)*
+                    _ => {
+                        log::warn!("Unknown {} \"{}\" in response.", stringify!($name), value);
+                        $name::Unknown(value)
+                    }
+                }
+            }
+        }
+    };
+}
+
 // META
 pub mod meta {
     use super::*;
@@ -85,6 +158,241 @@ pub mod status {
     }
 }
 
+// System control
+pub mod system {
+    use super::{meta::Meta, *};
+    use iso8601_timestamp::Timestamp;
+
+    #[derive(Debug, Deserialize)]
+    pub struct Root {
+        pub body: Body,
+        pub meta: Meta,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Body {
+        pub configuration: SystemConfiguration,
+        pub status: Status,
+        #[serde(default)]
+        pub zones: Vec<Zone>,
+        #[serde(default)]
+        pub heating_circuits: Vec<HeatingCircuit>,
+        #[serde(default)]
+        pub dhw: Vec<Dhw>,
+        #[serde(default)]
+        pub ventilation: Vec<Ventilation>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct SystemConfiguration {
+        pub eco_mode: Option<bool>,
+        pub holidaymode: Option<HolidayMode>,
+        pub quickmode: Option<QuickModeConfiguration>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct HolidayMode {
+        pub active: bool,
+        pub start_date: Option<String>,
+        pub end_date: Option<String>,
+        pub temperature_setpoint: Option<f64>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct QuickModeConfiguration {
+        pub quickmode: QuickMode,
+        /// minutes
+        pub duration: Option<i64>,
+    }
+
+    api_enum! {
+        /// Named like the api, e.g. QM_PARTY
+        pub enum QuickMode {
+            QmHotwaterBoost = "QM_HOTWATER_BOOST",
+            QmVentilationBoost = "QM_VENTILATION_BOOST",
+            QmOneDayAway = "QM_ONE_DAY_AWAY",
+            QmSystemOff = "QM_SYSTEM_OFF",
+            QmOneDayAtHome = "QM_ONE_DAY_AT_HOME",
+            QmParty = "QM_PARTY",
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Status {
+        pub datetime: Timestamp,
+        pub outside_temperature: Option<f64>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Zone {
+        #[serde(rename = "_id")]
+        pub id: String,
+        pub configuration: ZoneConfiguration,
+        pub heating: Option<Function>,
+        pub cooling: Option<Function>,
+        #[serde(default)]
+        pub currently_in_quick_veto: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct ZoneConfiguration {
+        pub name: String,
+        pub enabled: bool,
+        pub inside_temperature: Option<f64>,
+        pub active_function: Option<ActiveFunction>,
+        pub quick_veto: Option<QuickVeto>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct QuickVeto {
+        pub active: bool,
+        pub temperature_setpoint: Option<f64>,
+    }
+
+    /// Heating or cooling of a zone
+    #[derive(Debug, Deserialize)]
+    pub struct Function {
+        pub configuration: FunctionConfiguration,
+        pub timeprogram: Option<TimeProgram>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct FunctionConfiguration {
+        pub mode: OperatingMode,
+        /// target temperature of DAY
+        pub setpoint_temperature: Option<f64>,
+        /// target temperature of NIGHT
+        pub setback_temperature: Option<f64>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct HeatingCircuit {
+        #[serde(rename = "_id")]
+        pub id: String,
+        pub name: Option<String>,
+        pub heating_curve: Option<f64>,
+        pub min_flow_temperature_setpoint: Option<f64>,
+        pub max_flow_temperature_setpoint: Option<f64>,
+        pub flow_temperature: Option<f64>,
+        #[serde(default)]
+        pub zones: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Dhw {
+        #[serde(rename = "_id")]
+        pub id: String,
+        pub hotwater: HotWater,
+        pub circulation: Option<Circulation>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct HotWater {
+        pub configuration: HotWaterConfiguration,
+        pub timeprogram: Option<TimeProgram>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct HotWaterConfiguration {
+        pub operation_mode: OperatingMode,
+        pub temperature_setpoint: Option<f64>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Circulation {
+        pub configuration: CirculationConfiguration,
+        pub timeprogram: Option<TimeProgram>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct CirculationConfiguration {
+        #[serde(rename = "operationMode")]
+        pub operation_mode: OperatingMode,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Ventilation {
+        #[serde(rename = "_id")]
+        pub id: String,
+        pub fan: Fan,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Fan {
+        pub configuration: FanConfiguration,
+        pub timeprogram: Option<TimeProgram>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct FanConfiguration {
+        pub operation_mode: OperatingMode,
+        pub day_level: Option<u8>,
+        pub night_level: Option<u8>,
+    }
+
+    api_enum! {
+        pub enum OperatingMode {
+            Auto = "AUTO",
+            Manual = "MANUAL",
+            Day = "DAY",
+            Night = "NIGHT",
+            On = "ON",
+            Off = "OFF",
+        }
+    }
+
+    api_enum! {
+        pub enum ActiveFunction {
+            Heating = "HEATING",
+            Cooling = "COOLING",
+            Standby = "STANDBY",
+        }
+    }
+
+    api_enum! {
+        /// Setting of a zone or fan from the start time of a slot
+        pub enum Setting {
+            Day = "DAY",
+            Night = "NIGHT",
+        }
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    pub struct TimeProgram {
+        #[serde(default)]
+        pub monday: Vec<TimeSlot>,
+        #[serde(default)]
+        pub tuesday: Vec<TimeSlot>,
+        #[serde(default)]
+        pub wednesday: Vec<TimeSlot>,
+        #[serde(default)]
+        pub thursday: Vec<TimeSlot>,
+        #[serde(default)]
+        pub friday: Vec<TimeSlot>,
+        #[serde(default)]
+        pub saturday: Vec<TimeSlot>,
+        #[serde(default)]
+        pub sunday: Vec<TimeSlot>,
+    }
+
+    /// Zones and fans switch between settings, hot water and circulation are on between start and end time
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    pub struct TimeSlot {
+        /// HH:MM
+        #[serde(rename = "startTime")]
+        pub start_time: String,
+        #[serde(rename = "endTime")]
+        pub end_time: Option<String>,
+        pub setting: Option<Setting>,
+    }
+
+    impl Body {
+        pub fn find_zone(&self, zone_id: &str) -> Option<&Zone> {
+            self.zones.iter().find(|z| z.id == zone_id)
+        }
+    }
+}
+
 // Live report
 pub mod live_report {
     use super::{meta::Meta, *};
@@ -119,22 +427,20 @@ pub mod live_report {
         pub associated_device_function: Option<AssociatedDeviceFunction>,
     }
 
-    #[derive(Debug, PartialEq, Deserialize)]
-    #[serde(rename_all = "UPPERCASE")]
-    pub enum MeasurementCategory {
-        Temperature,
-        Pressure,
-        #[serde(rename = "AIR_QUALITY")]
-        AirQuality,
+    api_enum! {
+        pub enum MeasurementCategory {
+            Temperature = "TEMPERATURE",
+            Pressure = "PRESSURE",
+            AirQuality = "AIR_QUALITY",
+        }
     }
 
-    #[derive(Debug, PartialEq, Deserialize)]
-    #[serde(rename_all = "UPPERCASE")]
-    pub enum AssociatedDeviceFunction {
-        Dhw,
-        Heating,
-        #[serde(rename = "RELATIVE_HUMIDITY")]
-        RelativeHumidity,
+    api_enum! {
+        pub enum AssociatedDeviceFunction {
+            Dhw = "DHW",
+            Heating = "HEATING",
+            RelativeHumidity = "RELATIVE_HUMIDITY",
+        }
     }
 
     impl Body {
@@ -146,12 +452,23 @@ pub mod live_report {
                 .map(|x| &x.reports)
         }
 
+        // find the first air quality report for given function, None for co2
+        pub fn find_air_quality_report(
+            &self,
+            function: Option<AssociatedDeviceFunction>,
+        ) -> Option<&Report> {
+            self.devices.iter().flat_map(|d| &d.reports).find(|r| {
+                r.measurement_category == MeasurementCategory::AirQuality
+                    && r.associated_device_function == function
+            })
+        }
+
         // find report for given device and report id
         pub fn find_report_for_device<'a>(
             &'a self,
             device_id: &'a str,
             report_id: &'a str,
-        ) -> Option<&Report> {
+        ) -> Option<&'a Report> {
             let reports = self.find_reports_for_device(device_id)?;
             find_report(reports, report_id)
         }
@@ -163,11 +480,92 @@ pub mod live_report {
     }
 }
 
+// HVAC state with errors and maintenance messages
+pub mod hvac_state {
+    use super::{meta::MetaEmpty, *};
+    use chrono::{DateTime, Local};
+
+    #[derive(Debug, Deserialize)]
+    pub struct Root {
+        pub body: Body,
+        pub meta: MetaEmpty,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Body {
+        #[serde(rename = "errorMessages", default)]
+        pub error_messages: Vec<ErrorMessage>,
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct ErrorMessage {
+        #[serde(rename = "type")]
+        pub type_field: ErrorType,
+        /// e.g. F.28
+        #[serde(rename = "statusCode")]
+        pub status_code: String,
+        #[serde(rename = "deviceName")]
+        pub device_name: String,
+        pub title: String,
+        #[serde(default)]
+        pub description: String,
+        pub hint: Option<String>,
+        #[serde(with = "timestamp_seconds_milli_or_not")]
+        pub timestamp: DateTime<Local>,
+    }
+
+    api_enum! {
+        pub enum ErrorType {
+            Error = "ERROR",
+            Maintenance = "MAINTENANCE",
+        }
+    }
+}
+
+// Details of the gateway
+pub mod facility_details {
+    use super::{meta::MetaEmpty, *};
+
+    #[derive(Debug, Deserialize)]
+    pub struct Root {
+        pub body: Body,
+        pub meta: MetaEmpty,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Body {
+        #[serde(rename = "serialNumber")]
+        pub serial_number: String,
+        #[serde(rename = "firmwareVersion")]
+        pub firmware_version: String,
+        #[serde(rename = "ethernetMAC")]
+        pub ethernet_mac: Option<String>,
+        #[serde(rename = "wifiMAC")]
+        pub wifi_mac: Option<String>,
+    }
+}
+
+pub mod gateway_type {
+    use super::{meta::MetaEmpty, *};
+
+    #[derive(Debug, Deserialize)]
+    pub struct Root {
+        pub body: Body,
+        pub meta: MetaEmpty,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Body {
+        /// e.g. VR920
+        #[serde(rename = "gatewayType")]
+        pub gateway_type: String,
+    }
+}
+
 pub mod emf_devices {
     use super::{meta::MetaEmpty, *};
 
     use iso8601_timestamp::Timestamp;
-    use strum_macros::AsRefStr;
 
     #[derive(Debug, Deserialize)]
     pub struct Root {
@@ -180,6 +578,11 @@ pub mod emf_devices {
         pub id: String,
         #[serde(rename = "marketingName")]
         pub marketing_name: String,
+        /// not reported by every device
+        #[serde(rename = "serialNumber")]
+        pub serial_number: Option<String>,
+        #[serde(rename = "firmwareVersion")]
+        pub firmware_version: Option<String>,
         pub reports: Vec<Report>,
         #[serde(rename = "type")]
         pub type_field: EmfDevice,
@@ -196,40 +599,34 @@ pub mod emf_devices {
         pub to: Timestamp,
     }
 
-    #[derive(Debug, Clone, Copy, PartialEq, Deserialize, AsRefStr)]
-    pub enum EnergyType {
-        #[serde(rename = "ENVIRONMENTAL_YIELD")]
-        #[strum(serialize = "ENVIRONMENTAL_YIELD")]
-        EnvironmentalYield,
-        #[serde(rename = "CONSUMED_ELECTRICAL_POWER")]
-        #[strum(serialize = "CONSUMED_ELECTRICAL_POWER")]
-        ConsumedElectricalPower,
-    }
-
-    #[derive(Debug, Clone, Copy, PartialEq, Deserialize, AsRefStr)]
-    pub enum EmfFunction {
-        #[serde(rename = "DHW")]
-        #[strum(serialize = "DHW")]
-        DomesticHotWater,
-        #[serde(rename = "CENTRAL_HEATING")]
-        #[strum(serialize = "CENTRAL_HEATING")]
-        CentralHeating,
-        #[serde(rename = "COOLING")]
-        #[strum(serialize = "COOLING")]
-        Cooling,
-    }
-
-    #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
-    pub enum EmfDevice {
-        #[serde(rename = "BOILER")]
-        Boiler,
-        #[serde(rename = "HEAT_PUMP")]
-        HeatPump,
+    api_enum! {
+        pub enum EnergyType {
+            EnvironmentalYield = "ENVIRONMENTAL_YIELD",
+            ConsumedElectricalPower = "CONSUMED_ELECTRICAL_POWER",
+        }
+    }
+
+    api_enum! {
+        pub enum EmfFunction {
+            DomesticHotWater = "DHW",
+            CentralHeating = "CENTRAL_HEATING",
+            Cooling = "COOLING",
+            Ventilation = "VENTILATION",
+        }
+    }
+
+    api_enum! {
+        pub enum EmfDevice {
+            Boiler = "BOILER",
+            HeatPump = "HEAT_PUMP",
+            Ventilation = "VENTILATION",
+        }
     }
 }
 
 pub mod emf_report_device {
     use super::{meta::MetaEmpty, *};
+    use crate::units::Energy;
     use iso8601_timestamp::Timestamp;
 
     #[derive(Debug, Deserialize)]
@@ -249,9 +646,16 @@ pub mod emf_report_device {
     #[derive(Debug, Deserialize)]
     pub struct Dataset {
         pub key: Timestamp,
+        /// Wh
         #[serde(with = "default_for_null")]
         pub value: f64,
     }
+
+    impl Dataset {
+        pub fn energy(&self) -> Energy {
+            Energy::from_wh(self.value)
+        }
+    }
 }
 
 // Deserializer i64 that is a Timestamp or TimestampMilli to a DateTime<Local>
@@ -316,7 +720,13 @@ mod tests {
     use chrono::{DateTime, Local};
     use serde::Deserialize;
 
-    use super::{default_for_null, meta::Rel, meta::State, timestamp_seconds_milli_or_not};
+    use super::{
+        default_for_null,
+        emf_devices::EmfFunction,
+        live_report::MeasurementCategory,
+        meta::{Rel, State},
+        timestamp_seconds_milli_or_not,
+    };
 
     #[derive(Debug, Deserialize)]
     struct TestStructTS {
@@ -360,6 +770,62 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn deserialize_unknown_api_enum() {
+        let result: Vec<MeasurementCategory> =
+            serde_json::from_str(r#"["AIR_QUALITY", "NOISE"]"#).unwrap();
+        assert_eq!(MeasurementCategory::AirQuality, result[0]);
+        assert_eq!(MeasurementCategory::Unknown("NOISE".into()), result[1]);
+        // raw value is kept
+        assert_eq!("NOISE", result[1].to_string());
+
+        assert_eq!(Ok(EmfFunction::CentralHeating), "CENTRAL_HEATING".parse());
+        assert_eq!("DHW", EmfFunction::DomesticHotWater.as_ref());
+        // still has to be a string
+        assert!(serde_json::from_str::<EmfFunction>("1").is_err());
+    }
+
+    #[test]
+    fn deserialize_unknown_system_values() {
+        use super::system::{ActiveFunction, Body, OperatingMode, QuickMode, Setting};
+
+        let body: Body = serde_json::from_value(serde_json::json!({
+            "configuration": {
+                "quickmode": { "quickmode": "QM_COOLING_FOR_X_DAYS" }
+            },
+            "status": { "datetime": "2023-03-18T10:00:00.000Z", "outside_temperature": 5.0 },
+            "zones": [{
+                "_id": "Control_ZO1",
+                "configuration": { "name": "Zone 1", "enabled": true, "active_function": "DEFROST" },
+                "heating": {
+                    "configuration": { "mode": "ECO" },
+                    "timeprogram": { "monday": [{ "startTime": "06:00", "setting": "COMFORT" }] }
+                }
+            }]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            QuickMode::Unknown("QM_COOLING_FOR_X_DAYS".into()),
+            body.configuration.quickmode.unwrap().quickmode
+        );
+        let zone = &body.zones[0];
+        assert_eq!(
+            Some(ActiveFunction::Unknown("DEFROST".into())),
+            zone.configuration.active_function
+        );
+        let heating = zone.heating.as_ref().unwrap();
+        assert_eq!(
+            OperatingMode::Unknown("ECO".into()),
+            heating.configuration.mode
+        );
+        assert_eq!(
+            Some(Setting::Unknown("COMFORT".into())),
+            heating.timeprogram.as_ref().unwrap().monday[0].setting
+        );
+        assert_eq!(Ok(QuickMode::QmParty), "qm_party".parse());
+    }
+
     #[derive(Debug, Deserialize)]
     struct TestStructRel {
         pub rel: Rel,
@@ -0,0 +1,165 @@
+use chrono::{Duration, NaiveDateTime};
+use cli_table::Table;
+use log::info;
+
+use crate::db::{Bucket, DB};
+
+/// Default days raw sensor samples are kept
+pub const DEFAULT_RAW_DAYS: i64 = 30;
+
+/// Days the stored data is kept, daily rollups are always kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetentionPolicy {
+    pub raw_days: i64,
+    /// None keeps the hourly rollups forever
+    pub hourly_days: Option<i64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            raw_days: DEFAULT_RAW_DAYS,
+            hourly_days: None,
+        }
+    }
+}
+
+/// Rows changed by one run of the policy
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct RetentionReport {
+    /// raw samples before are deleted
+    #[table(title = "raw cutoff")]
+    pub raw_cutoff: NaiveDateTime,
+    #[table(title = "hourly buckets")]
+    pub hourly: usize,
+    #[table(title = "daily buckets")]
+    pub daily: usize,
+    #[table(title = "deleted samples")]
+    pub deleted_samples: usize,
+    #[table(title = "deleted hourly buckets")]
+    pub deleted_hourly: usize,
+}
+
+/// Rolls up the raw samples since the newest bucket before `now` and deletes the expired rows.
+///
+/// The newest bucket is rolled up again, it may have been incomplete.
+/// Cutoffs are aligned to the start of a day, so only complete days are deleted.
+/// The last raw samples of an expired bucket are always rolled up before the bucket is deleted.
+/// Runs in one transaction, applying the policy again is safe.
+pub fn apply(
+    db: &DB,
+    policy: &RetentionPolicy,
+    now: NaiveDateTime,
+) -> anyhow::Result<RetentionReport> {
+    // SAFETY midnight always exists
+    let today = now.date().and_hms_opt(0, 0, 0).unwrap();
+    let raw_cutoff = today - Duration::days(policy.raw_days.max(0));
+    // older buckets were rolled up by earlier runs, the first run rolls up all raw samples
+    let from = |bucket: Bucket| -> anyhow::Result<NaiveDateTime> {
+        Ok(db.latest_rollup(bucket)?.unwrap_or(NaiveDateTime::MIN))
+    };
+
+    db.in_transaction(|| {
+        let hourly = db.rollup(Bucket::Hourly, from(Bucket::Hourly)?, now)?;
+        let daily = db.rollup(Bucket::Daily, from(Bucket::Daily)?, now)?;
+        let deleted_samples = db.delete_sensor_data_before(raw_cutoff)?;
+        let deleted_hourly = match policy.hourly_days {
+            Some(days) => {
+                db.delete_rollups_before(Bucket::Hourly, today - Duration::days(days.max(0)))?
+            }
+            None => 0,
+        };
+        info!("Applied retention, kept raw samples since {}.", raw_cutoff);
+        Ok(RetentionReport {
+            raw_cutoff,
+            hourly,
+            daily,
+            deleted_samples,
+            deleted_hourly,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    use crate::db::{Bucket, Channel, SensorData, DB};
+
+    use super::{apply, RetentionPolicy};
+
+    fn time(day: u32, minute: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 2, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + Duration::minutes(minute)
+    }
+
+    #[test]
+    fn rollup_and_retention() {
+        let db = DB::new(None).unwrap();
+        for day in 1..=4 {
+            // every 15 minutes for 2 hours, no pressure in the second hour
+            for i in 0..8 {
+                let pressure = if i < 4 { Some(1.0 + i as f64) } else { None };
+                db.insert_sensor_data_at(
+                    time(day, i * 15),
+                    SensorData::new_raw(Some(i as f64), Some(50.0), pressure, Some(30.0)),
+                )
+                .unwrap();
+            }
+        }
+        let policy = RetentionPolicy {
+            raw_days: 2,
+            hourly_days: Some(3),
+        };
+
+        let report = apply(&db, &policy, time(5, 0)).unwrap();
+        assert_eq!(time(3, 0), report.raw_cutoff);
+        assert_eq!(8, report.hourly);
+        assert_eq!(4, report.daily);
+        assert_eq!(16, report.deleted_samples);
+        // day 1
+        assert_eq!(2, report.deleted_hourly);
+        assert_eq!(16, db.sensor_samples(time(1, 0), time(5, 0)).unwrap().len());
+
+        let daily = db
+            .rollups(Bucket::Daily, Channel::Outdoor, time(1, 0), time(5, 0))
+            .unwrap();
+        assert_eq!(4, daily.len());
+        assert_eq!(time(1, 0), daily[0].time);
+        assert_eq!(0.0, daily[0].min);
+        assert_eq!(7.0, daily[0].max);
+        assert_eq!(3.5, daily[0].mean);
+        assert_eq!(8, daily[0].count);
+
+        // hourly buckets without any pressure are skipped
+        let hourly = db
+            .rollups(
+                Bucket::Hourly,
+                Channel::Waterpressure,
+                time(1, 0),
+                time(5, 0),
+            )
+            .unwrap();
+        assert_eq!(3, hourly.len());
+        assert_eq!(2.5, hourly[0].mean);
+        assert_eq!(4, hourly[0].count);
+
+        // days without raw samples keep their rollups, only the newest buckets are rolled up again
+        let again = apply(&db, &policy, time(5, 0)).unwrap();
+        assert_eq!(0, again.deleted_samples);
+        assert_eq!(1, again.hourly);
+        assert_eq!(1, again.daily);
+        assert_eq!(Some(time(4, 60)), db.latest_rollup(Bucket::Hourly).unwrap());
+        assert_eq!(
+            daily,
+            db.rollups(Bucket::Daily, Channel::Outdoor, time(1, 0), time(5, 0))
+                .unwrap()
+        );
+        assert!(db
+            .rollups(Bucket::Daily, Channel::ChHpY, time(1, 0), time(5, 0))
+            .is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use anyhow::anyhow;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Weekday};
+use cli_table::Table;
+
+use crate::{
+    db::DB,
+    yp::{calc_eer, calc_yp, YpData},
+};
+
+/// First month of the heating season
+pub const HEATING_SEASON_START_MONTH: u32 = 10;
+/// First month after the heating season
+pub const HEATING_SEASON_END_MONTH: u32 = 5;
+
+/// Interval to aggregate over
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    /// iso week
+    Week {
+        year: i32,
+        week: u32,
+    },
+    Month {
+        year: i32,
+        month: u32,
+    },
+    /// October of `start_year` till end of April of the next year
+    HeatingSeason {
+        start_year: i32,
+    },
+    Year {
+        year: i32,
+    },
+    /// `[from, to)`
+    Range {
+        from: NaiveDate,
+        to: NaiveDate,
+    },
+}
+
+impl Period {
+    /// Start and end of the period as `[from, to)`
+    pub fn range(&self) -> anyhow::Result<(NaiveDateTime, NaiveDateTime)> {
+        let (from, to) = match *self {
+            Period::Week { year, week } => {
+                let from = NaiveDate::from_isoywd_opt(year, week, Weekday::Mon);
+                (
+                    from,
+                    from.and_then(|f| f.checked_add_signed(chrono::Duration::days(7))),
+                )
+            }
+            Period::Month { year, month } => (
+                NaiveDate::from_ymd_opt(year, month, 1),
+                if month == 12 {
+                    NaiveDate::from_ymd_opt(year + 1, 1, 1)
+                } else {
+                    NaiveDate::from_ymd_opt(year, month + 1, 1)
+                },
+            ),
+            Period::HeatingSeason { start_year } => (
+                NaiveDate::from_ymd_opt(start_year, HEATING_SEASON_START_MONTH, 1),
+                NaiveDate::from_ymd_opt(start_year + 1, HEATING_SEASON_END_MONTH, 1),
+            ),
+            Period::Year { year } => (
+                NaiveDate::from_ymd_opt(year, 1, 1),
+                NaiveDate::from_ymd_opt(year + 1, 1, 1),
+            ),
+            Period::Range { from, to } => (Some(from), Some(to)),
+        };
+
+        let from = from.ok_or(anyhow!("out-of-range date for start of period"))?;
+        let to = to.ok_or(anyhow!("out-of-range date for end of period"))?;
+        if from >= to {
+            return Err(anyhow!("Start of period has to be before the end."));
+        }
+        Ok((
+            from.and_hms_opt(0, 0, 0).ok_or(anyhow!(""))?,
+            to.and_hms_opt(0, 0, 0).ok_or(anyhow!(""))?,
+        ))
+    }
+
+    /// Same period moved by given years, e.g. -1 for the year before
+    pub fn shifted_years(&self, years: i32) -> anyhow::Result<Self> {
+        let shift = |d: NaiveDate| {
+            d.with_year(d.year() + years)
+                .ok_or(anyhow!("Can't move {} by {} years.", d, years))
+        };
+        Ok(match *self {
+            Period::Week { year, week } => Period::Week {
+                year: year + years,
+                week,
+            },
+            Period::Month { year, month } => Period::Month {
+                year: year + years,
+                month,
+            },
+            Period::HeatingSeason { start_year } => Period::HeatingSeason {
+                start_year: start_year + years,
+            },
+            Period::Year { year } => Period::Year { year: year + years },
+            Period::Range { from, to } => Period::Range {
+                from: shift(from)?,
+                to: shift(to)?,
+            },
+        })
+    }
+}
+
+/// Seasonal performance factor of an interval.
+///
+/// Yield and power are summed over all days before dividing.
+#[derive(Debug, PartialEq, Table)]
+pub struct Spf {
+    #[table(title = "from")]
+    pub from: NaiveDateTime,
+    #[table(title = "to")]
+    pub to: NaiveDateTime,
+    /// number of days with usage data
+    #[table(title = "days")]
+    pub days: i64,
+    #[table(title = "ch_y")]
+    pub ch_y: f64,
+    /// heat pump and boiler
+    #[table(title = "ch_p")]
+    pub ch_p: f64,
+    #[table(title = "hw_y")]
+    pub hw_y: f64,
+    /// heat pump and boiler
+    #[table(title = "hw_p")]
+    pub hw_p: f64,
+    /// boiler for central heating and hot water
+    #[table(title = "bo_p")]
+    pub bo_p: f64,
+    #[table(title = "ch_spf")]
+    pub ch_spf: f64,
+    #[table(title = "hw_spf")]
+    pub hw_spf: f64,
+    #[table(title = "total_spf")]
+    pub total_spf: f64,
+    /// share of the delivered heat from the boiler
+    #[table(title = "boiler_share")]
+    pub boiler_share: f64,
+    #[table(title = "co_seer")]
+    pub co_seer: f64,
+}
+
+/// Aggregate the stored usage over the given period
+pub fn aggregate(db: &DB, period: Period) -> anyhow::Result<Spf> {
+    let (from, to) = period.range()?;
+    let usage = db.get_yp_data(from, to)?;
+    let sum = |f: fn(&YpData) -> f64| usage.iter().map(f).sum::<f64>();
+
+    let ch_y = sum(|u| u.ch_hp_y);
+    let ch_bo_p = sum(|u| u.ch_bo_p);
+    let ch_p = sum(|u| u.ch_hp_p) + ch_bo_p;
+    let hw_y = sum(|u| u.hw_hp_y);
+    let hw_bo_p = sum(|u| u.hw_bo_p);
+    let hw_p = sum(|u| u.hw_hp_p) + hw_bo_p;
+    let bo_p = ch_bo_p + hw_bo_p;
+
+    // the boiler turns power 1:1 into heat
+    let heat = ch_y + ch_p + hw_y + hw_p;
+    let boiler_share = if heat == 0.0 {
+        0.0
+    } else {
+        ((bo_p / heat) * 10000.0).round() / 10000.0
+    };
+
+    Ok(Spf {
+        from,
+        to,
+        days: usage.len() as i64,
+        ch_y,
+        ch_p,
+        hw_y,
+        hw_p,
+        bo_p,
+        ch_spf: calc_yp(ch_y, ch_p),
+        hw_spf: calc_yp(hw_y, hw_p),
+        total_spf: calc_yp(ch_y + hw_y, ch_p + hw_p),
+        boiler_share,
+        co_seer: calc_eer(sum(|u| u.co_hp_y), sum(|u| u.co_hp_p)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use crate::{
+        db::DB,
+        yp::{calc_yp, YpData},
+    };
+
+    use super::{aggregate, Period};
+
+    fn day(m: u32, d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, m, d)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn period_range() {
+        let (from, to) = Period::Week {
+            year: 2023,
+            week: 9,
+        }
+        .range()
+        .unwrap();
+        assert_eq!((day(2, 27), day(3, 6)), (from, to));
+
+        let (from, to) = Period::Month {
+            year: 2022,
+            month: 12,
+        }
+        .range()
+        .unwrap();
+        assert_eq!(NaiveDate::from_ymd_opt(2022, 12, 1).unwrap(), from.date());
+        assert_eq!(day(1, 1), to);
+
+        let (from, to) = Period::HeatingSeason { start_year: 2022 }.range().unwrap();
+        assert_eq!(NaiveDate::from_ymd_opt(2022, 10, 1).unwrap(), from.date());
+        assert_eq!(day(5, 1), to);
+
+        assert!(Period::Range {
+            from: day(3, 1).date(),
+            to: day(3, 1).date()
+        }
+        .range()
+        .is_err());
+    }
+
+    #[test]
+    fn aggregate_sums_before_dividing() {
+        let db = DB::new(None).unwrap();
+        // daily ch yp of 5 and 1.3333, hw yp of 2 and 7
+        for (d, ch_hp_y, ch_hp_p, hw_hp_y, hw_bo_p) in [
+            (1, 4000.0, 1000.0, 1000.0, 500.0),
+            (2, 1000.0, 3000.0, 3000.0, 0.0),
+        ] {
+            db.insert_yp_data(&YpData {
+                ts: day(3, d),
+                ch_hp_y,
+                ch_hp_p,
+                hw_hp_y,
+                hw_hp_p: 500.0,
+                hw_bo_p,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        // outside of march
+        db.insert_yp_data(&YpData {
+            ts: day(4, 1),
+            ch_hp_y: 10000.0,
+            ch_hp_p: 1000.0,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let spf = aggregate(
+            &db,
+            Period::Month {
+                year: 2023,
+                month: 3,
+            },
+        )
+        .unwrap();
+        assert_eq!(2, spf.days);
+        // 9000 / 4000, the mean of the daily ratios would be 3.1667
+        assert_eq!(2.25, spf.ch_spf);
+        assert_ne!(
+            (calc_yp(4000.0, 1000.0) + calc_yp(1000.0, 3000.0)) / 2.0,
+            spf.ch_spf
+        );
+        // 5500 / 1500, the mean of the daily ratios would be 4.5
+        assert_eq!(3.6667, spf.hw_spf);
+        // 14500 / 5500
+        assert_eq!(2.6364, spf.total_spf);
+        // 500 of 14500
+        assert_eq!(0.0345, spf.boiler_share);
+    }
+}
@@ -0,0 +1,380 @@
+use std::fs;
+
+use anyhow::anyhow;
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use cli_table::Table;
+use serde::Deserialize;
+
+use crate::{db::DB, spf::Period, yp::YpData};
+
+/// Tariffs loaded from a json config.
+///
+/// ```json
+/// {
+///     "tariffs": [
+///         { "valid_from": "2022-01-01", "type": "flat", "price": 0.32 },
+///         { "valid_from": "2023-01-01", "type": "time_of_use", "default_price": 0.40,
+///           "windows": [{ "from_hour": 22, "to_hour": 6, "price": 0.28 }] },
+///         { "valid_from": "2023-07-01", "type": "heat_pump", "price": 0.27, "base_fee_per_year": 90.0 }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TariffConfig {
+    pub tariffs: Vec<Tariff>,
+}
+
+/// Tariff valid from a day until the next tariff starts
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Tariff {
+    pub valid_from: NaiveDate,
+    #[serde(flatten)]
+    pub kind: TariffKind,
+}
+
+/// Prices are per kWh
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TariffKind {
+    /// Same price all day on the household meter
+    Flat { price: f64 },
+    /// Price depends on the hour of the day on the household meter.
+    /// Hours not covered by a window use the default price.
+    TimeOfUse {
+        default_price: f64,
+        windows: Vec<TimeWindow>,
+    },
+    /// Dedicated heat pump tariff with a separate meter.
+    /// The base fee of the meter is part of the heat pump costs.
+    HeatPump { price: f64, base_fee_per_year: f64 },
+}
+
+/// `[from_hour, to_hour)`, wraps around midnight if `to_hour` < `from_hour`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TimeWindow {
+    pub from_hour: u32,
+    pub to_hour: u32,
+    pub price: f64,
+}
+
+impl TimeWindow {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.from_hour > 23 || self.to_hour > 23 {
+            return Err(anyhow!(
+                "Time window {}-{} has an hour above 23.",
+                self.from_hour,
+                self.to_hour
+            ));
+        }
+        if self.from_hour == self.to_hour {
+            return Err(anyhow!(
+                "Time window {}-{} is empty, use the default price for the whole day.",
+                self.from_hour,
+                self.to_hour
+            ));
+        }
+        Ok(())
+    }
+
+    fn contains(&self, hour: u32) -> bool {
+        if self.from_hour < self.to_hour {
+            (self.from_hour..self.to_hour).contains(&hour)
+        } else {
+            hour >= self.from_hour || hour < self.to_hour
+        }
+    }
+}
+
+impl TariffKind {
+    /// Price per kWh for one day.
+    ///
+    /// Only daily usage is known, so time of use assumes the usage is spread evenly over the day.
+    pub fn daily_price(&self) -> f64 {
+        match self {
+            TariffKind::Flat { price } => *price,
+            TariffKind::TimeOfUse {
+                default_price,
+                windows,
+            } => {
+                (0..24)
+                    .map(|hour| {
+                        windows
+                            .iter()
+                            .find(|w| w.contains(hour))
+                            .map_or(*default_price, |w| w.price)
+                    })
+                    .sum::<f64>()
+                    / 24.0
+            }
+            TariffKind::HeatPump { price, .. } => *price,
+        }
+    }
+
+    /// Base fee for one day that is caused by the heat pump
+    pub fn daily_base_fee(&self, day: NaiveDate) -> f64 {
+        match self {
+            TariffKind::HeatPump {
+                base_fee_per_year, ..
+            } => {
+                let days_in_year = if NaiveDate::from_ymd_opt(day.year(), 2, 29).is_some() {
+                    366.0
+                } else {
+                    365.0
+                };
+                base_fee_per_year / days_in_year
+            }
+            // household meter exists anyway
+            _ => 0.0,
+        }
+    }
+}
+
+impl TariffConfig {
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let config: TariffConfig = serde_json::from_str(&fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the hours of all time windows
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for tariff in &self.tariffs {
+            if let TariffKind::TimeOfUse { windows, .. } = &tariff.kind {
+                for window in windows {
+                    window.validate().map_err(|e| {
+                        anyhow!("Invalid tariff valid from {}: {}", tariff.valid_from, e)
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Tariff valid on the given day
+    pub fn tariff_for(&self, day: NaiveDate) -> Option<&Tariff> {
+        self.tariffs
+            .iter()
+            .filter(|t| t.valid_from <= day)
+            .max_by_key(|t| t.valid_from)
+    }
+
+    /// Costs of one day of usage
+    pub fn daily_cost(&self, yp_data: &YpData) -> anyhow::Result<DailyCost> {
+        let day = yp_data.ts.date();
+        let tariff = self
+            .tariff_for(day)
+            .ok_or(anyhow!("No tariff valid on {}.", day))?;
+        let price = tariff.kind.daily_price();
+        // usage is in Wh, round with precision of 4 places after 0.0000
+        let cost = |wh: f64| ((wh / 1000.0 * price) * 10000.0).round() / 10000.0;
+
+        Ok(DailyCost::new(
+            yp_data.ts,
+            cost(yp_data.ch_hp_p),
+            cost(yp_data.hw_hp_p),
+            cost(yp_data.ch_bo_p + yp_data.hw_bo_p),
+            cost(yp_data.co_hp_p),
+            tariff.kind.daily_base_fee(day),
+        ))
+    }
+}
+
+/// Costs of one day for heating, hot water, boiler backup and cooling
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct DailyCost {
+    #[table(title = "ts")]
+    pub ts: NaiveDateTime,
+    /// heat pump for central heating
+    #[table(title = "ch_cost")]
+    pub ch_cost: f64,
+    /// heat pump for hot water
+    #[table(title = "hw_cost")]
+    pub hw_cost: f64,
+    /// boiler for central heating and hot water
+    #[table(title = "bo_cost")]
+    pub bo_cost: f64,
+    #[table(title = "co_cost")]
+    pub co_cost: f64,
+    #[table(title = "base_fee")]
+    pub base_fee: f64,
+    #[table(title = "total_cost")]
+    pub total_cost: f64,
+}
+
+impl DailyCost {
+    pub fn new(
+        ts: NaiveDateTime,
+        ch_cost: f64,
+        hw_cost: f64,
+        bo_cost: f64,
+        co_cost: f64,
+        base_fee: f64,
+    ) -> Self {
+        Self {
+            ts,
+            ch_cost,
+            hw_cost,
+            bo_cost,
+            co_cost,
+            base_fee,
+            total_cost: ch_cost + hw_cost + bo_cost + co_cost + base_fee,
+        }
+    }
+}
+
+/// Summed costs of a period
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct PeriodCost {
+    #[table(title = "from")]
+    pub from: NaiveDateTime,
+    #[table(title = "to")]
+    pub to: NaiveDateTime,
+    #[table(title = "days")]
+    pub days: usize,
+    #[table(title = "ch_cost")]
+    pub ch_cost: f64,
+    #[table(title = "hw_cost")]
+    pub hw_cost: f64,
+    #[table(title = "bo_cost")]
+    pub bo_cost: f64,
+    #[table(title = "co_cost")]
+    pub co_cost: f64,
+    #[table(title = "base_fee")]
+    pub base_fee: f64,
+    #[table(title = "total_cost")]
+    pub total_cost: f64,
+}
+
+/// Calculates and stores the costs for every stored day of usage in the period
+pub fn calculate(db: &DB, config: &TariffConfig, period: Period) -> anyhow::Result<Vec<DailyCost>> {
+    let (from, to) = period.range()?;
+    let costs = db
+        .get_yp_data(from, to)?
+        .iter()
+        .map(|yp_data| config.daily_cost(yp_data))
+        .collect::<anyhow::Result<Vec<DailyCost>>>()?;
+    db.insert_costs(&costs)?;
+    Ok(costs)
+}
+
+/// Sum of the stored costs in the period
+pub fn aggregate(db: &DB, period: Period) -> anyhow::Result<PeriodCost> {
+    let (from, to) = period.range()?;
+    let costs = db.get_costs(from, to)?;
+    let sum = |f: fn(&DailyCost) -> f64| costs.iter().map(f).sum();
+
+    Ok(PeriodCost {
+        from,
+        to,
+        days: costs.len(),
+        ch_cost: sum(|c| c.ch_cost),
+        hw_cost: sum(|c| c.hw_cost),
+        bo_cost: sum(|c| c.bo_cost),
+        co_cost: sum(|c| c.co_cost),
+        base_fee: sum(|c| c.base_fee),
+        total_cost: sum(|c| c.total_cost),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::{db::DB, spf::Period, yp::YpData};
+
+    use super::{aggregate, calculate, TariffConfig};
+
+    fn config() -> TariffConfig {
+        serde_json::from_str(
+            r#"{
+                "tariffs": [
+                    { "valid_from": "2023-01-01", "type": "flat", "price": 0.3 },
+                    { "valid_from": "2023-02-01", "type": "time_of_use", "default_price": 0.4,
+                      "windows": [{ "from_hour": 18, "to_hour": 6, "price": 0.2 }] },
+                    { "valid_from": "2024-01-01", "type": "heat_pump", "price": 0.25, "base_fee_per_year": 366.0 }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn usage(y: i32, m: u32, d: u32) -> YpData {
+        YpData {
+            ts: NaiveDate::from_ymd_opt(y, m, d)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            ch_hp_p: 10000.0,
+            hw_hp_p: 2000.0,
+            ch_bo_p: 500.0,
+            hw_bo_p: 500.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tariff_changes() {
+        let config = config();
+        assert!(config
+            .tariff_for(NaiveDate::from_ymd_opt(2022, 12, 31).unwrap())
+            .is_none());
+        assert!(config.daily_cost(&usage(2022, 12, 31)).is_err());
+
+        let flat = config.daily_cost(&usage(2023, 1, 31)).unwrap();
+        assert_eq!(3.0, flat.ch_cost);
+        assert_eq!(0.3, flat.bo_cost);
+        assert_eq!(0.0, flat.base_fee);
+
+        // half of the day for 0.2 and 0.4
+        let time_of_use = config.daily_cost(&usage(2023, 2, 1)).unwrap();
+        assert_eq!(3.0, time_of_use.ch_cost);
+
+        let heat_pump = config.daily_cost(&usage(2024, 1, 1)).unwrap();
+        assert_eq!(0.5, heat_pump.hw_cost);
+        assert_eq!(1.0, heat_pump.base_fee);
+        assert_eq!(4.25, heat_pump.total_cost);
+    }
+
+    #[test]
+    fn invalid_windows() {
+        assert!(config().validate().is_ok());
+        let window = |from_hour: u32, to_hour: u32| -> TariffConfig {
+            serde_json::from_value(serde_json::json!({
+                "tariffs": [{ "valid_from": "2023-02-01", "type": "time_of_use", "default_price": 0.4,
+                  "windows": [{ "from_hour": from_hour, "to_hour": to_hour, "price": 0.2 }] }]
+            }))
+            .unwrap()
+        };
+        assert_eq!(
+            "Invalid tariff valid from 2023-02-01: Time window 22-24 has an hour above 23.",
+            window(22, 24).validate().unwrap_err().to_string()
+        );
+        assert!(window(6, 6).validate().is_err());
+        assert!(window(22, 0).validate().is_ok());
+    }
+
+    #[test]
+    fn period_cost() {
+        let db = DB::new(None).unwrap();
+        db.insert_yp_data(&usage(2023, 1, 30)).unwrap();
+        db.insert_yp_data(&usage(2023, 1, 31)).unwrap();
+        db.insert_yp_data(&usage(2023, 2, 1)).unwrap();
+
+        let year = Period::Year { year: 2023 };
+        assert_eq!(3, calculate(&db, &config(), year).unwrap().len());
+        // calculating again replaces the costs
+        calculate(&db, &config(), year).unwrap();
+
+        let january = aggregate(
+            &db,
+            Period::Month {
+                year: 2023,
+                month: 1,
+            },
+        )
+        .unwrap();
+        assert_eq!(2, january.days);
+        assert_eq!(6.0, january.ch_cost);
+        assert_eq!(aggregate(&db, year).unwrap().days, 3);
+    }
+}
@@ -0,0 +1,509 @@
+use std::{collections::BTreeMap, fmt, fs, str::FromStr};
+
+use anyhow::anyhow;
+use cli_table::Table;
+use itertools::Itertools;
+use log::info;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use strum_macros::{AsRefStr, Display};
+
+use crate::{
+    connector::{ApiError, Connector},
+    request,
+    response::system::{self, Setting},
+};
+
+/// Max switching points per day of a zone or fan
+pub const MAX_SWITCHING_POINTS: usize = 12;
+/// Max intervals per day of hot water or circulation
+pub const MAX_INTERVALS: usize = 3;
+
+/// Time of day in minutes, `24:00` is allowed as end of an interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Time(u16);
+
+impl Time {
+    pub const END_OF_DAY: Time = Time(24 * 60);
+
+    pub fn new(hour: u16, minute: u16) -> Option<Self> {
+        let time = Time(hour * 60 + minute);
+        (minute < 60 && time <= Self::END_OF_DAY).then_some(time)
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+impl FromStr for Time {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once(':')
+            .and_then(|(h, m)| Time::new(h.parse().ok()?, m.parse().ok()?))
+            .ok_or(anyhow!("Invalid time \"{}\", expected HH:MM.", s))
+    }
+}
+
+impl Serialize for Time {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Time {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Zones and fans switch to the setting at start, hot water and circulation are on from start to end
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Slot {
+    pub start: Time,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<Time>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setting: Option<Setting>,
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.start)?;
+        if let Some(end) = self.end {
+            write!(f, "-{}", end)?;
+        }
+        if let Some(setting) = &self.setting {
+            write!(f, " {:?}", setting)?;
+        }
+        Ok(())
+    }
+}
+
+/// Time program of the system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, AsRefStr, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum ProgramKind {
+    /// heating of a zone
+    Zone,
+    Dhw,
+    Circulation,
+    Ventilation,
+}
+
+impl ProgramKind {
+    /// Zones and fans have switching points, hot water and circulation intervals
+    pub fn has_intervals(&self) -> bool {
+        matches!(self, ProgramKind::Dhw | ProgramKind::Circulation)
+    }
+}
+
+/// Slots of every weekday
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TimeProgram {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub monday: Vec<Slot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tuesday: Vec<Slot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wednesday: Vec<Slot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thursday: Vec<Slot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub friday: Vec<Slot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub saturday: Vec<Slot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sunday: Vec<Slot>,
+}
+
+impl TimeProgram {
+    pub fn days(&self) -> [(&'static str, &Vec<Slot>); 7] {
+        [
+            ("monday", &self.monday),
+            ("tuesday", &self.tuesday),
+            ("wednesday", &self.wednesday),
+            ("thursday", &self.thursday),
+            ("friday", &self.friday),
+            ("saturday", &self.saturday),
+            ("sunday", &self.sunday),
+        ]
+    }
+
+    pub fn from_response(program: &system::TimeProgram) -> anyhow::Result<Self> {
+        let slots = |slots: &[system::TimeSlot]| -> anyhow::Result<Vec<Slot>> {
+            slots
+                .iter()
+                .map(|s| {
+                    Ok(Slot {
+                        start: s.start_time.parse()?,
+                        end: s.end_time.as_deref().map(str::parse).transpose()?,
+                        setting: s.setting.clone(),
+                    })
+                })
+                .collect()
+        };
+        Ok(Self {
+            monday: slots(&program.monday)?,
+            tuesday: slots(&program.tuesday)?,
+            wednesday: slots(&program.wednesday)?,
+            thursday: slots(&program.thursday)?,
+            friday: slots(&program.friday)?,
+            saturday: slots(&program.saturday)?,
+            sunday: slots(&program.sunday)?,
+        })
+    }
+
+    pub fn to_request(&self) -> request::system::TimeProgram {
+        let slots = |slots: &[Slot]| {
+            slots
+                .iter()
+                .map(|s| request::system::TimeSlot {
+                    start_time: s.start.to_string(),
+                    end_time: s.end.map(|e| e.to_string()),
+                    setting: s.setting.clone(),
+                })
+                .collect()
+        };
+        request::system::TimeProgram {
+            monday: slots(&self.monday),
+            tuesday: slots(&self.tuesday),
+            wednesday: slots(&self.wednesday),
+            thursday: slots(&self.thursday),
+            friday: slots(&self.friday),
+            saturday: slots(&self.saturday),
+            sunday: slots(&self.sunday),
+        }
+    }
+
+    /// Checks the slots of every day for the kind of program. All problems are part of the error.
+    pub fn validate(&self, kind: ProgramKind) -> Result<(), ApiError> {
+        let mut problems = Vec::new();
+        for (day, slots) in self.days() {
+            let max = if kind.has_intervals() {
+                MAX_INTERVALS
+            } else {
+                MAX_SWITCHING_POINTS
+            };
+            if slots.len() > max {
+                problems.push(format!("{}: {} slots, max is {}", day, slots.len(), max));
+            }
+            for slot in slots {
+                match (kind.has_intervals(), slot.end, &slot.setting) {
+                    (_, _, Some(Setting::Unknown(_))) => {
+                        problems.push(format!("{}: {} has an unknown setting", day, slot))
+                    }
+                    (true, Some(end), None) if slot.start < end => {}
+                    (true, Some(_), None) => {
+                        problems.push(format!("{}: {} ends before it starts", day, slot))
+                    }
+                    (true, _, _) => {
+                        problems.push(format!("{}: {} needs an end and no setting", day, slot))
+                    }
+                    (false, None, Some(_)) if slot.start < Time::END_OF_DAY => {}
+                    (false, None, Some(_)) => {
+                        problems.push(format!("{}: {} starts at end of day", day, slot))
+                    }
+                    (false, _, _) => {
+                        problems.push(format!("{}: {} needs a setting and no end", day, slot))
+                    }
+                }
+            }
+            for (a, b) in slots.iter().sorted_by_key(|s| s.start).tuple_windows() {
+                if b.start < a.end.unwrap_or(a.start) || b.start == a.start {
+                    problems.push(format!("{}: {} overlaps {}", day, a, b));
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::InvalidTimeProgram(problems.join("; ")))
+        }
+    }
+}
+
+/// All time programs of a facility by kind and id, e.g. one file per house
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schedules {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub zones: BTreeMap<String, TimeProgram>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dhw: BTreeMap<String, TimeProgram>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub circulation: BTreeMap<String, TimeProgram>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ventilation: BTreeMap<String, TimeProgram>,
+}
+
+impl Schedules {
+    /// Time programs of the live system. Zones without heating program are skipped.
+    pub fn from_system(body: &system::Body) -> anyhow::Result<Self> {
+        let mut result = Schedules::default();
+        for zone in &body.zones {
+            if let Some(program) = zone.heating.as_ref().and_then(|h| h.timeprogram.as_ref()) {
+                result
+                    .zones
+                    .insert(zone.id.clone(), TimeProgram::from_response(program)?);
+            }
+        }
+        for dhw in &body.dhw {
+            if let Some(program) = &dhw.hotwater.timeprogram {
+                result
+                    .dhw
+                    .insert(dhw.id.clone(), TimeProgram::from_response(program)?);
+            }
+            if let Some(program) = dhw
+                .circulation
+                .as_ref()
+                .and_then(|c| c.timeprogram.as_ref())
+            {
+                result
+                    .circulation
+                    .insert(dhw.id.clone(), TimeProgram::from_response(program)?);
+            }
+        }
+        for ventilation in &body.ventilation {
+            if let Some(program) = &ventilation.fan.timeprogram {
+                result
+                    .ventilation
+                    .insert(ventilation.id.clone(), TimeProgram::from_response(program)?);
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn from_yaml(yaml: &str) -> anyhow::Result<Self> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        Self::from_yaml(&fs::read_to_string(path)?)
+    }
+
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+
+    pub fn programs(&self) -> impl Iterator<Item = (ProgramKind, &String, &TimeProgram)> {
+        [
+            (ProgramKind::Zone, &self.zones),
+            (ProgramKind::Dhw, &self.dhw),
+            (ProgramKind::Circulation, &self.circulation),
+            (ProgramKind::Ventilation, &self.ventilation),
+        ]
+        .into_iter()
+        .flat_map(|(kind, programs)| programs.iter().map(move |(id, p)| (kind, id, p)))
+    }
+
+    fn get(&self, kind: ProgramKind, id: &str) -> Option<&TimeProgram> {
+        match kind {
+            ProgramKind::Zone => self.zones.get(id),
+            ProgramKind::Dhw => self.dhw.get(id),
+            ProgramKind::Circulation => self.circulation.get(id),
+            ProgramKind::Ventilation => self.ventilation.get(id),
+        }
+    }
+
+    /// Validates every program, the error names the program of each problem
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let problems = self
+            .programs()
+            .filter_map(|(kind, id, p)| {
+                p.validate(kind)
+                    .err()
+                    .map(|e| format!("{} {}: {}", kind, id, e))
+            })
+            .collect_vec();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(problems.join("\n")))
+        }
+    }
+}
+
+/// Day of a program that differs between the live system and the desired schedules
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Change {
+    #[table(title = "kind")]
+    pub kind: ProgramKind,
+    #[table(title = "id")]
+    pub id: String,
+    #[table(title = "day")]
+    pub day: &'static str,
+    #[table(title = "live")]
+    pub live: String,
+    #[table(title = "desired")]
+    pub desired: String,
+}
+
+fn display_slots(slots: Option<&Vec<Slot>>) -> String {
+    match slots {
+        None => "missing".into(),
+        Some(slots) if slots.is_empty() => "-".into(),
+        Some(slots) => slots.iter().join(", "),
+    }
+}
+
+/// Days of the desired programs that differ from the live system.
+///
+/// Programs of the live system that are not part of the desired schedules are not changed.
+pub fn diff(live: &Schedules, desired: &Schedules) -> Vec<Change> {
+    let mut result = Vec::new();
+    for (kind, id, program) in desired.programs() {
+        let live_program = live.get(kind, id);
+        for (i, (day, slots)) in program.days().into_iter().enumerate() {
+            let live_slots = live_program.map(|p| p.days()[i].1);
+            if live_slots != Some(slots) {
+                result.push(Change {
+                    kind,
+                    id: id.clone(),
+                    day,
+                    live: display_slots(live_slots),
+                    desired: display_slots(Some(slots)),
+                });
+            }
+        }
+    }
+    result
+}
+
+/// Pushes every desired program with a change to the system. Returns the changes.
+///
+/// All programs are validated before the first one is pushed.
+pub fn apply(c: &Connector, desired: &Schedules) -> anyhow::Result<Vec<Change>> {
+    desired.validate()?;
+    let live = Schedules::from_system(&c.system()?.body)?;
+    let changes = diff(&live, desired);
+    for (kind, id) in changes.iter().map(|c| (c.kind, &c.id)).dedup() {
+        // SAFETY changes are only found for desired programs
+        let program = desired.get(kind, id).unwrap();
+        c.set_time_program(kind, id, program)?;
+    }
+    info!("Applied {} changed days.", changes.len());
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{connector::ApiError, response::system::Setting};
+
+    use super::{diff, ProgramKind, Schedules, Slot, Time, TimeProgram};
+
+    const YAML: &str = r#"
+zones:
+  Control_ZO1:
+    monday:
+      - start: "00:00"
+        setting: NIGHT
+      - start: "06:00"
+        setting: DAY
+      - start: "22:00"
+        setting: NIGHT
+dhw:
+  Control_DHW:
+    monday:
+      - start: "05:30"
+        end: "22:00"
+    sunday:
+      - start: "07:00"
+        end: "24:00"
+"#;
+
+    fn slot(start: &str, end: Option<&str>, setting: Option<Setting>) -> Slot {
+        Slot {
+            start: start.parse().unwrap(),
+            end: end.map(|e| e.parse().unwrap()),
+            setting,
+        }
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(Some(Time(390)), "06:30".parse().ok());
+        assert_eq!("24:00", Time::END_OF_DAY.to_string());
+        assert!("24:10".parse::<Time>().is_err());
+        assert!("6".parse::<Time>().is_err());
+    }
+
+    #[test]
+    fn yaml() {
+        let schedules = Schedules::from_yaml(YAML).unwrap();
+        schedules.validate().unwrap();
+        assert_eq!(3, schedules.zones["Control_ZO1"].monday.len());
+        assert_eq!(
+            Some(Time::END_OF_DAY),
+            schedules.dhw["Control_DHW"].sunday[0].end
+        );
+        // round trip
+        assert_eq!(
+            schedules,
+            Schedules::from_yaml(&schedules.to_yaml().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn validation() {
+        let program = TimeProgram {
+            monday: vec![
+                slot("06:00", Some("08:00"), None),
+                slot("07:00", Some("09:00"), None),
+            ],
+            tuesday: vec![slot("10:00", Some("09:00"), None)],
+            friday: (0..4)
+                .map(|h| Slot {
+                    start: Time::new(h, 0).unwrap(),
+                    end: Time::new(h, 30),
+                    setting: None,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        match program.validate(ProgramKind::Dhw) {
+            Err(ApiError::InvalidTimeProgram(problems)) => {
+                assert!(problems.contains("monday: 06:00-08:00 overlaps 07:00-09:00"));
+                assert!(problems.contains("tuesday: 10:00-09:00 ends before it starts"));
+                assert!(problems.contains("friday: 4 slots, max is 3"));
+            }
+            r => panic!("expected invalid time program, got {:?}", r),
+        }
+
+        // switching points need a setting
+        let mut program = TimeProgram {
+            monday: vec![slot("06:00", None, None)],
+            ..Default::default()
+        };
+        assert!(program.validate(ProgramKind::Zone).is_err());
+        program.monday = vec![
+            slot("06:00", None, Some(Setting::Day)),
+            slot("06:00", None, Some(Setting::Night)),
+        ];
+        assert!(program.validate(ProgramKind::Zone).is_err());
+    }
+
+    #[test]
+    fn changes() {
+        let live = Schedules::from_yaml(YAML).unwrap();
+        let mut desired = live.clone();
+        desired.dhw.get_mut("Control_DHW").unwrap().monday =
+            vec![slot("06:00", Some("21:00"), None)];
+        // not part of the live system
+        desired
+            .ventilation
+            .insert("Control_VE1".into(), TimeProgram::default());
+
+        let changes = diff(&live, &desired);
+        assert_eq!(8, changes.len());
+        assert_eq!(ProgramKind::Dhw, changes[0].kind);
+        assert_eq!("05:30-22:00", changes[0].live);
+        assert_eq!("06:00-21:00", changes[0].desired);
+        assert_eq!("missing", changes[1].live);
+
+        assert!(diff(&live, &live).is_empty());
+    }
+}
@@ -0,0 +1,376 @@
+use std::str::FromStr;
+
+use strum_macros::{AsRefStr, Display, EnumIter, EnumString};
+use thiserror::Error;
+
+/// Physical dimension of a quantity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsRefStr, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Dimension {
+    Temperature,
+    Pressure,
+    Energy,
+    Power,
+    Ratio,
+    Concentration,
+}
+
+/// Unit of a reading as reported by the api or requested by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsRefStr, Display, EnumString, EnumIter)]
+#[strum(ascii_case_insensitive)]
+pub enum Unit {
+    #[strum(to_string = "°C", serialize = "C")]
+    Celsius,
+    #[strum(to_string = "°F", serialize = "F")]
+    Fahrenheit,
+    #[strum(serialize = "bar")]
+    Bar,
+    #[strum(serialize = "kPa")]
+    KiloPascal,
+    #[strum(serialize = "Wh")]
+    WattHour,
+    #[strum(serialize = "kWh")]
+    KiloWattHour,
+    #[strum(serialize = "W")]
+    Watt,
+    #[strum(serialize = "kW")]
+    KiloWatt,
+    #[strum(serialize = "%")]
+    Percent,
+    #[strum(serialize = "ppm")]
+    Ppm,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum UnitError {
+    #[error("Unknown unit \"{0}\"")]
+    Unknown(String),
+    #[error("{unit} is not a unit of {expected}")]
+    Mismatch { expected: Dimension, unit: Unit },
+}
+
+impl Unit {
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Unit::Celsius | Unit::Fahrenheit => Dimension::Temperature,
+            Unit::Bar | Unit::KiloPascal => Dimension::Pressure,
+            Unit::WattHour | Unit::KiloWattHour => Dimension::Energy,
+            Unit::Watt | Unit::KiloWatt => Dimension::Power,
+            Unit::Percent => Dimension::Ratio,
+            Unit::Ppm => Dimension::Concentration,
+        }
+    }
+
+    /// Unit the quantities of a dimension are stored in
+    pub fn base(dimension: Dimension) -> Unit {
+        match dimension {
+            Dimension::Temperature => Unit::Celsius,
+            Dimension::Pressure => Unit::Bar,
+            Dimension::Energy => Unit::WattHour,
+            Dimension::Power => Unit::Watt,
+            Dimension::Ratio => Unit::Percent,
+            Dimension::Concentration => Unit::Ppm,
+        }
+    }
+
+    /// Parses a unit string of the api, unknown units are an error
+    pub fn parse(unit: &str) -> Result<Unit, UnitError> {
+        Unit::from_str(unit.trim()).map_err(|_| UnitError::Unknown(unit.to_string()))
+    }
+
+    fn to_base(self, value: f64) -> f64 {
+        match self {
+            Unit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+            Unit::KiloPascal => value / 100.0,
+            Unit::KiloWattHour | Unit::KiloWatt => value * 1000.0,
+            _ => value,
+        }
+    }
+
+    fn of_base(self, value: f64) -> f64 {
+        match self {
+            Unit::Fahrenheit => value * 9.0 / 5.0 + 32.0,
+            Unit::KiloPascal => value * 100.0,
+            Unit::KiloWattHour | Unit::KiloWatt => value / 1000.0,
+            _ => value,
+        }
+    }
+
+    fn check(self, expected: Dimension) -> Result<Unit, UnitError> {
+        if self.dimension() == expected {
+            Ok(self)
+        } else {
+            Err(UnitError::Mismatch {
+                expected,
+                unit: self,
+            })
+        }
+    }
+}
+
+macro_rules! quantity {
+    ($(#[$meta:meta])* $name:ident, $dimension:ident, $base:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+        pub struct $name(f64);
+
+        impl $name {
+            pub const DIMENSION: Dimension = Dimension::$dimension;
+
+            /// Value in the base unit of the dimension
+            pub fn $base(value: f64) -> $name {
+                $name(value)
+            }
+
+            pub fn new(value: f64, unit: Unit) -> Result<$name, UnitError> {
+                Ok($name(unit.check(Self::DIMENSION)?.to_base(value)))
+            }
+
+            pub fn get(&self, unit: Unit) -> Result<f64, UnitError> {
+                Ok(unit.check(Self::DIMENSION)?.of_base(self.0))
+            }
+
+            /// Value in the base unit of the dimension
+            pub fn value(&self) -> f64 {
+                self.0
+            }
+        }
+
+        impl From<$name> for Quantity {
+            fn from(q: $name) -> Quantity {
+                Quantity::$name(q)
+            }
+        }
+    };
+}
+
+quantity!(
+    /// Temperature in °C
+    Temperature,
+    Temperature,
+    from_celsius
+);
+quantity!(
+    /// Pressure in bar
+    Pressure,
+    Pressure,
+    from_bar
+);
+quantity!(
+    /// Energy in Wh
+    Energy,
+    Energy,
+    from_wh
+);
+quantity!(
+    /// Power in W
+    Power,
+    Power,
+    from_w
+);
+quantity!(
+    /// Ratio in %, e.g. relative humidity
+    Ratio,
+    Ratio,
+    from_percent
+);
+quantity!(
+    /// Concentration in ppm, e.g. co2
+    Concentration,
+    Concentration,
+    from_ppm
+);
+
+impl Temperature {
+    pub fn celsius(&self) -> f64 {
+        self.0
+    }
+
+    pub fn fahrenheit(&self) -> f64 {
+        Unit::Fahrenheit.of_base(self.0)
+    }
+}
+
+impl Pressure {
+    pub fn bar(&self) -> f64 {
+        self.0
+    }
+
+    pub fn kpa(&self) -> f64 {
+        Unit::KiloPascal.of_base(self.0)
+    }
+}
+
+impl Energy {
+    pub fn wh(&self) -> f64 {
+        self.0
+    }
+
+    pub fn kwh(&self) -> f64 {
+        Unit::KiloWattHour.of_base(self.0)
+    }
+}
+
+impl Power {
+    pub fn w(&self) -> f64 {
+        self.0
+    }
+
+    pub fn kw(&self) -> f64 {
+        Unit::KiloWatt.of_base(self.0)
+    }
+}
+
+impl Ratio {
+    pub fn percent(&self) -> f64 {
+        self.0
+    }
+}
+
+impl Concentration {
+    pub fn ppm(&self) -> f64 {
+        self.0
+    }
+}
+
+/// A value with its dimension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantity {
+    Temperature(Temperature),
+    Pressure(Pressure),
+    Energy(Energy),
+    Power(Power),
+    Ratio(Ratio),
+    Concentration(Concentration),
+}
+
+impl Quantity {
+    pub fn new(value: f64, unit: Unit) -> Quantity {
+        let value = unit.to_base(value);
+        match unit.dimension() {
+            Dimension::Temperature => Temperature(value).into(),
+            Dimension::Pressure => Pressure(value).into(),
+            Dimension::Energy => Energy(value).into(),
+            Dimension::Power => Power(value).into(),
+            Dimension::Ratio => Ratio(value).into(),
+            Dimension::Concentration => Concentration(value).into(),
+        }
+    }
+
+    /// Quantity of a reported value and unit string, e.g. 1.8 and "bar"
+    pub fn parse(value: f64, unit: &str) -> Result<Quantity, UnitError> {
+        Ok(Quantity::new(value, Unit::parse(unit)?))
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Quantity::Temperature(_) => Dimension::Temperature,
+            Quantity::Pressure(_) => Dimension::Pressure,
+            Quantity::Energy(_) => Dimension::Energy,
+            Quantity::Power(_) => Dimension::Power,
+            Quantity::Ratio(_) => Dimension::Ratio,
+            Quantity::Concentration(_) => Dimension::Concentration,
+        }
+    }
+
+    /// Value in the base unit of the dimension
+    pub fn value(&self) -> f64 {
+        match self {
+            Quantity::Temperature(q) => q.value(),
+            Quantity::Pressure(q) => q.value(),
+            Quantity::Energy(q) => q.value(),
+            Quantity::Power(q) => q.value(),
+            Quantity::Ratio(q) => q.value(),
+            Quantity::Concentration(q) => q.value(),
+        }
+    }
+
+    pub fn get(&self, unit: Unit) -> Result<f64, UnitError> {
+        Ok(unit.check(self.dimension())?.of_base(self.value()))
+    }
+}
+
+/// Target units of a conversion, at most one per dimension
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnitSet(Vec<Unit>);
+
+impl UnitSet {
+    pub fn new(units: &[Unit]) -> anyhow::Result<UnitSet> {
+        for (i, unit) in units.iter().enumerate() {
+            if let Some(other) = units[..i]
+                .iter()
+                .find(|u| u.dimension() == unit.dimension())
+            {
+                anyhow::bail!(
+                    "{} and {} are both units of {}.",
+                    other,
+                    unit,
+                    unit.dimension()
+                );
+            }
+        }
+        Ok(UnitSet(units.to_vec()))
+    }
+
+    /// Requested unit of a dimension, the base unit if none was requested
+    pub fn unit(&self, dimension: Dimension) -> Unit {
+        self.0
+            .iter()
+            .find(|u| u.dimension() == dimension)
+            .copied()
+            .unwrap_or_else(|| Unit::base(dimension))
+    }
+
+    /// Converts a value in the base unit of a dimension to the requested unit
+    pub fn convert(&self, dimension: Dimension, value: f64) -> f64 {
+        self.unit(dimension).of_base(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Unit::parse("°C"), Ok(Unit::Celsius));
+        assert_eq!(Unit::parse("kwh"), Ok(Unit::KiloWattHour));
+        assert_eq!(Unit::parse(" bar"), Ok(Unit::Bar));
+        assert_eq!(Unit::parse("ppb"), Err(UnitError::Unknown("ppb".into())));
+        assert_eq!(Unit::Celsius.to_string(), "°C");
+        assert_eq!(Unit::KiloPascal.to_string(), "kPa");
+
+        let q = Quantity::parse(1.8, "bar").unwrap();
+        assert_eq!(q.dimension(), Dimension::Pressure);
+        assert_eq!(q, Quantity::Pressure(Pressure::from_bar(1.8)));
+    }
+
+    #[test]
+    fn convert() {
+        let t = Temperature::new(212.0, Unit::Fahrenheit).unwrap();
+        assert!((t.celsius() - 100.0).abs() < 1e-9);
+        assert!((Temperature::from_celsius(-40.0).fahrenheit() + 40.0).abs() < 1e-9);
+        assert_eq!(Pressure::from_bar(1.5).kpa(), 150.0);
+        assert_eq!(Energy::new(2.5, Unit::KiloWattHour).unwrap().wh(), 2500.0);
+        assert_eq!(Energy::from_wh(500.0).kwh(), 0.5);
+        assert_eq!(
+            Energy::new(1.0, Unit::Bar),
+            Err(UnitError::Mismatch {
+                expected: Dimension::Energy,
+                unit: Unit::Bar
+            })
+        );
+        assert!(Quantity::Ratio(Ratio::from_percent(40.0))
+            .get(Unit::Ppm)
+            .is_err());
+    }
+
+    #[test]
+    fn unit_set() {
+        let units = UnitSet::new(&[Unit::KiloWattHour, Unit::Fahrenheit]).unwrap();
+        assert_eq!(units.convert(Dimension::Energy, 1500.0), 1.5);
+        assert_eq!(units.convert(Dimension::Temperature, 0.0), 32.0);
+        assert_eq!(units.convert(Dimension::Pressure, 2.0), 2.0);
+        assert!(UnitSet::new(&[Unit::WattHour, Unit::KiloWattHour]).is_err());
+    }
+}
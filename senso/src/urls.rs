@@ -6,9 +6,25 @@ pub trait Urls {
     fn LOGOUT(&self) -> &str;
 
     fn LIVE_REPORT(&self) -> &str;
+    fn HVAC_STATE(&self) -> &str;
+    fn FACILITY_DETAILS(&self) -> &str;
+    fn GATEWAY_TYPE(&self) -> &str;
 
     fn SYSTEM(&self) -> &str;
     fn SYSTEM_STATUS(&self) -> &str;
+    fn DHW_SETPOINT(&self, dhw_id: &str) -> String;
+    fn DHW_OPERATION_MODE(&self, dhw_id: &str) -> String;
+    fn ZONE_SETPOINT(&self, zone_id: &str) -> String;
+    fn ZONE_QUICK_VETO(&self, zone_id: &str) -> String;
+    fn HOLIDAY_MODE(&self) -> &str;
+    fn QUICK_MODE(&self) -> &str;
+    fn VENTILATION_OPERATION_MODE(&self, ventilation_id: &str) -> String;
+    fn VENTILATION_DAY_LEVEL(&self, ventilation_id: &str) -> String;
+    fn VENTILATION_NIGHT_LEVEL(&self, ventilation_id: &str) -> String;
+    fn ZONE_TIME_PROGRAM(&self, zone_id: &str) -> String;
+    fn DHW_TIME_PROGRAM(&self, dhw_id: &str) -> String;
+    fn CIRCULATION_TIME_PROGRAM(&self, dhw_id: &str) -> String;
+    fn VENTILATION_TIME_PROGRAM(&self, ventilation_id: &str) -> String;
 
     fn EMF_DEVICES(&self) -> &str;
     fn EMF_REPORT_DEVICE(&self, device_id: &str) -> String;
@@ -44,8 +60,13 @@ pub struct VaillantV4 {
     new_token: String,
     logout: String,
     live_report: String,
+    hvac_state: String,
+    facility_details: String,
+    gateway_type: String,
     system: String,
     system_status: String,
+    holiday_mode: String,
+    quick_mode: String,
     emf_devices: String,
 }
 
@@ -69,8 +90,13 @@ impl VaillantV4 {
             new_token: base_authenticate.clone() + "/token/new",
             logout: base_authenticate + "/logout",
             live_report: facilities.clone() + "/livereport/v1",
+            hvac_state: facilities.clone() + "/hvacstate/v1/overview",
+            facility_details: facilities.clone() + "/system/v1/details",
+            gateway_type: facilities.clone() + "/public/v1/gatewayType",
             system: system.clone(),
-            system_status: system + "/status",
+            system_status: system.clone() + "/status",
+            holiday_mode: system.clone() + "/configuration/holidaymode",
+            quick_mode: system + "/configuration/quickmode",
             emf_devices: facilities + "/emf/v1/devices",
         }
     }
@@ -93,6 +119,18 @@ impl Urls for VaillantV4 {
         &self.live_report
     }
 
+    fn HVAC_STATE(&self) -> &str {
+        &self.hvac_state
+    }
+
+    fn FACILITY_DETAILS(&self) -> &str {
+        &self.facility_details
+    }
+
+    fn GATEWAY_TYPE(&self) -> &str {
+        &self.gateway_type
+    }
+
     fn SYSTEM(&self) -> &str {
         &self.system
     }
@@ -101,6 +139,79 @@ impl Urls for VaillantV4 {
         &self.system_status
     }
 
+    fn DHW_SETPOINT(&self, dhw_id: &str) -> String {
+        format!(
+            "{}/dhw/{}/hotwater/configuration/temperature_setpoint",
+            self.system, dhw_id
+        )
+    }
+
+    fn DHW_OPERATION_MODE(&self, dhw_id: &str) -> String {
+        format!(
+            "{}/dhw/{}/hotwater/configuration/operation_mode",
+            self.system, dhw_id
+        )
+    }
+
+    fn ZONE_SETPOINT(&self, zone_id: &str) -> String {
+        format!(
+            "{}/zones/{}/heating/configuration/setpoint_temperature",
+            self.system, zone_id
+        )
+    }
+
+    fn ZONE_QUICK_VETO(&self, zone_id: &str) -> String {
+        format!("{}/zones/{}/configuration/quick_veto", self.system, zone_id)
+    }
+
+    fn HOLIDAY_MODE(&self) -> &str {
+        &self.holiday_mode
+    }
+
+    fn QUICK_MODE(&self) -> &str {
+        &self.quick_mode
+    }
+
+    fn VENTILATION_OPERATION_MODE(&self, ventilation_id: &str) -> String {
+        format!(
+            "{}/ventilation/{}/fan/configuration/operation_mode",
+            self.system, ventilation_id
+        )
+    }
+
+    fn VENTILATION_DAY_LEVEL(&self, ventilation_id: &str) -> String {
+        format!(
+            "{}/ventilation/{}/fan/configuration/day_level",
+            self.system, ventilation_id
+        )
+    }
+
+    fn VENTILATION_NIGHT_LEVEL(&self, ventilation_id: &str) -> String {
+        format!(
+            "{}/ventilation/{}/fan/configuration/night_level",
+            self.system, ventilation_id
+        )
+    }
+
+    fn ZONE_TIME_PROGRAM(&self, zone_id: &str) -> String {
+        format!("{}/zones/{}/heating/timeprogram", self.system, zone_id)
+    }
+
+    fn DHW_TIME_PROGRAM(&self, dhw_id: &str) -> String {
+        format!("{}/dhw/{}/hotwater/timeprogram", self.system, dhw_id)
+    }
+
+    fn CIRCULATION_TIME_PROGRAM(&self, dhw_id: &str) -> String {
+        format!("{}/dhw/{}/circulation/timeprogram", self.system, dhw_id)
+    }
+
+    fn VENTILATION_TIME_PROGRAM(&self, ventilation_id: &str) -> String {
+        format!(
+            "{}/ventilation/{}/fan/timeprogram",
+            self.system, ventilation_id
+        )
+    }
+
     fn EMF_DEVICES(&self) -> &str {
         &self.emf_devices
     }
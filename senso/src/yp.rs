@@ -1,11 +1,14 @@
+use std::collections::HashMap;
+
 use crate::{
-    array2d,
     connector::Connector,
+    db::Channel,
     request::emf::{Query, TimeRange},
     response::{
-        emf_devices::{EmfDevice, EmfFunction, EnergyType},
+        emf_devices::{self, EmfDevice, EmfFunction, EnergyType},
         emf_report_device::{self, Dataset},
     },
+    units::Energy,
 };
 use anyhow::anyhow;
 use chrono::{NaiveDate, NaiveDateTime};
@@ -13,8 +16,8 @@ use cli_table::Table;
 use itertools::Itertools;
 use num_traits::cast::FromPrimitive;
 
-/// data for central heating and hotwater with total
-#[derive(Debug, Table)]
+/// data for central heating and hotwater with total, cooling and ventilation, energies in Wh
+#[derive(Debug, Default, Table)]
 pub struct YpData {
     pub ts: NaiveDateTime,
     pub ch_hp_y: f64,
@@ -28,6 +31,10 @@ pub struct YpData {
     pub total_y: f64,
     pub total_p: f64,
     pub total_yp: f64,
+    pub co_hp_y: f64,
+    pub co_hp_p: f64,
+    pub co_eer: f64,
+    pub ve_p: f64,
 }
 
 pub fn calc_yp(y: f64, p: f64) -> f64 {
@@ -39,163 +46,308 @@ pub fn calc_yp(y: f64, p: f64) -> f64 {
     }
 }
 
+/// energy efficiency ratio for cooling
+///
+/// The yield while cooling is the heat removed from the building,
+/// so unlike the yp the power usage is not part of the useful energy.
+pub fn calc_eer(y: f64, p: f64) -> f64 {
+    if p == 0.0 {
+        0.0
+    } else {
+        // round with precision of 4 places after 0.0000
+        ((y / p) * 10000.0).round() / 10000.0
+    }
+}
+
+/// Key of one series in the energy balance
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BalanceKey {
+    pub function: EmfFunction,
+    pub device: EmfDevice,
+    pub energy_type: EnergyType,
+}
+
+/// Daily energy values of one week keyed by function, device type and energy type.
+///
+/// Values of several devices with the same type (e.g. two heat pumps) are summed up.
+/// Missing series are treated as 0.0, so installations without a boiler work too.
+#[derive(Debug)]
+pub struct EnergyBalance {
+    days: Vec<NaiveDateTime>,
+    values: HashMap<BalanceKey, Vec<f64>>,
+}
+
+impl EnergyBalance {
+    /// Empty balance for every day of the given iso week
+    pub fn new(year: i32, week_nr: u32) -> anyhow::Result<Self> {
+        let mut days: Vec<NaiveDateTime> = Vec::with_capacity(7);
+
+        for day in 0..=6_u8 {
+            // SAFTEY weekday enum is defined between 0 and 6
+            days.push(
+                NaiveDate::from_isoywd_opt(
+                    year,
+                    week_nr,
+                    chrono::Weekday::from_u8(day).ok_or(anyhow!("Can't convert u8 to Weekday"))?,
+                )
+                .ok_or(anyhow!("out-of-range date and/or invalid week number"))?
+                .and_hms_opt(0, 0, 0)
+                .ok_or(anyhow!(""))?,
+            );
+        }
+
+        Ok(Self {
+            days,
+            values: HashMap::new(),
+        })
+    }
+
+    /// Balance from retrieved usage of several functions.
+    ///
+    /// All usages have to be for the same week.
+    pub fn from_usage(usage: &[&UsageFunctionWeek]) -> anyhow::Result<Self> {
+        let first = usage
+            .first()
+            .ok_or(anyhow!("Need at least one usage to build a balance."))?;
+        let mut balance = Self::new(first.year, first.week_nr)?;
+        for u in usage {
+            balance.add_usage(u)?;
+        }
+        Ok(balance)
+    }
+
+    /// Adds all retrieved datasets of a usage to the balance
+    pub fn add_usage(&mut self, usage: &UsageFunctionWeek) -> anyhow::Result<()> {
+        if self.days.first()
+            != NaiveDate::from_isoywd_opt(usage.year, usage.week_nr, chrono::Weekday::Mon)
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .as_ref()
+        {
+            return Err(anyhow!("Usage is not for the same week as the balance."));
+        }
+
+        for (device, energy_type, root) in usage.reports() {
+            let values = root
+                .body
+                .first()
+                .map(|b| b.dataset.iter().map(|d| d.energy().wh()).collect_vec())
+                .unwrap_or_default();
+            self.insert(
+                BalanceKey {
+                    function: usage.function.clone(),
+                    device,
+                    energy_type,
+                },
+                &values,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Adds daily values to the series of given key
+    pub fn insert(&mut self, key: BalanceKey, values: &[f64]) -> anyhow::Result<()> {
+        if values.len() > self.days.len() {
+            return Err(anyhow!(
+                "Dataset is to long for {} days. Got {} values.",
+                self.days.len(),
+                values.len()
+            ));
+        }
+        let series = self
+            .values
+            .entry(key)
+            .or_insert_with(|| vec![0.0; self.days.len()]);
+        for (i, v) in values.iter().enumerate() {
+            series[i] += v;
+        }
+        Ok(())
+    }
+
+    /// Start of every day in the balance
+    pub fn days(&self) -> &[NaiveDateTime] {
+        &self.days
+    }
+
+    /// All functions with at least one series
+    pub fn functions(&self) -> Vec<EmfFunction> {
+        self.values
+            .keys()
+            .map(|k| k.function.clone())
+            .unique()
+            .sorted_by(|a, b| a.as_ref().cmp(b.as_ref()))
+            .collect()
+    }
+
+    /// Daily values for given function, device type and energy type
+    pub fn get(
+        &self,
+        function: EmfFunction,
+        device: EmfDevice,
+        energy_type: EnergyType,
+    ) -> Vec<f64> {
+        self.values
+            .get(&BalanceKey {
+                function,
+                device,
+                energy_type,
+            })
+            .cloned()
+            .unwrap_or_else(|| vec![0.0; self.days.len()])
+    }
+
+    /// Daily values for given functions and energy type summed over all devices
+    pub fn total(&self, functions: &[EmfFunction], energy_type: EnergyType) -> Vec<f64> {
+        let mut total = vec![0.0; self.days.len()];
+        for (_, series) in self
+            .values
+            .iter()
+            .filter(|(k, _)| k.energy_type == energy_type && functions.contains(&k.function))
+        {
+            for (t, v) in total.iter_mut().zip(series) {
+                *t += v;
+            }
+        }
+        total
+    }
+
+    /// Daily yp for given functions.
+    ///
+    /// yield over the power usage of all devices
+    pub fn yp(&self, functions: &[EmfFunction]) -> Vec<f64> {
+        self.total(functions, EnergyType::EnvironmentalYield)
+            .iter()
+            .zip(self.total(functions, EnergyType::ConsumedElectricalPower))
+            .map(|(y, p)| calc_yp(*y, p))
+            .collect()
+    }
+
+    /// Daily energy efficiency ratio for given functions, e.g. cooling.
+    ///
+    /// heat removed from the building (the yield) over the power usage of all devices,
+    /// the power usage itself is not useful energy as in the yp
+    pub fn eer(&self, functions: &[EmfFunction]) -> Vec<f64> {
+        self.total(functions, EnergyType::EnvironmentalYield)
+            .iter()
+            .zip(self.total(functions, EnergyType::ConsumedElectricalPower))
+            .map(|(y, p)| calc_eer(*y, p))
+            .collect()
+    }
+}
+
+impl YpData {
+    /// Value of a usage channel, None for sensor channels
+    pub fn value(&self, channel: Channel) -> Option<f64> {
+        match channel {
+            Channel::ChHpY => Some(self.ch_hp_y),
+            Channel::ChHpP => Some(self.ch_hp_p),
+            Channel::ChBoP => Some(self.ch_bo_p),
+            Channel::HwHpY => Some(self.hw_hp_y),
+            Channel::HwHpP => Some(self.hw_hp_p),
+            Channel::HwBoP => Some(self.hw_bo_p),
+            Channel::CoHpY => Some(self.co_hp_y),
+            Channel::CoHpP => Some(self.co_hp_p),
+            Channel::VeP => Some(self.ve_p),
+            _ => None,
+        }
+    }
+
+    /// Energy of a usage channel, None for sensor channels
+    pub fn energy(&self, channel: Channel) -> Option<Energy> {
+        self.value(channel).map(Energy::from_wh)
+    }
+
+    /// Fixed columns for central heating, hotwater and cooling derived from a balance
+    pub fn from_balance(balance: &EnergyBalance) -> Vec<YpData> {
+        use EmfDevice::*;
+        use EmfFunction::*;
+        use EnergyType::*;
+
+        let ch_hp_y = balance.get(CentralHeating, HeatPump, EnvironmentalYield);
+        let ch_hp_p = balance.get(CentralHeating, HeatPump, ConsumedElectricalPower);
+        let ch_bo_p = balance.get(CentralHeating, Boiler, ConsumedElectricalPower);
+        let ch_yp = balance.yp(&[CentralHeating]);
+        let hw_hp_y = balance.get(DomesticHotWater, HeatPump, EnvironmentalYield);
+        let hw_hp_p = balance.get(DomesticHotWater, HeatPump, ConsumedElectricalPower);
+        let hw_bo_p = balance.get(DomesticHotWater, Boiler, ConsumedElectricalPower);
+        let hw_yp = balance.yp(&[DomesticHotWater]);
+        let total_y = balance.total(&[CentralHeating, DomesticHotWater], EnvironmentalYield);
+        let total_p = balance.total(&[CentralHeating, DomesticHotWater], ConsumedElectricalPower);
+        let total_yp = balance.yp(&[CentralHeating, DomesticHotWater]);
+        let co_hp_y = balance.get(Cooling, HeatPump, EnvironmentalYield);
+        let co_hp_p = balance.get(Cooling, HeatPump, ConsumedElectricalPower);
+        let co_eer = balance.eer(&[Cooling]);
+        let ve_p = balance.total(&[EmfFunction::Ventilation], ConsumedElectricalPower);
+
+        balance
+            .days()
+            .iter()
+            .enumerate()
+            .map(|(i, ts)| YpData {
+                ts: *ts,
+                ch_hp_y: ch_hp_y[i],
+                ch_hp_p: ch_hp_p[i],
+                ch_bo_p: ch_bo_p[i],
+                ch_yp: ch_yp[i],
+                hw_hp_y: hw_hp_y[i],
+                hw_hp_p: hw_hp_p[i],
+                hw_bo_p: hw_bo_p[i],
+                hw_yp: hw_yp[i],
+                total_y: total_y[i],
+                total_p: total_p[i],
+                total_yp: total_yp[i],
+                co_hp_y: co_hp_y[i],
+                co_hp_p: co_hp_p[i],
+                co_eer: co_eer[i],
+                ve_p: ve_p[i],
+            })
+            .collect()
+    }
+}
+
+/// Build the fixed columns for central heating and hotwater for one week.
 pub fn build_yp_data_vec(
     dhw: UsageFunctionWeek,
     ch: UsageFunctionWeek,
 ) -> anyhow::Result<Vec<YpData>> {
-    // # central heatings
-    // heat pump yield
-    let ch_hp_y_vec = ch
-        .get_dataset(EmfDevice::HeatPump, EnergyType::EnvironmentalYield)
-        .map(|x| x.value)
-        .collect_vec();
-    // heat pump power usage
-    let ch_hp_p_vec = ch
-        .get_dataset(EmfDevice::HeatPump, EnergyType::ConsumedElectricalPower)
-        .map(|x| x.value)
-        .collect_vec();
-    // boiler power usage
-    let ch_bo_p_vec = ch
-        .get_dataset(EmfDevice::Boiler, EnergyType::ConsumedElectricalPower)
-        .map(|x| x.value)
-        .collect_vec();
-
-    // total power usage of central heating
-    let ch_p_vec = ch.get_total(EnergyType::ConsumedElectricalPower)?;
-    // yp of central heating
-    // yield is only generated from the heatpump
-    let cp_yp_vec: Vec<f64> = ch_hp_y_vec
-        .iter()
-        .zip(ch_p_vec.iter())
-        .map(|(ch_hp_y, ch_p)| calc_yp(*ch_hp_y, *ch_p))
-        .collect_vec();
-
-    // # how water
-    // heat pump yield
-    let hw_hp_y_vec = dhw
-        .get_dataset(EmfDevice::HeatPump, EnergyType::EnvironmentalYield)
-        .map(|x| x.value)
-        .collect_vec();
-    // heat pump power usage
-    let hw_hp_p_vec = dhw
-        .get_dataset(EmfDevice::HeatPump, EnergyType::ConsumedElectricalPower)
-        .map(|x| x.value)
-        .collect_vec();
-    // boiler power usage
-    let hw_bo_p_vec = dhw
-        .get_dataset(EmfDevice::Boiler, EnergyType::ConsumedElectricalPower)
-        .map(|x| x.value)
-        .collect_vec();
-    // total power usage of how water
-    let hw_p_vec = dhw.get_total(EnergyType::ConsumedElectricalPower)?;
-    // yp of hot water
-    // yield is only generated from the heatpump
-    let hw_yp_vec = hw_hp_y_vec
-        .iter()
-        .zip(hw_p_vec.iter())
-        .map(|(hw_hp_y, hw_p)| calc_yp(*hw_hp_y, *hw_p))
-        .collect_vec();
+    let balance = EnergyBalance::from_usage(&[&ch, &dhw])?;
+    Ok(YpData::from_balance(&balance))
+}
 
-    // # Total
-    // yield
-    let total_y = ch_hp_y_vec
-        .iter()
-        .zip(hw_hp_y_vec.iter())
-        .map(|(ch_hp_y, hw_hp_y)| ch_hp_y + hw_hp_y)
-        .collect_vec();
-    // power usage
-    let total_p = ch_p_vec
+/// Heat pumps and boilers with heating, hot water or cooling reports, any number of each
+pub fn usage_devices(devices: &emf_devices::Root) -> Vec<(EmfDevice, String)> {
+    devices
+        .body
         .iter()
-        .zip(hw_p_vec.iter())
-        .map(|(ch_p, hw_p)| ch_p + hw_p)
-        .collect_vec();
-    // yp
-    let total_yp = total_y
-        .iter()
-        .zip(total_p.iter())
-        .map(|(y, p)| calc_yp(*y, *p))
-        .collect_vec();
-
-    // create matrix from all the vecs
-    // order important for result
-    let array2d = array2d![
-        ch_hp_y_vec,
-        ch_hp_p_vec,
-        ch_bo_p_vec,
-        cp_yp_vec,
-        hw_hp_y_vec,
-        hw_hp_p_vec,
-        hw_bo_p_vec,
-        hw_yp_vec,
-        total_y,
-        total_p,
-        total_yp
-    ]?;
-
-    // create timestamp
-    // TODO check if correct
-    let mut timestamps: Vec<NaiveDateTime> = Vec::with_capacity(7);
-
-    for day in 0..=6_u8 {
-        // SAFTEY weekday enum is defined between 0 and 6
-        timestamps.push(
-            NaiveDate::from_isoywd_opt(
-                dhw.year,
-                dhw.week_nr,
-                chrono::Weekday::from_u8(day).ok_or(anyhow!("Can't convert u8 to Weekday"))?,
-            )
-            .ok_or(anyhow!("out-of-range date and/or invalid week number"))?
-            .and_hms_opt(0, 0, 0)
-            .ok_or(anyhow!(""))?,
-        );
-    }
-
-    let result = array2d
-        // iter over colums
-        .over_columns()
-        .into_iter()
-        // create tuple of yp data, order set in array2d
-        .tuples::<(_, _, _, _, _, _, _, _, _, _, _)>()
-        // add timestamp
-        .zip(timestamps.iter())
-        // create yp data struct
-        .map(
-            |(
-                (
-                    ch_hp_y,
-                    ch_hp_p,
-                    ch_bo_p,
-                    ch_yp,
-                    hw_hp_y,
-                    hw_hp_p,
-                    hw_bo_p,
-                    hw_yp,
-                    total_y,
-                    total_p,
-                    total_yp,
-                ),
-                ts,
-            )| YpData {
-                ts: *ts,
-                ch_hp_y: *ch_hp_y,
-                ch_hp_p: *ch_hp_p,
-                ch_bo_p: *ch_bo_p,
-                ch_yp: *ch_yp,
-                hw_hp_y: *hw_hp_y,
-                hw_hp_p: *hw_hp_p,
-                hw_bo_p: *hw_bo_p,
-                hw_yp: *hw_yp,
-                total_y: *total_y,
-                total_p: *total_p,
-                total_yp: *total_yp,
-            },
-        )
-        .collect();
+        .filter(|d| matches!(d.type_field, EmfDevice::HeatPump | EmfDevice::Boiler))
+        .filter(|d| {
+            d.reports.iter().any(|r| {
+                matches!(
+                    r.function,
+                    EmfFunction::CentralHeating
+                        | EmfFunction::DomesticHotWater
+                        | EmfFunction::Cooling
+                )
+            })
+        })
+        .map(|d| (d.type_field.clone(), d.id.clone()))
+        .collect()
+}
 
-    Ok(result)
+/// Devices with ventilation reports, e.g. to retrieve the ventilation usage only if it's reported
+pub fn ventilation_devices(devices: &emf_devices::Root) -> Vec<(EmfDevice, String)> {
+    devices
+        .body
+        .iter()
+        .filter(|d| {
+            d.reports
+                .iter()
+                .any(|r| r.function == EmfFunction::Ventilation)
+        })
+        .map(|d| (d.type_field.clone(), d.id.clone()))
+        .collect()
 }
 
-/// Power usage and yield for given funtion(HotWater, Heating) and devices(Heatpump, Boiler)
+/// Power usage and yield for given funtion(HotWater, Heating, Cooling) and devices(Heatpump, Boiler)
 #[derive(Debug)]
 pub struct UsageFunctionWeek<'a> {
     function: EmfFunction,
@@ -234,7 +386,7 @@ impl<'a> UsageFunctionWeek<'a> {
 
         let q_power = Query::new(
             EnergyType::ConsumedElectricalPower,
-            self.function,
+            self.function.clone(),
             TimeRange::Week,
             start,
             None,
@@ -242,7 +394,7 @@ impl<'a> UsageFunctionWeek<'a> {
 
         let q_yield = Query::new(
             EnergyType::EnvironmentalYield,
-            self.function,
+            self.function.clone(),
             TimeRange::Week,
             start,
             None,
@@ -250,28 +402,35 @@ impl<'a> UsageFunctionWeek<'a> {
 
         // call api for every device
         for (device, device_id) in self.devices {
+            // Boiler can't cool
+            if self.function == EmfFunction::Cooling && *device == EmfDevice::Boiler {
+                continue;
+            }
+
             let resp_power = conn.emf_report_device(device_id, &q_power)?;
-            self.power_usage.push((*device, resp_power));
+            self.power_usage.push((device.clone(), resp_power));
 
-            // Boiler has no yield
-            if *device != EmfDevice::Boiler {
+            // Boiler and ventilation have no yield
+            if *device != EmfDevice::Boiler && self.function != EmfFunction::Ventilation {
                 let resp_yield = conn.emf_report_device(device_id, &q_yield)?;
-                self.yield_vec.push((*device, resp_yield));
+                self.yield_vec.push((device.clone(), resp_yield));
             }
         }
 
         Ok(())
     }
 
-    ///
+    /// Iterate over the daily values of all datasets for given device and energy type
     pub fn get_dataset(
         &self,
         emf_device: EmfDevice,
         energy_type: EnergyType,
     ) -> impl Iterator<Item = &Dataset> {
-        let dataset = match energy_type {
+        let dataset: &[(EmfDevice, emf_report_device::Root)] = match energy_type {
             EnergyType::EnvironmentalYield => &self.yield_vec,
             EnergyType::ConsumedElectricalPower => &self.power_usage,
+            // never retrieved
+            EnergyType::Unknown(_) => &[],
         };
 
         let dataset = dataset
@@ -290,16 +449,177 @@ impl<'a> UsageFunctionWeek<'a> {
     /// Get total power/yield
     pub fn get_total(&self, energy_type: EnergyType) -> anyhow::Result<Vec<f64>> {
         let mut total: Vec<f64> = vec![0.0; 7];
-        for (device, _) in self.devices {
-            for (i, data) in self.get_dataset(*device, energy_type).enumerate() {
+        for (_, e, root) in self.reports() {
+            if e != energy_type {
+                continue;
+            }
+            for (i, data) in root.body.iter().flat_map(|b| &b.dataset).enumerate() {
                 total[i] = total.get(i).ok_or(anyhow!(
                     "Index of Result(get_total) is out of bound. Dataset is to long for 1 Week."
-                ))? + data.value;
+                ))? + data.energy().wh();
             }
         }
         Ok(total)
     }
+
+    /// Retrieved reports of every device with their energy type
+    pub fn reports(
+        &self,
+    ) -> impl Iterator<Item = (EmfDevice, EnergyType, &emf_report_device::Root)> {
+        self.power_usage
+            .iter()
+            .map(|(d, r)| (d.clone(), EnergyType::ConsumedElectricalPower, r))
+            .chain(
+                self.yield_vec
+                    .iter()
+                    .map(|(d, r)| (d.clone(), EnergyType::EnvironmentalYield, r)),
+            )
+    }
+
+    pub fn function(&self) -> EmfFunction {
+        self.function.clone()
+    }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use crate::response::emf_devices::{self, EmfDevice, EmfFunction, EnergyType};
+
+    use super::{usage_devices, ventilation_devices, BalanceKey, EnergyBalance, YpData};
+
+    fn key(function: EmfFunction, device: EmfDevice, energy_type: EnergyType) -> BalanceKey {
+        BalanceKey {
+            function,
+            device,
+            energy_type,
+        }
+    }
+
+    #[test]
+    fn balance_without_boiler() {
+        use EmfDevice::*;
+        use EmfFunction::*;
+        use EnergyType::*;
+
+        let mut balance = EnergyBalance::new(2023, 9).unwrap();
+        // two heat pumps for central heating
+        balance
+            .insert(
+                key(CentralHeating, HeatPump, EnvironmentalYield),
+                &[3000.0; 7],
+            )
+            .unwrap();
+        balance
+            .insert(
+                key(CentralHeating, HeatPump, EnvironmentalYield),
+                &[1000.0; 7],
+            )
+            .unwrap();
+        balance
+            .insert(
+                key(CentralHeating, HeatPump, ConsumedElectricalPower),
+                &[1000.0; 7],
+            )
+            .unwrap();
+        balance
+            .insert(
+                key(CentralHeating, HeatPump, ConsumedElectricalPower),
+                &[1000.0; 7],
+            )
+            .unwrap();
+        // cooling
+        balance
+            .insert(key(Cooling, HeatPump, EnvironmentalYield), &[2000.0; 7])
+            .unwrap();
+        balance
+            .insert(key(Cooling, HeatPump, ConsumedElectricalPower), &[500.0; 7])
+            .unwrap();
+
+        // dataset to long for one week
+        assert!(balance
+            .insert(key(Cooling, HeatPump, EnvironmentalYield), &[0.0; 8])
+            .is_err());
+
+        assert_eq!(vec![CentralHeating, Cooling], balance.functions());
+        assert_eq!(
+            vec![4000.0; 7],
+            balance.get(CentralHeating, HeatPump, EnvironmentalYield)
+        );
+        assert_eq!(
+            vec![0.0; 7],
+            balance.get(CentralHeating, Boiler, ConsumedElectricalPower)
+        );
+        assert_eq!(vec![3.0; 7], balance.yp(&[CentralHeating]));
+        // no hot water
+        assert_eq!(vec![0.0; 7], balance.yp(&[DomesticHotWater]));
+
+        let yp_data = YpData::from_balance(&balance);
+        assert_eq!(7, yp_data.len());
+        assert_eq!(3.0, yp_data[0].ch_yp);
+        assert_eq!(0.0, yp_data[0].ch_bo_p);
+        assert_eq!(3.0, yp_data[0].total_yp);
+        assert_eq!(500.0, yp_data[0].co_hp_p);
+        assert_eq!(4.0, yp_data[0].co_eer);
+        assert_eq!(0.0, yp_data[0].ve_p);
+    }
+
+    #[test]
+    fn ventilation() {
+        let devices: emf_devices::Root = serde_json::from_str(
+            r#"{"body": [
+                {"id": "hp", "marketingName": "aroTHERM", "type": "HEAT_PUMP", "reports": [
+                    {"currentMeterReading": 10.0, "energyType": "CONSUMED_ELECTRICAL_POWER",
+                     "from": "2023-01-01T00:00:00.000Z", "to": "2023-03-01T00:00:00.000Z", "function": "CENTRAL_HEATING"}]},
+                {"id": "ve", "marketingName": "recoVAIR", "type": "VENTILATION", "reports": [
+                    {"currentMeterReading": 5.0, "energyType": "CONSUMED_ELECTRICAL_POWER",
+                     "from": "2023-01-01T00:00:00.000Z", "to": "2023-03-01T00:00:00.000Z", "function": "VENTILATION"}]}
+            ], "meta": {}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![(EmfDevice::Ventilation, "ve".to_string())],
+            ventilation_devices(&devices)
+        );
+        assert_eq!(
+            vec![(EmfDevice::HeatPump, "hp".to_string())],
+            usage_devices(&devices)
+        );
+
+        let mut balance = EnergyBalance::new(2023, 9).unwrap();
+        balance
+            .insert(
+                key(
+                    EmfFunction::Ventilation,
+                    EmfDevice::Ventilation,
+                    EnergyType::ConsumedElectricalPower,
+                ),
+                &[120.0; 7],
+            )
+            .unwrap();
+        let yp_data = YpData::from_balance(&balance);
+        assert_eq!(120.0, yp_data[0].ve_p);
+        // not part of the heating total
+        assert_eq!(0.0, yp_data[0].total_p);
+    }
+
+    #[test]
+    fn several_heat_pumps_without_boiler() {
+        let report = r#"{"currentMeterReading": 10.0, "energyType": "CONSUMED_ELECTRICAL_POWER",
+            "from": "2023-01-01T00:00:00.000Z", "to": "2023-03-01T00:00:00.000Z", "function": "DHW"}"#;
+        let devices: emf_devices::Root = serde_json::from_str(&format!(
+            r#"{{"body": [
+                {{"id": "hp1", "marketingName": "aroTHERM", "type": "HEAT_PUMP", "reports": [{report}]}},
+                {{"id": "hp2", "marketingName": "aroTHERM", "type": "HEAT_PUMP", "reports": [{report}]}},
+                {{"id": "other", "marketingName": "sensoCOMFORT", "type": "CONTROL", "reports": [{report}]}}
+            ], "meta": {{}}}}"#
+        ))
+        .unwrap();
+        assert_eq!(
+            vec![
+                (EmfDevice::HeatPump, "hp1".to_string()),
+                (EmfDevice::HeatPump, "hp2".to_string())
+            ],
+            usage_devices(&devices)
+        );
+    }
+}
@@ -3,9 +3,15 @@ use iso8601_timestamp::Timestamp;
 use mockito::{Matcher, Mock, Server, ServerGuard};
 use num_traits::cast::FromPrimitive;
 use senso::{
-    db::DB,
-    request::emf,
-    response::emf_devices::{EmfDevice, EmfFunction},
+    db::{Channel, DB},
+    request::{emf, system::VentilationMode},
+    response::{
+        emf_devices::{EmfDevice, EmfFunction, EnergyType},
+        hvac_state::ErrorType,
+        live_report::{AssociatedDeviceFunction, MeasurementCategory},
+        system::QuickMode,
+    },
+    timeprogram::{self, Schedules},
     yp::{build_yp_data_vec, UsageFunctionWeek},
 };
 use serde_json::json;
@@ -148,6 +154,369 @@ fn status_test() {
     status_mock.assert();
 }
 
+#[test]
+fn system_test() {
+    use senso::response::system::{ActiveFunction, OperatingMode, QuickMode, Setting};
+
+    init();
+    let mut server = Server::new();
+    let c = senso::connector::Connector::new(
+        senso::urls::UrlBase::Localhost(port(&server)),
+        "1".into(),
+        "".into(),
+    );
+
+    let system_mock = server
+        .mock("GET", "/facilities/1/systemcontrol/tli/v1")
+        .with_body_from_file("tests/responses/system.json")
+        .create();
+
+    let system = c.system().unwrap().body;
+
+    assert_eq!(
+        Some(QuickMode::QmHotwaterBoost),
+        system
+            .configuration
+            .quickmode
+            .as_ref()
+            .map(|q| q.quickmode.clone())
+    );
+    assert!(!system.configuration.holidaymode.as_ref().unwrap().active);
+
+    // zones
+    assert_eq!(2, system.zones.len());
+    let zone = system.find_zone("Control_ZO1").unwrap();
+    assert_eq!("Wohnen", zone.configuration.name);
+    assert_eq!(Some(21.3), zone.configuration.inside_temperature);
+    assert_eq!(
+        Some(ActiveFunction::Heating),
+        zone.configuration.active_function
+    );
+    let heating = zone.heating.as_ref().unwrap();
+    assert_eq!(OperatingMode::Auto, heating.configuration.mode);
+    assert_eq!(Some(21.5), heating.configuration.setpoint_temperature);
+    let monday = &heating.timeprogram.as_ref().unwrap().monday;
+    assert_eq!(3, monday.len());
+    assert_eq!("06:00", monday[1].start_time);
+    assert_eq!(Some(Setting::Day), monday[1].setting);
+    // disabled zone without time program and temperature
+    let zone = system.find_zone("Control_ZO2").unwrap();
+    assert_eq!(None, zone.configuration.inside_temperature);
+    assert!(zone.heating.as_ref().unwrap().timeprogram.is_none());
+
+    // heating circuits
+    assert_eq!(Some(0.6), system.heating_circuits[0].heating_curve);
+    assert_eq!(2, system.heating_circuits[0].zones.len());
+
+    // hot water and circulation
+    let dhw = &system.dhw[0];
+    assert_eq!(
+        OperatingMode::Auto,
+        dhw.hotwater.configuration.operation_mode
+    );
+    assert_eq!(Some(50.0), dhw.hotwater.configuration.temperature_setpoint);
+    assert_eq!(
+        Some("22:00".to_string()),
+        dhw.hotwater.timeprogram.as_ref().unwrap().sunday[0].end_time
+    );
+    let circulation = dhw.circulation.as_ref().unwrap();
+    assert_eq!(2, circulation.timeprogram.as_ref().unwrap().monday.len());
+    assert!(circulation.timeprogram.as_ref().unwrap().sunday.is_empty());
+
+    // ventilation
+    let fan = &system.ventilation[0].fan;
+    assert_eq!(Some(3), fan.configuration.day_level);
+
+    system_mock.assert();
+}
+
+#[test]
+fn dhw_write_test() {
+    use senso::{connector::ApiError, request::system::DhwMode};
+
+    init();
+    let mut server = Server::new();
+    let c = senso::connector::Connector::new(
+        senso::urls::UrlBase::Localhost(port(&server)),
+        "1".into(),
+        "".into(),
+    );
+
+    let setpoint_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/dhw/Control_DHW/hotwater/configuration/temperature_setpoint",
+        )
+        .match_header("content-type", "application/json; charset=UTF-8")
+        .match_body(r#"{"temperature_setpoint":45.0}"#)
+        .with_status(200)
+        .expect(1)
+        .create();
+    c.set_dhw_setpoint("Control_DHW", 45.0).unwrap();
+
+    // rejected before calling the api
+    let e = c.set_dhw_setpoint("Control_DHW", 90.0).unwrap_err();
+    assert!(matches!(
+        e.downcast_ref(),
+        Some(&ApiError::OutOfRange { value, .. }) if value == 90.0
+    ));
+    setpoint_mock.assert();
+
+    let mode_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/dhw/Control_DHW/hotwater/configuration/operation_mode",
+        )
+        .match_body(r#"{"operation_mode":"OFF"}"#)
+        .with_status(200)
+        .create();
+    c.set_dhw_operation_mode("Control_DHW", DhwMode::Off)
+        .unwrap();
+    mode_mock.assert();
+
+    // blocked by an active quick mode
+    let conflict_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/dhw/Control_DHW2/hotwater/configuration/operation_mode",
+        )
+        .with_status(409)
+        .with_body("quick mode active")
+        .create();
+    let e = c
+        .set_dhw_operation_mode("Control_DHW2", DhwMode::Auto)
+        .unwrap_err();
+    match e.downcast_ref() {
+        Some(ApiError::Conflict(body)) => assert_eq!("quick mode active", body),
+        _ => panic!("expected conflict, got {}", e),
+    }
+    conflict_mock.assert();
+}
+
+#[test]
+fn zone_write_test() {
+    init();
+    let mut server = Server::new();
+    let c = senso::connector::Connector::new(
+        senso::urls::UrlBase::Localhost(port(&server)),
+        "1".into(),
+        "".into(),
+    );
+
+    // every change returns the state read afterwards
+    let system_mock = server
+        .mock("GET", "/facilities/1/systemcontrol/tli/v1")
+        .with_body_from_file("tests/responses/system.json")
+        .expect(5)
+        .create();
+
+    let setpoint_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/zones/Control_ZO1/heating/configuration/setpoint_temperature",
+        )
+        .match_body(r#"{"setpoint_temperature":21.5}"#)
+        .create();
+    let zone = c.set_zone_setpoint("Control_ZO1", 21.5).unwrap();
+    assert_eq!(
+        Some(21.5),
+        zone.heating.unwrap().configuration.setpoint_temperature
+    );
+    setpoint_mock.assert();
+    assert!(c.set_zone_setpoint("Control_ZO1", 40.0).is_err());
+
+    let veto_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/zones/Control_ZO1/configuration/quick_veto",
+        )
+        .match_body(r#"{"setpoint_temperature":22.0,"duration":120}"#)
+        .create();
+    let cancel_mock = server
+        .mock(
+            "DELETE",
+            "/facilities/1/systemcontrol/tli/v1/zones/Control_ZO1/configuration/quick_veto",
+        )
+        .create();
+    let zone = c.start_quick_veto("Control_ZO1", 22.0, 120).unwrap();
+    assert_eq!("Control_ZO1", zone.id);
+    let zone = c.cancel_quick_veto("Control_ZO1").unwrap();
+    assert!(!zone.currently_in_quick_veto);
+    veto_mock.assert();
+    cancel_mock.assert();
+
+    let holiday_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/configuration/holidaymode",
+        )
+        .match_body(Matcher::Json(json!({
+            "active": true,
+            "start_date": "2023-08-01",
+            "end_date": "2023-08-14",
+            "temperature_setpoint": 15.0
+        })))
+        .create();
+    let clear_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/configuration/holidaymode",
+        )
+        .match_body(r#"{"active":false}"#)
+        .create();
+    let holiday = c
+        .set_holiday_mode(
+            NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2023, 8, 14).unwrap(),
+            15.0,
+        )
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(15.0), holiday.temperature_setpoint);
+    let holiday = c.clear_holiday_mode().unwrap().unwrap();
+    assert!(!holiday.active);
+    holiday_mock.assert();
+    clear_mock.assert();
+
+    system_mock.assert();
+}
+
+#[test]
+fn ventilation_write_test() {
+    init();
+    let mut server = Server::new();
+    let c = senso::connector::Connector::new(
+        senso::urls::UrlBase::Localhost(port(&server)),
+        "1".into(),
+        "".into(),
+    );
+    let system_mock = server
+        .mock("GET", "/facilities/1/systemcontrol/tli/v1")
+        .with_body_from_file("tests/responses/system.json")
+        .expect(3)
+        .create();
+    let base = "/facilities/1/systemcontrol/tli/v1/ventilation/Control_VE1/fan/configuration";
+    let mode_mock = server
+        .mock("PUT", format!("{}/operation_mode", base).as_str())
+        .match_body(r#"{"operation_mode":"DAY"}"#)
+        .create();
+    let day_mock = server
+        .mock("PUT", format!("{}/day_level", base).as_str())
+        .match_body(r#"{"level":4}"#)
+        .create();
+    let night_mock = server
+        .mock("PUT", format!("{}/night_level", base).as_str())
+        .match_body(r#"{"level":1}"#)
+        .create();
+
+    let ventilation = c
+        .set_ventilation_operation_mode("Control_VE1", VentilationMode::Day)
+        .unwrap();
+    assert_eq!(Some(3), ventilation.fan.configuration.day_level);
+    c.set_ventilation_day_level("Control_VE1", 4).unwrap();
+    c.set_ventilation_night_level("Control_VE1", 1).unwrap();
+    // out of range is not sent
+    assert!(c.set_ventilation_night_level("Control_VE1", 9).is_err());
+
+    mode_mock.assert();
+    day_mock.assert();
+    night_mock.assert();
+    system_mock.assert();
+}
+
+#[test]
+fn quick_mode_test() {
+    init();
+    let mut server = Server::new();
+    let c = senso::connector::Connector::new(
+        senso::urls::UrlBase::Localhost(port(&server)),
+        "1".into(),
+        "".into(),
+    );
+    let system_mock = server
+        .mock("GET", "/facilities/1/systemcontrol/tli/v1")
+        .with_body_from_file("tests/responses/system.json")
+        .expect(2)
+        .create();
+    let start_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/configuration/quickmode",
+        )
+        .match_body(r#"{"quickmode":"QM_HOTWATER_BOOST","duration":30}"#)
+        .create();
+    let cancel_mock = server
+        .mock(
+            "DELETE",
+            "/facilities/1/systemcontrol/tli/v1/configuration/quickmode",
+        )
+        .create();
+
+    let quick_mode = c
+        .start_quick_mode(QuickMode::QmHotwaterBoost, Some(30))
+        .unwrap()
+        .unwrap();
+    assert_eq!(QuickMode::QmHotwaterBoost, quick_mode.quickmode);
+    assert_eq!(Some(30), quick_mode.duration);
+    assert!(c.start_quick_mode(QuickMode::QmParty, Some(0)).is_err());
+    c.cancel_quick_mode().unwrap();
+
+    start_mock.assert();
+    cancel_mock.assert();
+    system_mock.assert();
+}
+
+#[test]
+fn time_program_test() {
+    init();
+    let mut server = Server::new();
+    let c = senso::connector::Connector::new(
+        senso::urls::UrlBase::Localhost(port(&server)),
+        "1".into(),
+        "".into(),
+    );
+    let system_mock = server
+        .mock("GET", "/facilities/1/systemcontrol/tli/v1")
+        .with_body_from_file("tests/responses/system.json")
+        .expect(2)
+        .create();
+
+    let live = Schedules::from_system(&c.system().unwrap().body).unwrap();
+    assert_eq!(1, live.zones.len());
+    assert_eq!(2, live.circulation["Control_DHW"].monday.len());
+
+    // only the changed hot water program is pushed
+    let mut desired = live.clone();
+    desired.dhw.get_mut("Control_DHW").unwrap().sunday[0].start = "08:00".parse().unwrap();
+    let dhw_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/dhw/Control_DHW/hotwater/timeprogram",
+        )
+        .match_body(Matcher::PartialJson(json!({
+            "monday": [{"startTime": "05:30", "endTime": "22:00"}],
+            "sunday": [{"startTime": "08:00", "endTime": "22:00"}]
+        })))
+        .create();
+    let zone_mock = server
+        .mock(
+            "PUT",
+            "/facilities/1/systemcontrol/tli/v1/zones/Control_ZO1/heating/timeprogram",
+        )
+        .expect(0)
+        .create();
+    let changes = timeprogram::apply(&c, &desired).unwrap();
+    assert_eq!(1, changes.len());
+    assert_eq!("sunday", changes[0].day);
+    dhw_mock.assert();
+
+    // invalid programs are not pushed
+    desired.zones.get_mut("Control_ZO1").unwrap().monday[0].setting = None;
+    assert!(timeprogram::apply(&c, &desired).is_err());
+    zone_mock.assert();
+    system_mock.assert();
+}
+
 #[test]
 fn live_report_test() {
     init();
@@ -174,6 +543,11 @@ fn live_report_test() {
             .value
     );
 
+    // air quality of the ventilation
+    let data = senso::db::SensorData::new(&Err(()), &Ok(live_report));
+    assert_eq!(Some(1000.0), data.value(Channel::Co2));
+    assert_eq!(Some(40.0), data.value(Channel::Humidity));
+
     live_report_mock.assert();
 }
 
@@ -225,6 +599,73 @@ fn emf_report_device_test() {
     emf_report_device_mock.assert();
 }
 
+#[test]
+fn unknown_values_test() {
+    init();
+    let mut server = Server::new();
+    let c = senso::connector::Connector::new(
+        senso::urls::UrlBase::Localhost(port(&server)),
+        "1".into(),
+        "".into(),
+    );
+    let live_report_mock = server
+        .mock("GET", "/facilities/1/livereport/v1")
+        .with_body_from_file("tests/responses/live_report_unknown.json")
+        .create();
+    let emf_devices_mock = server
+        .mock("GET", "/facilities/1/emf/v1/devices")
+        .with_body_from_file("tests/responses/emf_devices_unknown.json")
+        .create();
+
+    // known readings are kept
+    let live_report = c.live_report().unwrap();
+    let voc = live_report
+        .body
+        .find_report_for_device("Control_SYS_senso", "VocSensor1")
+        .unwrap();
+    assert_eq!(
+        MeasurementCategory::Unknown("VOLATILE_ORGANIC_COMPOUNDS".into()),
+        voc.measurement_category
+    );
+    let solar = live_report
+        .body
+        .find_report_for_device("Control_DHW", "SolarCollectorTemperature")
+        .unwrap();
+    assert_eq!(
+        Some(AssociatedDeviceFunction::Unknown("SOLAR".into())),
+        solar.associated_device_function
+    );
+    let data = senso::db::SensorData::new(&Err(()), &Ok(live_report));
+    assert_eq!(Some(47.5), data.value(Channel::Hotwatertank));
+    assert_eq!(Some(1.4), data.value(Channel::Waterpressure));
+
+    let emf_devices = c.emf_devices().unwrap();
+    assert_eq!(
+        EmfDevice::Unknown("SOLAR_STATION".into()),
+        emf_devices.body[1].type_field
+    );
+    let report = &emf_devices.body[0].reports[1];
+    assert_eq!(
+        EnergyType::Unknown("SOLAR_YIELD".into()),
+        report.energy_type
+    );
+    assert_eq!("POOL_HEATING", report.function.as_ref());
+
+    // unknown values round trip through the db and are not reconciled
+    let readings = senso::meter::MeterReading::from_emf_devices(&emf_devices).unwrap();
+    let db = DB::new(None).unwrap();
+    db.insert_meter_readings(&readings).unwrap();
+    let stored = db.get_meter_readings().unwrap();
+    assert_eq!(3, stored.len());
+    assert!(stored
+        .iter()
+        .any(|r| r.function == EmfFunction::Unknown("POOL_HEATING".into())));
+    senso::meter::reconcile(&db, 0.05).unwrap();
+
+    live_report_mock.assert();
+    emf_devices_mock.assert();
+}
+
 #[test]
 fn emf_devices() {
     init();
@@ -247,6 +688,21 @@ fn emf_devices() {
         emf_devices.body.first().unwrap().marketing_name
     );
 
+    // meter readings from all reports
+    let readings = senso::meter::MeterReading::from_emf_devices(&emf_devices).unwrap();
+    assert_eq!(8, readings.len());
+    assert_eq!(460000.0, readings[0].value);
+    assert_eq!(EmfFunction::CentralHeating, readings[0].function);
+
+    // insert and retrieve from DB
+    let db = DB::new(None).unwrap();
+    db.insert_meter_readings(&readings).unwrap();
+    assert_eq!(readings.len(), db.get_meter_readings().unwrap().len());
+
+    // same reading again replaces the old one
+    db.insert_meter_readings(&readings).unwrap();
+    assert_eq!(readings.len(), db.get_meter_readings().unwrap().len());
+
     emf_devices_mock.assert();
 }
 
@@ -298,7 +754,9 @@ fn insert_test() {
 
     // first test api to SensorData
     let data = senso::db::SensorData::new(&Err(()), &Ok(live_report));
-    let data_eq = senso::db::SensorData::new_raw(None, Some(45.5), Some(1.3), Some(38.5));
+    // humidity of the zone
+    let data_eq = senso::db::SensorData::new_raw(None, Some(45.5), Some(1.3), Some(38.5))
+        .with_air_quality(None, Some(43.0));
     assert_eq!(data_eq, data);
 
     let db = DB::new(None).unwrap();
@@ -450,3 +908,111 @@ fn yp_test() {
         x.assert();
     }
 }
+
+#[test]
+fn errors_test() {
+    init();
+    let mut server = Server::new();
+    let c = senso::connector::Connector::new(
+        senso::urls::UrlBase::Localhost(port(&server)),
+        "1".into(),
+        "".into(),
+    );
+
+    let hvac_state_mock = server
+        .mock("GET", "/facilities/1/hvacstate/v1/overview")
+        .with_body_from_file("tests/responses/hvac_state.json")
+        .expect(2)
+        .create();
+
+    let errors = c.errors().unwrap();
+    assert_eq!(2, errors.len());
+    assert_eq!(ErrorType::Error, errors[0].type_field);
+    assert_eq!("F.28", errors[0].status_code);
+    assert_eq!("VWL 101/6", errors[0].device_name);
+    assert_eq!(
+        NaiveDate::from_ymd_opt(2023, 3, 15)
+            .unwrap()
+            .and_hms_opt(21, 10, 6)
+            .unwrap(),
+        errors[0].timestamp.naive_utc()
+    );
+    assert_eq!(ErrorType::Maintenance, errors[1].type_field);
+    assert_eq!(None, errors[1].hint);
+
+    let db = DB::new(None).unwrap();
+    let now = NaiveDate::from_ymd_opt(2023, 3, 16)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let changes = senso::faults::update(&db, &c.errors().unwrap(), now).unwrap();
+    assert_eq!(2, changes.appeared.len());
+    assert_eq!(2, db.get_faults(true).unwrap().len());
+
+    hvac_state_mock.assert();
+}
+
+#[test]
+fn inventory_test() {
+    init();
+    let mut server = Server::new();
+    let c = senso::connector::Connector::new(
+        senso::urls::UrlBase::Localhost(port(&server)),
+        "1".into(),
+        "".into(),
+    );
+
+    let details_mock = server
+        .mock("GET", "/facilities/1/system/v1/details")
+        .with_body(
+            json!({
+              "body": {
+                "serialNumber": "1",
+                "firmwareVersion": "0357.40.32",
+                "ethernetMAC": "00:00:00:00:00:01"
+              },
+              "meta": {}
+            })
+            .to_string(),
+        )
+        .create();
+    let gateway_mock = server
+        .mock("GET", "/facilities/1/public/v1/gatewayType")
+        .with_body(
+            json!({
+              "body": {
+                "gatewayType": "VR920"
+              },
+              "meta": {}
+            })
+            .to_string(),
+        )
+        .create();
+    let emf_devices_mock = server
+        .mock("GET", "/facilities/1/emf/v1/devices")
+        .with_body_from_file("tests/responses/emf_devices.json")
+        .create();
+
+    let now = NaiveDate::from_ymd_opt(2023, 3, 18)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let emf_devices = c.emf_devices().unwrap();
+    let devices = senso::inventory::collect(&c, &emf_devices, now).unwrap();
+    assert_eq!(3, devices.len());
+    assert_eq!("1", devices[0].id);
+    assert_eq!(Some("0357.40.32".to_string()), devices[0].firmware);
+    assert_eq!("VWL 55/6 A 230V", devices[1].marketing_name);
+    assert_eq!("HEAT_PUMP", devices[1].device_type);
+    assert!(devices
+        .iter()
+        .all(|d| d.gateway == Some("VR920".to_string())));
+
+    let db = DB::new(None).unwrap();
+    assert!(senso::inventory::update(&db, &devices).unwrap().is_empty());
+    assert_eq!(3, db.get_devices().unwrap().len());
+
+    details_mock.assert();
+    gateway_mock.assert();
+    emf_devices_mock.assert();
+}
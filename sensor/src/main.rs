@@ -5,8 +5,10 @@ use const_format::formatcp;
 use env_logger::Env;
 use log::{debug, error, info};
 use senso::{
+    alert::{self, AlertConfig},
     connector::Connector,
     db::{SensorData, DB},
+    faults,
     urls::UrlBase,
 };
 
@@ -41,6 +43,11 @@ struct Args {
     /// Creates a new file if not found.
     #[arg(short, long, default_value = "./token")]
     token_file: String,
+
+    /// Path to a json file with alert rules and sinks.
+    /// Checks the rules after the collection and sends new faults to the sinks if set.
+    #[arg(long)]
+    alert_file: Option<String>,
 }
 
 impl fmt::Display for Args {
@@ -77,7 +84,23 @@ fn main() {
     let live_report = c.live_report().map_err(|e| error!("Failed to retrieve live report from api. Response: \"{}\". Continuing anyway, use None as result.", e.to_string()));
     debug!("{:#?}", live_report);
 
-    let data = SensorData::new(&status, &live_report);
+    // quick mode and hot water setpoint
+    let system = c.system().map_err(|e| error!("Failed to retrieve system from api. Response: \"{}\". Continuing anyway, use None as quick mode and hot water setpoint.", e.to_string()));
+    let quick_mode = system
+        .as_ref()
+        .ok()
+        .and_then(|s| s.body.configuration.quickmode.as_ref())
+        .map(|q| q.quickmode.clone());
+    debug!("{:#?}", quick_mode);
+    let dhw_setpoint = system
+        .as_ref()
+        .ok()
+        .and_then(|s| alert::dhw_setpoint(&s.body));
+
+    let errors = c.errors().map_err(|e| error!("Failed to retrieve errors from api. Response: \"{}\". Continuing anyway, faults are not updated.", e.to_string()));
+    debug!("{:#?}", errors);
+
+    let data = SensorData::new(&status, &live_report).with_quick_mode(quick_mode);
 
     info!("Got Sensor Data: {:#?}", &data);
 
@@ -90,5 +113,29 @@ fn main() {
                 e.to_string()
             )
         });
+
+        let config = args.alert_file.as_ref().and_then(|path| {
+            AlertConfig::from_file(path)
+                .map_err(|e| error!("Failed to read alert file because \"{}\".", e.to_string()))
+                .ok()
+        });
+        let sinks = config
+            .as_ref()
+            .map(|config| config.build_sinks())
+            .unwrap_or_default();
+        let now = chrono::Utc::now().naive_utc();
+
+        if let Ok(errors) = errors {
+            let _ = faults::update(&db, &errors, now)
+                .map_err(|e| error!("Failed to update faults because \"{}\".", e.to_string()));
+        }
+        // also retries faults that no sink accepted before
+        let _ = faults::notify(&db, &sinks, now)
+            .map_err(|e| error!("Failed to send faults because \"{}\".", e.to_string()));
+
+        if let Some(config) = &config {
+            let _ = alert::run(&db, config, &sinks, now, dhw_setpoint)
+                .map_err(|e| error!("Failed to check alerts because \"{}\".", e.to_string()));
+        }
     }
 }
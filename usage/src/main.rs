@@ -1,17 +1,21 @@
-use std::fmt;
+use std::{collections::BTreeMap, fmt};
 
-use chrono::{Datelike, Duration};
+use anyhow::anyhow;
+use chrono::{Datelike, Duration, NaiveDateTime};
 use clap::Parser;
 use cli_table::{print_stdout, WithTitle};
 use const_format::formatcp;
 use env_logger::Env;
-use log::{error, info};
+use log::{error, info, warn};
 use senso::{
+    alert::{self, AlertConfig},
     connector::Connector,
     db::DB,
+    inventory, meter,
     response::emf_devices::{EmfDevice, EmfFunction},
+    tariff::TariffConfig,
     urls::UrlBase,
-    yp::{self, UsageFunctionWeek},
+    yp::{self, EnergyBalance, UsageFunctionWeek, YpData},
 };
 
 // THIS PART IS THE SAME AS SENSOR
@@ -51,14 +55,38 @@ struct Args {
     /// 1 => yesterday
     #[arg(long, default_value_t = 1)]
     delta: i64,
+
+    /// Print a reconciliation of the stored daily usage against the stored meter readings.
+    /// Needs a db_file.
+    #[arg(long)]
+    reconcile: bool,
+
+    /// Accepted relative difference of the reconciliation, e.g. 0.05 for 5%.
+    #[arg(long, default_value_t = 0.05)]
+    reconcile_tolerance: f64,
+
+    /// Path to a json file with electricity tariffs.
+    /// Calculates the costs for the usage if set.
+    #[arg(long)]
+    tariff_file: Option<String>,
+
+    /// Path to a json file with alert rules and sinks.
+    /// Checks the rules after the collection if set. Needs a db_file.
+    #[arg(long)]
+    alert_file: Option<String>,
+
+    /// Retrieve the usage of all days queued for backfill by `senso quality --backfill`.
+    /// Needs a db_file.
+    #[arg(long)]
+    backfill: bool,
 }
 
 impl fmt::Display for Args {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "serial: {}\ndb_path: {:#?}\ntoken_path: {}\nuser: {}\npwd: ###",
-            self.serial, self.db_file, self.token_file, self.user
+            "serial: {}\ndb_path: {:#?}\ntoken_path: {}\ntariff_path: {:#?}\nuser: {}\npwd: ###",
+            self.serial, self.db_file, self.token_file, self.tariff_file, self.user
         )
     }
 }
@@ -80,17 +108,46 @@ fn main() {
         return;
     }
     // END SAME AS
-    // devices from emf_devices
-    let devices = vec![
-        (
-            EmfDevice::HeatPump,
-            "NoneGateway-LL_HMU03_0351_HP_Platform_Outdoor_Monobloc_PR_EBUS",
-        ),
-        (
-            EmfDevice::Boiler,
-            "NoneGateway-LL_VWZ02_0351_HP_Platform_Indoor_Monobloc_PR_EBUS",
-        ),
-    ];
+    // fetched once for the usage devices, ventilation, meter readings and inventory
+    let emf_devices = c.emf_devices().map_err(|e| {
+        error!(
+            "Failed to retrieve emf devices. Response: \"{}\". Continuing with the default devices and without ventilation, meter readings and inventory.",
+            e.to_string()
+        )
+    });
+
+    // heat pumps and boilers of the facility, the default installation if unknown
+    let devices = match &emf_devices {
+        Ok(emf_devices) => yp::usage_devices(emf_devices),
+        Err(_) => vec![
+            (
+                EmfDevice::HeatPump,
+                "NoneGateway-LL_HMU03_0351_HP_Platform_Outdoor_Monobloc_PR_EBUS".to_string(),
+            ),
+            (
+                EmfDevice::Boiler,
+                "NoneGateway-LL_VWZ02_0351_HP_Platform_Indoor_Monobloc_PR_EBUS".to_string(),
+            ),
+        ],
+    };
+    let devices: Vec<(EmfDevice, &str)> = devices
+        .iter()
+        .map(|(d, id)| (d.clone(), id.as_str()))
+        .collect();
+
+    // ventilation usage is only retrieved if the emf devices report it
+    let ventilation = emf_devices
+        .as_ref()
+        .map(yp::ventilation_devices)
+        .unwrap_or_default();
+    let ventilation: Vec<(EmfDevice, &str)> = ventilation
+        .iter()
+        .map(|(d, id)| (d.clone(), id.as_str()))
+        .collect();
+    let devices = Devices {
+        usage: &devices,
+        ventilation: &ventilation,
+    };
 
     let yesterday = chrono::offset::Utc::now() - Duration::days(args.delta);
 
@@ -98,40 +155,22 @@ fn main() {
     let year = yesterday.year();
     let day = yesterday.weekday().num_days_from_monday();
 
-    let mut usage_ch = UsageFunctionWeek::new(EmfFunction::CentralHeating, &devices, year, week_nr);
-    let mut usage_dhw =
-        UsageFunctionWeek::new(EmfFunction::DomesticHotWater, &devices, year, week_nr);
-
-    if usage_ch
-        .retrieve_data(&c)
-        .map_err(|e| {
-            error!(
-                "Failed to retrieve data for central heating. Response: \"{}\".",
-                e.to_string()
-            )
-        })
-        .is_err()
-    {
-        error!("Exiting");
-        return;
-    }
-    if usage_dhw
-        .retrieve_data(&c)
-        .map_err(|e| {
-            error!(
-                "Failed to retrieve data for domestic hot water. Response: \"{}\".",
-                e.to_string()
-            )
-        })
-        .is_err()
-    {
-        error!("Exiting");
-        return;
-    }
+    // costs are optional, continue without them
+    let tariff = args.tariff_file.as_ref().and_then(|path| {
+        TariffConfig::from_file(path)
+            .map_err(|e| {
+                error!(
+                    "Failed to read tariff file \"{}\" because \"{}\". Continuing without costs.",
+                    path,
+                    e.to_string()
+                )
+            })
+            .ok()
+    });
 
-    if let Ok(result) = yp::build_yp_data_vec(usage_dhw, usage_ch).map_err(|e| {
+    if let Ok(result) = retrieve_week(&c, devices, year, week_nr).map_err(|e| {
         error!(
-            "Failed to create yp data to insert into db. Error: \"{}\".",
+            "Failed to retrieve usage. Error: \"{}\". Exiting",
             e.to_string()
         )
     }) {
@@ -145,10 +184,221 @@ fn main() {
                         e.to_string()
                     )
                 });
+
+                if let Some(tariff) = &tariff {
+                    let _ = tariff
+                        .daily_cost(&result[day as usize])
+                        .and_then(|cost| db.insert_costs(&[cost]))
+                        .map_err(|e| {
+                            error!(
+                                "Could no insert costs in database because \"{}\".",
+                                e.to_string()
+                            )
+                        });
+                }
+
+                if let Ok(emf_devices) = &emf_devices {
+                    // lifetime meter readings
+                    match meter::MeterReading::from_emf_devices(emf_devices) {
+                        Ok(readings) => {
+                            let _ = db.insert_meter_readings(&readings).map_err(|e| {
+                                error!(
+                                    "Could no insert meter readings in database because \"{}\".",
+                                    e.to_string()
+                                )
+                            });
+                        }
+                        Err(e) => error!(
+                            "Failed to read meter readings. Error: \"{}\".",
+                            e.to_string()
+                        ),
+                    }
+
+                    // device inventory, firmware changes are logged
+                    match inventory::collect(&c, emf_devices, chrono::Utc::now().naive_utc()) {
+                        Ok(devices) => {
+                            let _ = inventory::update(&db, &devices).map_err(|e| {
+                                error!(
+                                    "Could no update device inventory in database because \"{}\".",
+                                    e.to_string()
+                                )
+                            });
+                        }
+                        Err(e) => error!(
+                            "Failed to retrieve device inventory. Response: \"{}\".",
+                            e.to_string()
+                        ),
+                    }
+                }
+
+                if args.reconcile {
+                    match meter::reconcile(&db, args.reconcile_tolerance) {
+                        Ok(r) => {
+                            let _ = print_stdout(r.with_title());
+                        }
+                        Err(e) => error!("Failed to reconcile usage. Error: \"{}\".", e),
+                    }
+                }
+
+                if args.backfill {
+                    backfill(&c, devices, &db, tariff.as_ref());
+                }
+
+                if let Some(path) = &args.alert_file {
+                    let _ = AlertConfig::from_file(path)
+                        .and_then(|config| {
+                            // only hot water rules need the system
+                            let dhw_setpoint = if config.needs_dhw_setpoint() {
+                                c.system()
+                                    .map_err(|e| {
+                                        error!(
+                                            "Failed to retrieve system from api. Response: \"{}\". Hot water rules are skipped.",
+                                            e.to_string()
+                                        )
+                                    })
+                                    .ok()
+                                    .and_then(|s| alert::dhw_setpoint(&s.body))
+                            } else {
+                                None
+                            };
+                            alert::check(&db, &config, dhw_setpoint)
+                        })
+                        .map_err(|e| {
+                            error!("Failed to check alerts because \"{}\".", e.to_string())
+                        });
+                }
             }
         } else {
             // no db file was given, print to stdout
             let _ = print_stdout(result.with_title());
+
+            if let Some(tariff) = &tariff {
+                match result
+                    .iter()
+                    .map(|yp_data| tariff.daily_cost(yp_data))
+                    .collect::<anyhow::Result<Vec<_>>>()
+                {
+                    Ok(costs) => {
+                        let _ = print_stdout(costs.with_title());
+                    }
+                    Err(e) => error!("Failed to calculate costs. Error: \"{}\".", e),
+                }
+            }
+        }
+    }
+}
+
+// devices to retrieve the usage for
+#[derive(Clone, Copy)]
+struct Devices<'a> {
+    // heating, hot water and cooling
+    usage: &'a Vec<(EmfDevice, &'a str)>,
+    // empty if the facility has no ventilation
+    ventilation: &'a Vec<(EmfDevice, &'a str)>,
+}
+
+// retrieves the usage of central heating, hot water, cooling and ventilation for every day of a week
+fn retrieve_week(
+    c: &Connector,
+    devices: Devices,
+    year: i32,
+    week_nr: u32,
+) -> anyhow::Result<Vec<YpData>> {
+    let mut usage_ch =
+        UsageFunctionWeek::new(EmfFunction::CentralHeating, devices.usage, year, week_nr);
+    let mut usage_dhw =
+        UsageFunctionWeek::new(EmfFunction::DomesticHotWater, devices.usage, year, week_nr);
+    let mut usage_co = UsageFunctionWeek::new(EmfFunction::Cooling, devices.usage, year, week_nr);
+    let mut usage_ve =
+        UsageFunctionWeek::new(EmfFunction::Ventilation, devices.ventilation, year, week_nr);
+
+    usage_ch.retrieve_data(c).map_err(|e| {
+        anyhow!(
+            "Failed to retrieve data for central heating. Response: \"{}\".",
+            e.to_string()
+        )
+    })?;
+    usage_dhw.retrieve_data(c).map_err(|e| {
+        anyhow!(
+            "Failed to retrieve data for domestic hot water. Response: \"{}\".",
+            e.to_string()
+        )
+    })?;
+
+    // not every facility can cool, continue without cooling
+    let cooling = usage_co
+        .retrieve_data(c)
+        .map_err(|e| {
+            warn!(
+                "Failed to retrieve data for cooling. Response: \"{}\". Continuing without cooling.",
+                e.to_string()
+            )
+        })
+        .is_ok();
+
+    let ventilation = !devices.ventilation.is_empty()
+        && usage_ve
+            .retrieve_data(c)
+            .map_err(|e| {
+                error!(
+                    "Failed to retrieve data for ventilation. Response: \"{}\". Continuing without ventilation.",
+                    e.to_string()
+                )
+            })
+            .is_ok();
+
+    let mut usage = vec![&usage_ch, &usage_dhw];
+    if cooling {
+        usage.push(&usage_co);
+    }
+    if ventilation {
+        usage.push(&usage_ve);
+    }
+
+    Ok(YpData::from_balance(&EnergyBalance::from_usage(&usage)?))
+}
+
+// retrieves and inserts the usage of all days queued for backfill, every week is retrieved once
+fn backfill(c: &Connector, devices: Devices, db: &DB, tariff: Option<&TariffConfig>) {
+    let days = match db.pending_backfills() {
+        Ok(days) => days,
+        Err(e) => {
+            error!("Failed to read backfill queue. Error: \"{}\".", e);
+            return;
+        }
+    };
+
+    let mut weeks: BTreeMap<(i32, u32), Vec<NaiveDateTime>> = BTreeMap::new();
+    for day in days {
+        let week = day.iso_week();
+        weeks
+            .entry((week.year(), week.week()))
+            .or_default()
+            .push(day);
+    }
+
+    for ((year, week_nr), days) in weeks {
+        let result = match retrieve_week(c, devices, year, week_nr) {
+            Ok(result) => result,
+            Err(e) => {
+                error!(
+                    "Failed to backfill week {} of {}. Error: \"{}\".",
+                    week_nr, year, e
+                );
+                continue;
+            }
+        };
+
+        for day in days {
+            let yp_data = &result[day.weekday().num_days_from_monday() as usize];
+            let inserted = db.insert_yp_data(yp_data).and_then(|_| match tariff {
+                Some(tariff) => db.insert_costs(&[tariff.daily_cost(yp_data)?]),
+                None => Ok(()),
+            });
+            match inserted.and_then(|_| db.finish_backfill(day)) {
+                Ok(_) => {}
+                Err(e) => error!("Failed to backfill day {}. Error: \"{}\".", day, e),
+            }
         }
     }
 }